@@ -90,7 +90,7 @@ function build_bh() {
 	if [ ! -e /tmp/rust_code.tar.bz2 ]; then
 		tar cf /tmp/rust_code.tar.bz2 -j /opt/rust-alpha
 	fi
-	tar cf /tmp/build_host_tools.tar.bz2 -j -C $THIS_DIR/.. bin/build_all_ir.sh bin/Preprocess bin/crust.native stdlib_tests/bin/driver.sh bin/filter_errors.py bin/bh_bootstrap.sh bin/crust_macros.py src/crust_macros.rs src/crust_intrinsics.rs src/main.rs src/trans.rs stdlib_tests/x86_64-custom-linux-gnu.json
+	tar cf /tmp/build_host_tools.tar.bz2 -j -C $THIS_DIR/.. bin/build_all_ir.sh bin/Preprocess bin/crust.native stdlib_tests/bin/driver.sh bin/filter_errors.py bin/bh_bootstrap.sh bin/crust_macros.py src/crust_macros.rs src/crust_intrinsics.rs src/main.rs src/trans.rs src/contracts.rs src/borrows.rs src/check.rs src/ir.rs src/json.rs src/text.rs src/filter.rs src/stubs.rs stdlib_tests/x86_64-custom-linux-gnu.json
 	scp -i $THIS_DIR/crust_test.pem /tmp/build_host_tools.tar.bz2 ubuntu@$BUILDHOST:~/
 	if ! ssh -i $THIS_DIR/crust_test.pem ubuntu@$BUILDHOST "/opt/rust-alpha/bin/rustc --version > /dev/null"; then
 		scp -i $THIS_DIR/crust_test.pem /tmp/rust_code.tar.bz2 ubuntu@$BUILDHOST:~/
@@ -33,30 +33,69 @@ TIMEOUT = 2
 BAD_TRANS = 3
 
 loop_re = re.compile(r'^Loop (.+):$')
+loop_location_re = re.compile(r'file (\S+) line (\d+)')
 failed_re = re.compile(r'VERIFICATION FAILED\n$')
 
 builtin_loops = re.compile(r'^(core\$ptr\$|core\$intrinsics\$|memmove|memcpy)')
+# Loops with a `__crust::unwind` bound check it themselves (see
+# compilation.ml), right after the loop head, so CBMC just needs to go one
+# iteration past it.
+declared_bound_re = re.compile(r'crust_unwind_iter <= (\d+)')
 
+def declared_unwind_bound(lines, loop_line):
+    for l in lines[loop_line - 1 : loop_line + 2]:
+        m = declared_bound_re.search(l)
+        if m is not None:
+            return int(m.group(1)) + 1
+    return None
+
+# With `crust -crust-spans`, `#line` directives make CBMC report most loop
+# heads in the Rust source.  The loop query runs on a copy with them blanked
+# out instead, so every loop head is a line of the C file; blanking keeps the
+# line numbers and CBMC's loop numbering the same.
 def find_loops(test_file, test_case_name):
-    command = [ cbmc_binary, "-I", include_dir, "--show-loops", "--function", test_case_name, test_file ]
-    child_proc = subprocess.Popen(command, stdout = subprocess.PIPE)
-    child_proc.wait()
+    with open(test_file) as f:
+        lines = f.readlines()
+    query_file = test_file + ".loops.c"
+    with open(query_file, "w") as f:
+        for l in lines:
+            f.write("\n" if l.startswith("#line") else l)
+    command = [ cbmc_binary, "-I", include_dir, "--show-loops", "--function", test_case_name, query_file ]
+    try:
+        child_proc = subprocess.Popen(command, stdout = subprocess.PIPE)
+        output = child_proc.stdout.readlines()
+        child_proc.wait()
+    finally:
+        os.remove(query_file)
     if child_proc.returncode != 6:
         raise Exception("bad exit from loop query " + str(child_proc.returncode))
+    # each loop with the line of its head in the C file, if it is there
     loops = []
-    for l in child_proc.stdout:
+    for l in output:
         l = l.strip()
         m = loop_re.match(l)
-        if m is None:
+        if m is not None:
+            loop_name = m.group(1)
+            #print "found loop: " + loop_name
+            if not builtin_loops.match(loop_name):
+                loops.append([loop_name, None])
             continue
-        loop_name = m.group(1)
-        #print "found loop: " + loop_name
-        if builtin_loops.match(loop_name):
+        m = loop_location_re.search(l)
+        if m is None or len(loops) == 0 or loops[-1][1] is not None:
             continue
-        loops.append(loop_name)
+        if os.path.basename(m.group(1)) == os.path.basename(query_file):
+            loops[-1][1] = int(m.group(2))
     if len(loops) == 0:
         return []
-    return ["--unwindset", ",".join([ l + ":" + str(unwind_bound) for l in loops ]) ]
+    unwindset = []
+    for (loop_name, line) in loops:
+        bound = None
+        if line is not None:
+            bound = declared_unwind_bound(lines, line)
+        if bound is None:
+            bound = int(unwind_bound)
+        unwindset.append(loop_name + ":" + str(bound))
+    return ["--unwindset", ",".join(unwindset) ]
 
 dev_null = open("/dev/null", "w")
 
@@ -4,9 +4,9 @@ set -o pipefail
 BIN_DIR=$(dirname $0);
 PROJECT_DIR=$BIN_DIR/../
 
-make -C $PROJECT_DIR all > /dev/null
+make -C $PROJECT_DIR all crust_lib/lib__crust.rlib > /dev/null
 
-RBMC_FLAGS="-A warnings -L $PROJECT_DIR/lib"
+RBMC_FLAGS="-A warnings -L $PROJECT_DIR/lib -L $PROJECT_DIR/crust_lib"
 
 FAILED=$'\x1b[20G\x1b[31mFAILED\x1b[0m'
 RBMC_PANIC=$'\x1b[20G\x1b[33mRBMC PANIC\x1b[0m'
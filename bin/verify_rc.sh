@@ -28,7 +28,7 @@ if [ $? -ne 0 ]; then
 	exit -1
 fi
 
-for patch in $(find $ROOT_DIR/tests -name '*.patch'); do
+for patch in $(find $ROOT_DIR/tests -name 'refcell_error*.patch'); do
 	run_toolchain $patch
 	if [ $? -eq 0 ]; then
 		echo "Verification succeeded, when failure was expected with patch $patch"
@@ -0,0 +1,72 @@
+#!/bin/bash
+
+# Runs CBMC on the tests containing a `// crust-verify` line.  Each
+# `crust_test_N` function in such a test must verify, and applying any
+# `tests/NAME_error*.patch` must make one of them fail.  Extra flags for a
+# stage can be given on `// crust-verify-rbmc:`, `// crust-verify-preprocess:`
+# and `// crust-verify-crust:` lines.
+set -o pipefail
+
+TEMP_DIR=$(mktemp -d);
+
+function cleanup {
+	rm -rf $TEMP_DIR
+}
+
+trap cleanup EXIT
+
+BIN_DIR=$(cd $(dirname $0) && pwd);
+ROOT_DIR=$BIN_DIR/../
+UNWIND=${UNWIND:-5}
+
+make -C $ROOT_DIR all crust_lib/lib__crust.rlib > /dev/null
+
+function stage_flags {
+	sed -n -e "s/^\/\/ crust-verify-$1: *//p" $2
+}
+
+# Returns 0 if every test function verifies, 1 if one fails, and 2 if the
+# toolchain itself fails.
+function run_toolchain {
+	local test=$1
+	local crate=$(basename $test .rs)
+	$BIN_DIR/rbmc -A warnings -L $ROOT_DIR/lib -L $ROOT_DIR/crust_lib $(stage_flags rbmc $test) $test | \
+		$BIN_DIR/Preprocess $(stage_flags preprocess $test) | \
+		$BIN_DIR/crust.native -test-compile $(stage_flags crust $test) > $TEMP_DIR/$crate.c || return 2
+	local ret=0
+	for fn in $(grep -o 'fn crust_test_[0-9]\+' $test | cut -c4-); do
+		cbmc --pointer-check --bounds-check --unwind $UNWIND -I $ROOT_DIR/src \
+			--function "$crate\$$fn" $TEMP_DIR/$crate.c > /dev/null 2> /dev/null
+		case $? in
+			0) ;;
+			10) ret=1 ;;
+			*) return 2 ;;
+		esac
+	done
+	return $ret
+}
+
+STATUS=0
+
+for test in $(grep -l -x '// crust-verify' $ROOT_DIR/tests/*.rs); do
+	name=$(basename $test .rs)
+	echo "Verifying $name"
+	cp $test $TEMP_DIR/$name.rs
+	run_toolchain $TEMP_DIR/$name.rs
+	if [ $? -ne 0 ]; then
+		echo "Base version of $name failed to verify"
+		STATUS=1
+		continue
+	fi
+	for patch in $(find $ROOT_DIR/tests -name "${name}_error*.patch" | sort); do
+		cp $test $TEMP_DIR/$name.rs
+		patch -s -d $TEMP_DIR -p2 < $patch
+		run_toolchain $TEMP_DIR/$name.rs
+		if [ $? -ne 1 ]; then
+			echo "Verification did not fail, when failure was expected with patch $patch"
+			STATUS=1
+		fi
+	done
+done
+
+exit $STATUS
@@ -46,6 +46,7 @@ data DriverTree =
     | DENondet Ty
     | DETupleIntro [DriverTree]
     | DETupleElim Int DriverTree
+    | DEStructIntro Ty [(Name, DriverTree)]
     | DERefIntro Mutbl DriverTree
     | DERefElim DriverTree
     | DECopy Int
@@ -149,7 +150,9 @@ genTree ix limit constr ty = go limit ty
     go limit (TTuple tys) = DETupleIntro <$> mapM (go limit) tys
     -- Remaining cases require fuel to operate.
     go 0 ty = []
-    go limit ty = do
+    go limit ty = viaCall limit ty ++ viaLiteral limit ty
+
+    viaCall limit ty = do
         (name, tyParams, argTys, retTy, preds) <- constr
         (retPart, destructOp) <- destructure retTy
         tyArgs <- filter (checkPreds ix tyParams preds) $
@@ -157,6 +160,17 @@ genTree ix limit constr ty = go limit ty
         genCall ix limit constr name argTys tyArgs
             (\n ts as -> destructOp $ DECall n ts as)
 
+    -- Client code can build a struct directly only if it can see every field.
+    viaLiteral limit ty@(TAdt name _ tas)
+      | Just (TStruct sd@(StructDef _ _ tps fields _)) <- M.lookup name $ i_types ix
+      , hasPublicLiteral sd = do
+        let fieldTys = map (\(FieldDef _ _ fty) -> subst ([], tps) ([], tas) fty) fields
+        fieldDts <- mapM (go (limit - 1)) fieldTys
+        return $ DEStructIntro ty $ zip (map (\(FieldDef _ n _) -> n) fields) fieldDts
+    viaLiteral _ _ = []
+
+hasPublicLiteral (StructDef _ _ _ fields _) = all (\(FieldDef vis _ _) -> vis == Public) fields
+
 getFn ix name = runCtxM ix $ Index.getFn name
 getTyParams ix = fn_tyParams . getFn ix
 
@@ -169,6 +183,7 @@ countCalls dt = go dt
     go (DENondet _) = 0
     go (DETupleIntro dts) = sum $ map go dts
     go (DETupleElim _ dt) = go dt
+    go (DEStructIntro _ fdts) = sum $ map (go . snd) fdts
     go (DERefIntro _ dt) = go dt
     go (DERefElim dt) = go dt
     go (DECopy _) = 0
@@ -180,6 +195,7 @@ countDistinctCalls dt = S.size $ go dt
     go (DENondet _) = S.empty
     go (DETupleIntro dts) = S.unions $ map go dts
     go (DETupleElim _ dt) = go dt
+    go (DEStructIntro _ fdts) = S.unions $ map (go . snd) fdts
     go (DERefIntro _ dt) = go dt
     go (DERefElim dt) = go dt
     go (DECopy _) = S.empty
@@ -290,6 +306,8 @@ walk a dt = do
         DENondet x -> return $ DENondet x
         DETupleIntro dts' -> DETupleIntro <$> mapM (walk a) dts'
         DETupleElim x dt' -> DETupleElim x <$> walk a dt'
+        DEStructIntro x fdts' -> DEStructIntro x <$>
+            mapM (\(n, dt'') -> (,) n <$> walk a dt'') fdts'
         DERefIntro x dt' -> DERefIntro x <$> walk a dt'
         DERefElim dt' -> DERefElim <$> walk a dt'
         DECopy x -> return $ DECopy x
@@ -302,6 +320,7 @@ walk' a dt = do
         DENondet x -> return ()
         DETupleIntro dts' -> mapM_ (walk' a) dts'
         DETupleElim x dt' -> walk' a dt'
+        DEStructIntro x fdts' -> mapM_ (walk' a . snd) fdts'
         DERefIntro x dt' -> walk' a dt'
         DERefElim dt' -> walk' a dt'
         DECopy x -> return ()
@@ -320,6 +339,7 @@ addMutCalls ix limit constr origDt = go (-1) origDt
         DENondet _ -> 0
         DETupleIntro dts' -> maximum $ 0 : map callsBelow dts'
         DETupleElim _ dt' -> callsBelow dt'
+        DEStructIntro _ fdts' -> maximum $ 0 : map (callsBelow . snd) fdts'
         DERefIntro _ dt' -> callsBelow dt'
         DERefElim dt' -> callsBelow dt'
         DECopy idx -> error $ "unexpected DECopy"
@@ -340,6 +360,8 @@ addMutCalls ix limit constr origDt = go (-1) origDt
         DENondet ty -> return $ DENondet ty
         DETupleIntro dts' -> DETupleIntro <$> mapM (go callsAbove) dts'
         DETupleElim idx dt' -> DETupleElim idx <$> go callsAbove dt'
+        DEStructIntro ty fdts' -> DEStructIntro ty <$>
+            mapM (\(n, dt'') -> (,) n <$> go callsAbove dt'') fdts'
         DERefIntro mutbl dt' -> DERefIntro mutbl <$> go callsAbove dt'
         DERefElim dt' -> DERefElim <$> go callsAbove dt'
         DECopy _ -> error "unexpected DECopy"
@@ -444,6 +466,9 @@ expandDriver' ix de = traceShow de $ Expr (typeOf expr) $ EBlock stmts expr
     go (DETupleElim idx dt) = do
         expr <- go dt
         return $ Expr (tupleFieldTy idx $ typeOf expr) $ EField expr $ "field" ++ show idx
+    go (DEStructIntro ty fdts) = do
+        exprs <- mapM (go . snd) fdts
+        return $ Expr ty $ EStructLiteral $ zipWith Field (map fst fdts) exprs
     go (DERefIntro mutbl dt) = do
         expr <- go dt
         expr' <- if hasStableLocation expr then return expr else do
@@ -542,13 +567,19 @@ expandDriver ix de = trace "expandDriver'" block
 addDrivers :: Index -> Int -> ([String], [String]) -> [Item] -> [Item]
 addDrivers ix depth (libLines, constrLines) items = items ++ drivers
   where
-    libFuncs = mapMaybe getFnDesc $ filterFnsByName libLines items
-    constrFuncs = mapMaybe getFnDesc $ filterFnsByName constrLines items
+    libFuncs = mapMaybe getFnDesc $
+        traceFilter "unexported (lib)" isExported $ filterFnsByName libLines items
+    constrFuncs = mapMaybe getFnDesc $
+        traceFilter "unexported (constr)" isExported $ filterFnsByName constrLines items
     driverExprs = genDrivers ix depth libFuncs constrFuncs
     drivers = map (IDriver . Driver . expandDriver ix) driverExprs
 
 
 
+-- Only functions that can be named from outside the crate are part of its API.
+isExported (IFn (FnDef vis _ _ _ _ _ _ _ _)) = vis == Public
+isExported _ = True
+
 filterFnsByName filterLines items = traceShow regexStr $ filter check items
   where
     globCharToRegex c = case c of
@@ -7,6 +7,7 @@ import Control.Monad
 import Control.Monad.Reader
 import Control.Monad.State
 import Data.Generics hiding (typeOf)
+import Data.List (isPrefixOf)
 import qualified Data.Map as M
 import qualified Data.Set as S
 
@@ -59,7 +60,7 @@ structGlueCalls selfTy = (:[]) $ do
 
     StructDef _ _ _ fs _ <- getStruct structName
 
-    calls <- forM fs $ \(FieldDef fieldName ty) -> do
+    calls <- forM fs $ \(FieldDef _ fieldName ty) -> do
         fld <- field self fieldName
         sexpr $ call "drop_glue" [] [typeOf fld] [addrOf MMut (return fld)]
     sexpr $ block (map return calls) unit
@@ -89,11 +90,13 @@ data CleanupState = CleanupState
     { cs_pending :: S.Set Name
     , cs_flagged :: S.Set Name
     , cs_scopes :: [[(Name, Ty)]]
+    , cs_retTy :: Ty
     }
 
 set_cs_pending x r = r { cs_pending = x }
 set_cs_flagged x r = r { cs_flagged = x }
 set_cs_scopes x r = r { cs_scopes = x }
+set_cs_retTy x r = r { cs_retTy = x }
 
 update_cs_pending f r = r { cs_pending = f $ cs_pending r }
 update_cs_flagged f r = r { cs_flagged = f $ cs_flagged r }
@@ -131,11 +134,28 @@ forkState f xs = do
     put s
     return $ unzip parts
 
-addCleanup :: Index -> [Item] -> [Item]
-addCleanup ix xs = evalState (runReaderT (go xs) ix) (CleanupState S.empty S.empty [[]])
+-- With `unwind`, every call to a function is followed by a check for a panic
+-- unwinding out of it, before anything else in the expression runs:
+--
+--      {
+--          let __unwind_ret = f(args);
+--          if __crust$unwinding() {
+--              __crust$begin_cleanup();
+--              drop_glue(&mut local); ...     -- every live local, innermost first
+--              __crust$resume_unwind();
+--              return __crust$nondet();
+--          }
+--          __unwind_ret
+--      }
+--
+-- The caller then sees the panic after its own call returns.  A loop condition
+-- is evaluated on each iteration, and so is its check.
+addCleanup :: Bool -> Index -> [Item] -> [Item]
+addCleanup unwind ix xs =
+    evalState (runReaderT (go xs) ix) (CleanupState S.empty S.empty [[]] TUnit)
   where
     go :: (MonadReader Index m, MonadState CleanupState m, Data d) => d -> m d
-    go = gmapM go `extM` goStmt `extM` goExpr
+    go = gmapM go `extM` goStmt `extM` goExpr `extM` goFn
 
     walkPat = everywhereM (mkM go)
       where go p@(Pattern ty (PVar name)) = do
@@ -148,15 +168,47 @@ addCleanup ix xs = evalState (runReaderT (go xs) ix) (CleanupState S.empty S.emp
                 return p
             go p = return p
 
+    goFn (FnDef vis name lps tps args retTy impl preds body) = do
+        modify $ set_cs_retTy retTy
+        body' <- go body
+        modify $ set_cs_retTy TUnit
+        return $ FnDef vis name lps tps args retTy impl preds body'
+
     goStmt (SLet pat expr) = do
     -- TODO: collect names and tys from pat
         expr' <- go expr
+        -- The new binding isn't live if the initializer panics.
         walkPat pat
         return $ SLet pat expr'
     goStmt (SExpr expr) = do
         expr' <- go expr
         return $ SExpr expr'
 
+    mkUnwindCheck = do
+        pending <- gets cs_pending
+        scopes <- gets cs_scopes
+        retTy <- gets cs_retTy
+        let live = filter (flip S.member pending . fst) $ concat scopes
+            ret = case retTy of
+                TUnit -> unit
+                TBottom -> unit
+                _ -> return $ Expr retTy $ ECall "__crust$nondet" [] [retTy] []
+        drops <- forM live $ \(name, ty) ->
+            sexpr $ call "drop_glue" [] [ty] [addrOf MMut $ var ty name]
+        sexpr $ if_ (call "__crust$unwinding" [] [] []) $ block
+            ([sexpr $ call "__crust$begin_cleanup" [] [] []] ++
+             map return drops ++
+             [ sexpr $ call "__crust$resume_unwind" [] [] []
+             , sexpr $ Expr TBottom . EReturn <$> ret
+             ])
+            unit
+
+    -- crust's own intrinsics don't panic.
+    goExpr e@(Expr ty (ECall name _ _ _))
+      | unwind && not ("__crust$" `isPrefixOf` name) = do
+        e' <- gmapM go e
+        check <- mkUnwindCheck
+        block [let_ "__unwind_ret" (return e'), return check] (var ty "__unwind_ret")
     goExpr (Expr ty (EBlock ss e)) = do   
         (ss', e') <- goBlock ss e
         block ss' e'
@@ -201,7 +201,7 @@ getKind (TAdt name las tas) = do
   where
     collectElementTypes (TStruct (StructDef _ lps tps fields _)) las tas =
         map (subst (lps, tps) (las, tas)) $
-        map (\(FieldDef _ ty) -> ty) fields
+        map (\(FieldDef _ _ ty) -> ty) fields
     collectElementTypes (TEnum (EnumDef _ lps tps variants _)) las tas =
         map (subst (lps, tps) (las, tas)) $
         concatMap (\(VariantDef _ tys) -> tys) variants
@@ -215,11 +215,11 @@ getKind _ = return Copy
 
 
 getFieldTy (StructDef structName _ _ fs _) n = case candidates of
-    [FieldDef _ ty] -> ty
+    [FieldDef _ _ ty] -> ty
     [] -> error $ "struct " ++ structName ++ " has no field called " ++ n
     _ -> error $ "struct " ++ structName ++ " has multiple fields called " ++ n
   where
-    candidates = filter (\(FieldDef n' _) -> n' == n) fs
+    candidates = filter (\(FieldDef _ n' _) -> n' == n) fs
 
 adtName ty = case ty of
     TAdt name _ _ -> name
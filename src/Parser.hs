@@ -92,7 +92,7 @@ data AssociatedTypeDef = AssociatedTypeDef [LifetimeParam] [TyParam] ImplClause
 data StructDef = StructDef Name [LifetimeParam] [TyParam] [FieldDef] (Maybe Name)
   deriving (Eq, Show, Data, Typeable, Generic)
 
-data FieldDef = FieldDef Name Ty
+data FieldDef = FieldDef Visibility Name Ty
   deriving (Eq, Show, Data, Typeable, Generic)
 
 data EnumDef = EnumDef Name [LifetimeParam] [TyParam] [VariantDef] (Maybe Name)
@@ -260,7 +260,7 @@ associatedTypeDef = exactWord "associated_type" >>
 
 structDef = exactWord "struct" >>
     StructDef <$> name <*> counted lifetimeParam <*> counted tyParam <*> counted fieldDef <*> optional name
-fieldDef = FieldDef <$> name <*> ty
+fieldDef = FieldDef <$> visibility <*> name <*> ty
 
 enumDef = exactWord "enum" >>
     EnumDef <$> name <*> counted lifetimeParam <*> counted tyParam <*> counted variantDef <*> optional name
@@ -271,6 +271,16 @@ visibility = tagged
     , ("pub", return Public)
     ]
 
+-- A `pub` fn that can't be named from outside the crate is not part of its
+-- API, so it is treated as private.
+fnVisibility = do
+    vis <- visibility
+    exported <- tagged
+        [ ("exported", return True)
+        , ("internal", return False)
+        ]
+    return $ if exported then vis else Private
+
 predicate = tagged
     [ ("ty_impl", PImpl <$> name <*> (counted lifetime >> counted ty))
     , ("ty_eq", PEq <$> ty <*> ty)
@@ -279,7 +289,7 @@ predicate = tagged
 
 fnDef = do
     exactWord "fn"
-    f <- FnDef <$> visibility <*> name <*> counted lifetimeParam <*> counted tyParam
+    f <- FnDef <$> fnVisibility <*> name <*> counted lifetimeParam <*> counted tyParam
     exactWord "args"
     f <- f <$> counted argDecl
     exactWord "return"
@@ -446,6 +456,9 @@ instance Pp Visibility where
   pp' Private = ["priv"]
   pp' Public = ["pub"]
 
+ppFnVis Public = "pub exported"
+ppFnVis Private = "priv internal"
+
 instance Pp AbstractTypeDef where
     pp' (AbstractTypeDef a b c) = ppGo "abstract_type" [pp a, pp b, pp c]
 
@@ -456,7 +469,7 @@ instance Pp StructDef where
     pp' (StructDef a b c d e) = ppGo "struct" [pp a, pp b, pp c, pp d, pp e]
 
 instance Pp FieldDef where
-    pp' (FieldDef a b) = map pp [pp a, pp b]
+    pp' (FieldDef a b c) = map pp [pp a, pp b, pp c]
 
 instance Pp EnumDef where
     pp' (EnumDef a b c d e) = ppGo "enum" [pp a, pp b, pp c, pp d, pp e]
@@ -470,7 +483,7 @@ instance Pp Predicate where
 
 instance Pp FnDef where
     pp' (FnDef vis name lifetimeParams tyParams args retTy implClause preds body) =
-        ["fn", pp vis, pp name, pp lifetimeParams, pp tyParams,
+        ["fn", ppFnVis vis, pp name, pp lifetimeParams, pp tyParams,
          "args", pp args,
          "return", pp retTy,
          pp implClause,
@@ -51,6 +51,9 @@ ppMutbl MImm = return ()
 ppVis Private = tell "priv"
 ppVis Public = tell "pub"
 
+ppFieldVis Private = return ()
+ppFieldVis Public = tell "pub "
+
 ppTy ty = case ty of
     TVar name -> tell name
     TAdt name las tas -> tell name >> listNe angles (map ppLifetime las ++ map ppTy tas)
@@ -87,8 +90,8 @@ ppStructDef :: (MonadReader Int m, MonadWriter String m) => StructDef -> m ()
 ppStructDef (StructDef name lps tps fields mDtor) = do
     line $ tell "struct " >> tell name >> listNe angles (map ppLifetime lps ++ map tell tps) >> tell " {"
     indent $ do
-        forM fields $ \(FieldDef name ty) ->
-            line $ tell name >> tell ": " >> ppTy ty >> tell ","
+        forM fields $ \(FieldDef vis name ty) ->
+            line $ ppFieldVis vis >> tell name >> tell ": " >> ppTy ty >> tell ","
         case mDtor of 
             Just dtor -> line $ tell "// destructor: " >> tell dtor
             Nothing -> return ()
@@ -6,7 +6,7 @@ import Control.Monad
 import Control.Monad.Identity
 import Control.Monad.State
 import Control.Monad.Writer
-import Data.Char (toLower)
+import Data.Char (ord, toLower)
 import Data.Functor
 import Data.Generics hiding (typeOf)
 import Data.List (intercalate, isPrefixOf, isSuffixOf, isInfixOf)
@@ -59,6 +59,9 @@ data Config = Config
     , c_passes :: [String]
     , c_trace_passes :: Bool
     , c_driver_bound :: Int
+    , c_stub_models_file :: Maybe String
+    -- Let panics unwind, running cleanup on the way (see `addCleanup`).
+    , c_unwind :: Bool
     }
 
 defaultConfig = Config
@@ -71,6 +74,8 @@ defaultConfig = Config
     , c_passes = []
     , c_trace_passes = False
     , c_driver_bound = 3
+    , c_stub_models_file = Nothing
+    , c_unwind = False
     }
 
 readArgs config args = go args config
@@ -89,7 +94,9 @@ readArgs config args = go args config
         config { c_passes = words $ map (\c -> case c of ',' -> ' '; c -> c) passes
                , c_mode = MRunPasses }
     go ("--driver-bound" : boundStr : args) config = go args $ config { c_driver_bound = read boundStr }
+    go ("--stub-models" : path : args) config = go args $ config { c_stub_models_file = Just path }
     go ("--trace-passes" : args) config = go args $ config { c_trace_passes = True }
+    go ("--unwind" : args) config = go args $ config { c_unwind = True }
     go [] config = config
 
 main = do
@@ -140,7 +147,9 @@ main = do
                     -- first, since `scrub` will kill functions for having them
                     -- in non-Stmt positions.
                     "move-break",
+                    "mark-panics",
                     "reindex",
+                    "check-stub-models",
                     if c_scrub config then "scrub" else "id",
                     "generate-default-methods",
                     "lift-strings",
@@ -238,8 +247,10 @@ runPass config "hl-compile-drivers" (items, ix) = runPasses' config passes (item
             -- first, since `scrub` will kill functions for having them
             -- in non-Stmt positions.
             , "move-break"
+            , "mark-panics"
             , "lift-strings"
             , "reindex"
+            , "check-stub-models"
             , "scrub"
             , "generate-default-methods"
             , "fix-block-ret"
@@ -278,8 +289,10 @@ runPass config "hl-prepare-libs" (items, ix) = runPasses' config passes (items,
             -- first, since `scrub` will kill functions for having them
             -- in non-Stmt positions.
             , "move-break"
+            , "mark-panics"
             , "lift-strings"
             , "reindex"
+            , "check-stub-models"
             , "scrub"
             , "generate-default-methods"
             , "fix-block-ret"
@@ -353,6 +366,16 @@ runPass config "hl-finish-drivers" (items, ix) = runPasses' config passes (items
             , "clean-duplicates"
             ]
 
+runPass config "check-stub-models" (items, ix) =
+    case c_stub_models_file config of
+        Nothing -> return (items, ix)
+        Just path -> do
+            stubs <- readStubModels <$> readFile path
+            evaluate $ checkStubModels ix stubs
+            return (items, ix)
+
+runPass config "add-cleanup" (items, ix) = return (addCleanup (c_unwind config) ix items, ix)
+
 runPass _ pass (items, ix) = return (runBasicPass ix pass items, ix)
 
 
@@ -371,13 +394,13 @@ runBasicPass _ "fix-block-ret" = fixBlockReturn
 runBasicPass _ "const-expand" = constExpand
 runBasicPass ix "lift-temps" = liftTemps ix
 runBasicPass ix "rename-locals" = renameLocals ix
-runBasicPass ix "add-cleanup" = addCleanup ix
 runBasicPass _ "filter-extern-fns" = filter (not . isExternFn)
 runBasicPass _ "filter-trait-impls" = filter (not . isTraitImpl)
 runBasicPass _ "filter-stubs" = filter (not . isStub)
 runBasicPass _ "stubify" = stubify
 runBasicPass _ "generate-drop-glues" = generateDropGlues
 runBasicPass _ "lift-strings" = liftStrings
+runBasicPass ix "mark-panics" = markPanics ix
 runBasicPass ix "generate-default-methods" = generateDefaultMethods ix
 runBasicPass _ "cleanup-drops" = cleanupDrops
 runBasicPass _ "cleanup-temps" = cleanupTemps
@@ -409,7 +432,12 @@ crush x = runIdentity $ gfoldl
 whnfList xs = foldl (\a b -> crush b `seq` a) xs xs
 
 
-intrinsicFns = [nondet, assume, assert, unreachable, dropGlue]
+intrinsicFns = [nondet, assume, assert, requires, ensures, invariant, unwind, unreachable,
+                nondetInRange, nondetVec, nondetSlice, nondetStr, nondetBuffer, assumeUtf8,
+                assertNoLeaks, allowAllocFailures, inAllocation, sameAllocation,
+                assertDisjoint, sbPush, sbRetag, sbAccess,
+                unwinding, beginCleanup, resumeUnwind,
+                line, panic, dropGlue]
   where
     externFn name lps tps argTys retTy = IExternFn $ ExternFnDef "intrinsic"
             name lps tps (map (\ty -> ArgDecl $ Pattern ty PWild) argTys) retTy
@@ -417,7 +445,46 @@ intrinsicFns = [nondet, assume, assert, unreachable, dropGlue]
     nondet = externFn "__crust$nondet" [] ["T"] [] (TVar "T")
     assume = externFn "__crust$assume" [] [] [TBool] TUnit
     assert = externFn "__crust$assert" [] [] [TBool] TUnit
+    requires = externFn "__crust$requires" [] [] [TBool] TUnit
+    ensures = externFn "__crust$ensures" [] [] [TBool] TUnit
+    invariant = externFn "__crust$invariant" [] [] [TBool] TUnit
+    unwind = externFn "__crust$unwind" [] [] [usize] TUnit
     unreachable = externFn "__crust$unreachable" [] [] [] TUnit
+    -- Bounded inputs.  `nondet_vec`, `nondet_slice` and `nondet_str` are
+    -- modeled in Rust, in the `__crust_models` crate.
+    nondetInRange = externFn "__crust$nondet_in_range" [] ["T"] [TVar "T", TVar "T"] (TVar "T")
+    nondetVec = externFn "__crust$nondet_vec" [] ["T"] [usize]
+            (TAdt "collections$vec$Vec" [] [TVar "T"])
+    nondetSlice = externFn "__crust$nondet_slice" [] ["T"] [usize]
+            (TRef "r_static" MImm $ TVec $ TVar "T")
+    nondetStr = externFn "__crust$nondet_str" [] [] [usize] (TRef "r_static" MImm TStr)
+    nondetBuffer = externFn "__crust$nondet_buffer" [] ["T"] [usize] (TPtr MMut $ TVar "T")
+    assumeUtf8 = externFn "__crust$assume_utf8" [] []
+            [TRef "r_anon" MImm $ TVec $ TUint $ BitSize 8] TUnit
+    inAllocation = externFn "__crust$in_allocation" [] [] [bytePtr, usize] TBool
+    sameAllocation = externFn "__crust$same_allocation" [] [] [bytePtr, bytePtr] TBool
+    -- Inserted by `rbmc --crust-alias-checks`.
+    assertDisjoint = externFn "__crust$assert_disjoint" [] ["T", "U"]
+            [TRef "r_anon" MImm $ TVar "T", TRef "r_anon" MImm $ TVar "U"] TUnit
+    assertNoLeaks = externFn "__crust$assert_no_leaks" [] [] [] TUnit
+    allowAllocFailures = externFn "__crust$allow_alloc_failures" [] [] [usize] TUnit
+    -- Inserted by `rbmc --crust-borrow-model`.
+    sbPush = externFn "__crust$sb_push" [] ["T"] [TPtr MImm $ TVar "T", usize] usize
+    sbRetag = externFn "__crust$sb_retag" [] ["T"] [TPtr MImm $ TVar "T", usize, usize] usize
+    sbAccess = externFn "__crust$sb_access" [] ["T"] [TPtr MImm $ TVar "T", usize, TBool] TUnit
+    -- Unwinding, with `--unwind`: whether a panic is propagating, and the
+    -- bracket around the cleanup it runs.
+    unwinding = externFn "__crust$unwinding" [] [] [] TBool
+    beginCleanup = externFn "__crust$begin_cleanup" [] [] [] TUnit
+    resumeUnwind = externFn "__crust$resume_unwind" [] [] [] TUnit
+    -- Source position marker emitted by `rbmc --crust-spans`: lo line, lo
+    -- column, hi line, hi column, file name.
+    line = externFn "__crust$line" [] [] [usize, usize, usize, usize, TStr] TUnit
+    -- Panic marker added by `mark-panics`: message, file name, line.
+    panic = externFn "__crust$panic" [] [] [strRef, strRef, TUint $ BitSize 32] TUnit
+    strRef = TRef "r_static" MImm TStr
+    bytePtr = TPtr MImm $ TUint $ BitSize 8
+    usize = TUint PtrSize
 
     dropGlue = IAbstractFn $ AbstractFnDef "drop_glue" [] ["T"]
                     [ArgDecl (Pattern (TRef "r_anon" MMut $ TVar "T") $ PVar "self")] TUnit
@@ -490,6 +557,61 @@ stubify is = map go is
 isStub (IFn (FnDef _ _ _ _ _ _ _ _ (Expr _ (ESimpleLiteral "__stub")))) = True
 isStub _ = False
 
+-- Parse the `TARGET MODEL` lines of an rbmc `--crust-stubs` file.  Blank
+-- lines and `#` comments are skipped, as in rbmc.
+readStubModels content = mapMaybe parse $ lines content
+  where
+    parse l = case words l of
+        [] -> Nothing
+        (w : _) | "#" `isPrefixOf` w -> Nothing
+        [target, model] -> Just (target, model)
+        _ -> error $ "bad line in stub file (expected `TARGET MODEL`): " ++ l
+
+-- rbmc replaces the body of each stubbed function with a call to its model,
+-- forwarding the arguments and type parameters positionally.  Check that the
+-- two signatures line up, so a bad model fails here instead of producing
+-- ill-typed C.  Pairs whose target isn't in this IR are skipped, but a target
+-- without its model means the model crate was left out of the bundle.
+checkStubModels :: Index -> [(Name, Name)] -> ()
+checkStubModels ix stubs = foldr seq () $ map check stubs
+  where
+    check (target, model) =
+        case (M.lookup target (i_fns ix), M.lookup model (i_fns ix)) of
+            (Just (FConcrete t), Just m) -> case stubMismatch t (anyFnSig m) of
+                Nothing -> ()
+                Just msg -> error $ "stub model " ++ model ++ " for " ++ target ++ ": " ++ msg
+            (Just _, Nothing) ->
+                error $ "stub model " ++ model ++ " for " ++ target ++ " is missing"
+            _ -> ()
+
+    anyFnSig (FConcrete (FnDef _ _ lps tps args ret _ _ _)) = (lps, tps, args, ret)
+    anyFnSig (FExtern (ExternFnDef _ _ lps tps args ret)) = (lps, tps, args, ret)
+    anyFnSig (FAbstract (AbstractFnDef _ lps tps args ret)) = (lps, tps, args, ret)
+
+    stubMismatch (FnDef _ _ _ tTps tArgs tRet _ _ _) (mLps, mTps, mArgs, mRet)
+      | length tTps /= length mTps = Just $
+            "expected " ++ show (length tTps) ++ " type parameters, found " ++ show (length mTps)
+      | length tArgs /= length mArgs = Just $
+            "expected " ++ show (length tArgs) ++ " arguments, found " ++ show (length mArgs)
+      | otherwise = listToMaybe $ catMaybes $
+            zipWith3 compareTy [0..] (map argTy tArgs ++ [tRet]) (map argTy mArgs ++ [mRet])
+      where
+        -- Rename the model's type parameters to the target's.  Lifetimes are
+        -- erased by monomorphization, so they don't need to match.
+        renameTy = eraseLifetimes . subst (mLps, mTps) ([], map TVar tTps)
+        compareTy i t m
+          | eraseLifetimes t == renameTy m = Nothing
+          | otherwise = Just $ (if i == length tArgs then "return type" else "argument " ++ show i)
+                ++ " has type " ++ pp m ++ ", expected " ++ pp t
+
+    argTy (ArgDecl (Pattern ty _)) = ty
+
+    eraseLifetimes = everywhere (mkT eraseT)
+    eraseT (TRef _ m t) = TRef "_" m t
+    eraseT (TAdt n ls ts) = TAdt n (map (const "_") ls) ts
+    eraseT (TAbstract n ls ts) = TAbstract n (map (const "_") ls) ts
+    eraseT t = t
+
 cleanupTemps = everywhere (mkT cleanupTempT)
   where
     getUsedVars = everything S.union (S.empty `mkQ` collectUsedVars)
@@ -745,17 +867,23 @@ desugarFor = everywhere (mkT fixFor)
           let failCond = Pattern ety $ PEnum "core$option$Option" 0 [] in
           let successCond = Pattern ety (PEnum "core$option$Option" 1 [patt]) in
           let breakAssign = Expr TUnit $ EAssign continueVar $ Expr TBool $ ESimpleLiteral "0" in
-          let body_e = Expr TUnit body in
+          -- The unwinding bound belongs to the `while`, not the match arm.
+          let (bounds, body') = splitUnwind body in
+          let body_e = Expr TUnit body' in
           let retType = TAdt "core$option$Option" [] [pty] in
           let iterCall = Expr retType $ ECall "core$iter$Iterator$next" [] [iType] [iterVar] in
           let match = Expr TUnit $ EMatch iterCall [(MatchArm successCond body_e), (MatchArm failCond breakAssign)] in
           Expr ty (EBlock [
                     SLet (Pattern TBool (PVar continueFlag)) (Just (Expr TBool (ESimpleLiteral "1"))),
                     SLet (Pattern iType (PVar iterTemp)) (Just (Expr iType (EDeref expr)))
-                   ] (Expr TUnit (EWhile continueVar match))
+                   ] (Expr TUnit (EWhile continueVar (Expr TUnit (EBlock bounds match))))
                   )
       fixFor e = e
 
+      splitUnwind (EBlock (s@(SExpr (Expr _ (ECall "__crust$unwind" _ _ _))) : ss) e) =
+          ([s], EBlock ss e)
+      splitUnwind body = ([], body)
+
       continueFlag = "__fkeepgoing"
       iterTemp = "__fitertemp"
       iterType (TRef _ _ ty) = ty
@@ -809,13 +937,54 @@ fresh base = do
     next `seq` put next
     return $ base ++ show cur
 
+-- Precede each panic whose message and location are known statically with a
+-- call to `__crust$panic(msg, file, line)`, which `crust -panic-check` turns
+-- into a failing assertion described by its arguments.  This covers
+-- `core$panicking$panic(&STATIC)`, `core$panicking$panic_fmt(_, &STATIC)` and
+-- the `crust_panic` of src/crust_macros.rs.  Must run before `lift-strings`.
+markPanics ix = everywhere (mkT goExpr)
+  where
+    goExpr e@(Expr ty (ECall name _ _ args))
+      | Just info <- panicInfo name args =
+        Expr ty $ EBlock [SExpr $ Expr TUnit $ ECall "__crust$panic" [] [] info] e
+    goExpr e = e
+
+    panicInfo "core$panicking$panic" [arg]
+      | Just [msg, file, line] <- staticTuple arg = Just [msg, file, line]
+    panicInfo "core$panicking$panic_fmt" [_, arg]
+      | Just [file, line] <- staticTuple arg = Just [strLit "formatted panic", file, line]
+    panicInfo name args@[_, _, _]
+      | "$crust_panic" `isSuffixOf` name && all isLit args = Just args
+    panicInfo _ _ = Nothing
+
+    staticTuple (Expr _ (EAddrOf (Expr _ (EVar name)))) =
+        case M.lookup name $ i_statics ix of
+            Just (StaticDef _ _ (Expr _ (ETupleLiteral es))) | all isLit es -> Just es
+            _ -> Nothing
+    staticTuple _ = Nothing
+
+    isLit (Expr _ (ESimpleLiteral _)) = True
+    isLit _ = False
+
+    strLit str = Expr (TRef "r_static" MImm TStr) $ ESimpleLiteral $
+        "str_" ++ concatMap (\c -> pad $ showHex (ord c) "") str
+    pad h = replicate (2 - length h) '0' ++ h
+
 liftStrings x = evalState (concat <$> mapM go x) 0
   where
     go :: Item -> State Int [Item]
     go x = do
-        (x', extra) <- runWriterT $ everywhereM (mkM goExpr) x
+        (x', extra) <- runWriterT $ liftIn x
         return $ x' : extra
 
+    -- crust reads the string arguments of these markers at compile time, so
+    -- they stay literals.
+    liftIn :: Data a => a -> WriterT [Item] (State Int) a
+    liftIn x = case cast x of
+        Just (Expr _ (ECall name _ _ _)) | name `elem` ["__crust$line", "__crust$panic"] ->
+            return x
+        _ -> gmapM liftIn x >>= mkM goExpr
+
     goExpr orig@(Expr (TRef life mutbl TStr) (ESimpleLiteral lit)) | lit /= "__stub" = do
         traceShow lit $ do
         let bytes = unhex $ drop 4 lit   -- drop "str_" prefix
@@ -44,10 +44,10 @@ withCollectedRefs ix mkBody input = evalState (go [] [addrOfExpr input]) 0
     go acc (e : es) = go acc es
     go acc [] = return $ mkBody acc
 
-    onFieldTy f (FieldDef name ty) = FieldDef name (f ty)
+    onFieldTy f (FieldDef vis name ty) = FieldDef vis name (f ty)
     onVariantTy f (VariantDef name tys) = VariantDef name (map f tys)
 
-    mkStructLet base (FieldDef name ty) = do
+    mkStructLet base (FieldDef _ name ty) = do
         varName <- fresh name
         return $ SLet (Pattern (refTy ty) $ PRefVar varName) (Just $ Expr ty $ EField base name)
 
@@ -79,7 +79,7 @@ hasRef ix ty = case ty of
     TVar _ -> False
     TAdt name las tas -> case i_types ix M.! name of
         TStruct (StructDef _ lps tps fields _) ->
-            let fieldTys = map (\(FieldDef _ ty) -> ty) fields
+            let fieldTys = map (\(FieldDef _ _ ty) -> ty) fields
                 fieldTys' = map (subst (lps, tps) (las, tas)) fieldTys
             in any (hasRef ix) fieldTys'
         TEnum (EnumDef _ lps tps variants _) ->
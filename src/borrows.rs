@@ -0,0 +1,437 @@
+//! Events for crust's borrow-stack model (`rbmc --crust-borrow-model`).
+//!
+//! crust keeps a stack of borrows for each memory location, in the style of
+//! Stacked Borrows (see `src/crust_intrinsics.h`).  This pass adds the events
+//! that drive it to a translated function body.  Each argument or local bound
+//! by a plain `let` that holds a reference or raw pointer (to a sized type)
+//! gets a companion tag variable:
+//!
+//!     let r = &mut x;
+//!     let __crust_tag_r = __crust::sb_retag(r, 0, KIND);
+//!
+//! `sb_retag(p, parent, kind)` accesses `*p` through the parent's tag (0 is the
+//! owner of a local) and then pushes a fresh tag; `sb_push(p, kind)` pushes one
+//! when the parent is unknown, such as for the result of a call.  Copying a
+//! pointer keeps its tag.  Assigning to a tracked variable updates its tag in
+//! the same way, and a reborrow passed straight to a call is retagged where it
+//! is evaluated.  Each statement that dereferences a tracked variable
+//! `v` is preceded by
+//!
+//!     __crust::sb_access(v, __crust_tag_v, WRITE);
+//!
+//! Only local variables carry tags.  Pointers stored in structs, and direct
+//! uses of an owned local, produce no events.
+
+use ir::*;
+
+// Kinds of borrow, as numbered in crust_intrinsics.h.
+const UNIQUE: usize = 0;
+const SHARED_RO: usize = 1;
+const SHARED_RW: usize = 2;
+
+pub fn instrument(self_arg: Option<&SelfArg>, args: &[Pat], body: Block) -> Block {
+    let mut params = Vec::new();
+    match self_arg {
+        Some(&SelfArg::Region(ref region, mutbl, Some(ref ty), ref name)) =>
+            params.push((name.clone(), Ty::Ref(region.clone(), mutbl, Box::new(ty.clone())))),
+        Some(&SelfArg::Explicit(ref ty, ref name)) =>
+            params.push((name.clone(), ty.clone())),
+        _ => {},
+    }
+    for arg in args.iter() {
+        if let PatKind::Var(ref name) = arg.kind {
+            params.push((name.clone(), arg.ty.clone()));
+        }
+    }
+
+    let mut tracked = Vec::new();
+    let mut stmts = Vec::new();
+    for (name, ty) in params.into_iter() {
+        if let Some(kind) = pointer_kind(&ty) {
+            let var = Expr::new(ty, ExprKind::Var(name.clone()));
+            stmts.push(bind_tag(&*name, sb_call("sb_push", var, vec![literal(kind)], usize_ty())));
+            tracked.push(name);
+        }
+    }
+    let body = block(&*tracked, body);
+    stmts.extend(body.stmts.into_iter());
+    Block {
+        unsafety: body.unsafety,
+        stmts: stmts,
+        expr: body.expr,
+    }
+}
+
+/// The kind of borrow a variable of type `ty` holds, if it is tracked at all.
+fn pointer_kind(ty: &Ty) -> Option<usize> {
+    match *ty {
+        Ty::Ref(_, Mutability::Mut, ref t) if is_sized(&**t) => Some(UNIQUE),
+        Ty::Ref(_, Mutability::Imm, ref t) if is_sized(&**t) => Some(SHARED_RO),
+        Ty::Ptr(_, ref t) if is_sized(&**t) => Some(SHARED_RW),
+        _ => None,
+    }
+}
+
+fn is_sized(ty: &Ty) -> bool {
+    match *ty {
+        Ty::Str | Ty::Vec(_) => false,
+        _ => true,
+    }
+}
+
+fn is_mut_pointer(ty: &Ty) -> bool {
+    match *ty {
+        Ty::Ref(_, Mutability::Mut, _) | Ty::Ptr(Mutability::Mut, _) => true,
+        _ => false,
+    }
+}
+
+fn usize_ty() -> Ty {
+    Ty::Uint(IntSize::PtrSize)
+}
+
+fn literal(n: usize) -> Expr {
+    Expr::new(usize_ty(), ExprKind::SimpleLiteral(format!("{}", n)))
+}
+
+fn tag_name(name: &str) -> Name {
+    format!("__crust_tag_{}", name)
+}
+
+fn tag_var(name: &str) -> Expr {
+    Expr::new(usize_ty(), ExprKind::Var(tag_name(name)))
+}
+
+fn bind_tag(name: &str, init: Expr) -> Stmt {
+    Stmt::Let(Pat { ty: usize_ty(), kind: PatKind::Var(tag_name(name)) }, Some(init), None)
+}
+
+/// A call to `__crust$NAME`, generic over the pointee of `ptr`.
+fn sb_call(name: &str, ptr: Expr, mut args: Vec<Expr>, ret: Ty) -> Expr {
+    let pointee = match ptr.ty {
+        Ty::Ref(_, _, ref t) | Ty::Ptr(_, ref t) => (**t).clone(),
+        _ => panic!("borrow event on a non-pointer"),
+    };
+    let substs = Substs { regions: vec![], types: vec![pointee] };
+    args.insert(0, ptr);
+    Expr::new(ret, ExprKind::Call(format!("__crust${}", name), substs, args))
+}
+
+fn pat_names(pat: &Pat, out: &mut Vec<Name>) {
+    match pat.kind {
+        PatKind::Var(ref name) | PatKind::RefVar(ref name) => out.push(name.clone()),
+        PatKind::Enum(_, _, ref pats) | PatKind::Tuple(ref pats) =>
+            for p in pats.iter() { pat_names(p, out) },
+        PatKind::AddrOf(ref p) => pat_names(&**p, out),
+        PatKind::Wild | PatKind::Const(_) | PatKind::SimpleLiteral(_) => {},
+    }
+}
+
+/// `tracked` without the names bound by `pat`.
+fn unshadowed(tracked: &[Name], pat: &Pat) -> Vec<Name> {
+    let mut bound = Vec::new();
+    pat_names(pat, &mut bound);
+    tracked.iter().filter(|t| !bound.contains(*t)).map(|t| t.clone()).collect()
+}
+
+enum Parent {
+    Owner,
+    Tag(Name),
+    Unknown,
+}
+
+/// Where a borrow of `place` comes from.
+fn place_parent(tracked: &[Name], place: &Expr) -> Parent {
+    match place.kind {
+        ExprKind::Var(_) => Parent::Owner,
+        ExprKind::Field(ref e, _) | ExprKind::Grouped(ref e) => place_parent(tracked, &**e),
+        ExprKind::Index(ref e, _) => match e.ty {
+            Ty::Ref(..) | Ty::Ptr(..) => Parent::Unknown,
+            _ => place_parent(tracked, &**e),
+        },
+        ExprKind::Deref(ref e) => match e.kind {
+            ExprKind::Var(ref name) if tracked.contains(name) => Parent::Tag(name.clone()),
+            _ => Parent::Unknown,
+        },
+        _ => Parent::Unknown,
+    }
+}
+
+/// The initial tag of `name`, a new pointer variable bound to `init`.
+fn tag_init(tracked: &[Name], name: &Name, ty: &Ty, init: &Expr, kind: usize) -> Expr {
+    let parent = match init.kind {
+        ExprKind::AddrOf(ref place) => place_parent(tracked, &**place),
+        ExprKind::Cast(ref e) | ExprKind::Grouped(ref e) => match e.kind {
+            ExprKind::Var(ref v) if tracked.contains(v) => Parent::Tag(v.clone()),
+            ExprKind::AddrOf(ref place) => place_parent(tracked, &**place),
+            _ => Parent::Unknown,
+        },
+        ExprKind::Var(ref v) if tracked.contains(v) && v != name => return tag_var(&**v),
+        _ => Parent::Unknown,
+    };
+    let var = Expr::new(ty.clone(), ExprKind::Var(name.clone()));
+    match parent {
+        Parent::Owner =>
+            sb_call("sb_retag", var, vec![literal(0), literal(kind)], usize_ty()),
+        // `let p = f(p)` would read the new tag variable in its own initializer
+        // once it is C.
+        Parent::Tag(ref v) if v != name =>
+            sb_call("sb_retag", var, vec![tag_var(&**v), literal(kind)], usize_ty()),
+        _ =>
+            sb_call("sb_push", var, vec![literal(kind)], usize_ty()),
+    }
+}
+
+fn block(tracked: &[Name], b: Block) -> Block {
+    let mut tracked = tracked.to_vec();
+    let mut stmts = Vec::new();
+    for stmt in b.stmts.into_iter() {
+        match stmt {
+            Stmt::Let(pat, init, span) => {
+                let init = init.map(|e| expr(&*tracked, e));
+                let tag = match (&pat.kind, &init, pointer_kind(&pat.ty)) {
+                    (&PatKind::Var(ref name), &Some(ref init), Some(kind)) =>
+                        Some((name.clone(), tag_init(&*tracked, name, &pat.ty, init, kind))),
+                    _ => None,
+                };
+                if let Some(ref init) = init {
+                    stmts.extend(accesses(&*tracked, init).into_iter());
+                }
+                tracked = unshadowed(&*tracked, &pat);
+                stmts.push(Stmt::Let(pat, init, span));
+                if let Some((name, tag)) = tag {
+                    stmts.push(bind_tag(&*name, tag));
+                    tracked.push(name);
+                }
+            },
+            Stmt::Expr(e) => {
+                let e = expr(&*tracked, e);
+                let retag = match e.kind {
+                    ExprKind::Assign(ref lhs, ref rhs) => match (&lhs.kind, pointer_kind(&lhs.ty)) {
+                        (&ExprKind::Var(ref name), Some(kind)) if tracked.contains(name) =>
+                            Some((name.clone(), tag_init(&*tracked, name, &lhs.ty, &**rhs, kind))),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                stmts.extend(accesses(&*tracked, &e).into_iter());
+                stmts.push(Stmt::Expr(e));
+                if let Some((name, tag)) = retag {
+                    let tag = ExprKind::Assign(Box::new(tag_var(&*name)), Box::new(tag));
+                    stmts.push(Stmt::Expr(Expr::new(Ty::Unit, tag)));
+                }
+            },
+        }
+    }
+    let tail = expr(&*tracked, *b.expr);
+    stmts.extend(accesses(&*tracked, &tail).into_iter());
+    Block {
+        unsafety: b.unsafety,
+        stmts: stmts,
+        expr: Box::new(tail),
+    }
+}
+
+/// Adds events to the blocks and match arms nested in `e`.  The events for `e`
+/// itself are left to the enclosing statement.
+fn expr(tracked: &[Name], e: Expr) -> Expr {
+    let Expr { ty, kind, span } = e;
+    let go = |e: Box<Expr>| Box::new(expr(tracked, *e));
+    let go_all = |es: Vec<Expr>| -> Vec<Expr> {
+        es.into_iter().map(|e| expr(tracked, e)).collect()
+    };
+    let kind = match kind {
+        ExprKind::StructLiteral(fields) =>
+            ExprKind::StructLiteral(fields.into_iter().map(|f| Field {
+                name: f.name,
+                expr: expr(tracked, f.expr),
+            }).collect()),
+        ExprKind::EnumLiteral(name, idx, es) => ExprKind::EnumLiteral(name, idx, go_all(es)),
+        ExprKind::TupleLiteral(es) => ExprKind::TupleLiteral(go_all(es)),
+        ExprKind::Vec(es) => ExprKind::Vec(go_all(es)),
+        ExprKind::Call(name, substs, es) =>
+            ExprKind::Call(name, substs, es.into_iter().map(|e| call_arg(tracked, e)).collect()),
+        ExprKind::BinOp(op, a, b) => ExprKind::BinOp(op, go(a), go(b)),
+        ExprKind::UnOp(op, a) => ExprKind::UnOp(op, go(a)),
+        ExprKind::Deref(a) => ExprKind::Deref(go(a)),
+        ExprKind::AddrOf(a) => ExprKind::AddrOf(go(a)),
+        ExprKind::Cast(a) => ExprKind::Cast(go(a)),
+        ExprKind::Field(a, name) => ExprKind::Field(go(a), name),
+        ExprKind::Index(a, b) => ExprKind::Index(go(a), go(b)),
+        ExprKind::Range(a, b) => ExprKind::Range(a.map(|a| go(a)), b.map(|b| go(b))),
+        ExprKind::Match(scrut, arms) =>
+            ExprKind::Match(go(scrut), arms.into_iter().map(|arm| {
+                let inner = unshadowed(tracked, &arm.pat);
+                MatchArm { body: arm_body(&*inner, arm.body), pat: arm.pat }
+            }).collect()),
+        ExprKind::Block(b) => ExprKind::Block(block(tracked, b)),
+        ExprKind::While(cond, body, info) => {
+            let cond = go(cond);
+            let body = loop_body(tracked, &*cond, body);
+            ExprKind::While(cond, body, info)
+        },
+        // Preprocess evaluates the iterator once, into a temporary, before the
+        // loop starts, so its events stay with the enclosing statement.
+        ExprKind::For(pat, iter, body, info) => {
+            let inner = unshadowed(tracked, &pat);
+            ExprKind::For(pat, go(iter), block(&*inner, body), info)
+        },
+        ExprKind::Assign(a, b) => ExprKind::Assign(go(a), go(b)),
+        ExprKind::AssignOp(op, a, b) => ExprKind::AssignOp(op, go(a), go(b)),
+        ExprKind::Return(a) => ExprKind::Return(go(a)),
+        ExprKind::UnsizeLen(len, a) => ExprKind::UnsizeLen(len, go(a)),
+        ExprKind::Grouped(a) => ExprKind::Grouped(go(a)),
+        // Variables, constants, literals, `break` and `continue`
+        kind => kind,
+    };
+    Expr { ty: ty, kind: kind, span: span }
+}
+
+/// A reborrow of a tracked pointer passed straight to a call, such as
+/// `f(&mut *p)`, is retagged where it is evaluated:
+///
+///     f({
+///         let __crust_reborrow = &mut *p;
+///         __crust::sb_retag(__crust_reborrow, __crust_tag_p, KIND);
+///         __crust_reborrow
+///     })
+fn call_arg(tracked: &[Name], e: Expr) -> Expr {
+    let e = expr(tracked, e);
+    let parent = match (&e.kind, pointer_kind(&e.ty)) {
+        (&ExprKind::AddrOf(ref place), Some(kind)) => match place_parent(tracked, &**place) {
+            Parent::Tag(name) => Some((name, kind)),
+            _ => None,
+        },
+        _ => None,
+    };
+    let (parent, kind) = match parent {
+        Some(p) => p,
+        None => return e,
+    };
+    let ty = e.ty.clone();
+    let tmp = format!("__crust_reborrow");
+    let var = Expr::new(ty.clone(), ExprKind::Var(tmp.clone()));
+    let retag = sb_call("sb_retag", var.clone(), vec![tag_var(&*parent), literal(kind)], usize_ty());
+    Expr::new(ty.clone(), ExprKind::Block(Block {
+        unsafety: false,
+        stmts: vec![
+            Stmt::Let(Pat { ty: ty, kind: PatKind::Var(tmp) }, Some(e), None),
+            Stmt::Expr(retag),
+        ],
+        expr: Box::new(var),
+    }))
+}
+
+/// The enclosing statement only checks the first evaluation of a `while`
+/// condition, so its events are repeated at the top of the body and again at
+/// the end, before the condition runs for the next iteration.
+fn loop_body(tracked: &[Name], cond: &Expr, body: Block) -> Block {
+    let body = block(tracked, body);
+    let events = accesses(tracked, cond);
+    if events.len() == 0 {
+        return body;
+    }
+    let mut stmts = events.clone();
+    stmts.extend(body.stmts.into_iter());
+    let unit_tail = match body.expr.kind {
+        ExprKind::Placeholder(_) => true,
+        _ => false,
+    };
+    if !unit_tail {
+        stmts.push(Stmt::Expr(*body.expr));
+    }
+    stmts.extend(events.into_iter());
+    Block {
+        unsafety: body.unsafety,
+        stmts: stmts,
+        expr: Box::new(Expr::new(Ty::Unit, ExprKind::Placeholder("_Block"))),
+    }
+}
+
+/// A match arm only runs sometimes, so its events go inside it.
+fn arm_body(tracked: &[Name], body: Expr) -> Expr {
+    let body = expr(tracked, body);
+    let events = accesses(tracked, &body);
+    if events.len() == 0 {
+        return body;
+    }
+    Expr::new(body.ty.clone(), ExprKind::Block(Block {
+        unsafety: false,
+        stmts: events,
+        expr: Box::new(body),
+    }))
+}
+
+/// `sb_access` events for the tracked variables that `e` dereferences,
+/// outside of any nested block or match arm.
+fn accesses(tracked: &[Name], e: &Expr) -> Vec<Stmt> {
+    let mut found: Vec<(Name, Ty, bool)> = Vec::new();
+    collect_derefs(tracked, e, false, &mut found);
+    found.into_iter().map(|(name, ty, write)| {
+        let var = Expr::new(ty, ExprKind::Var(name.clone()));
+        let write = Expr::new(Ty::Bool, ExprKind::SimpleLiteral(format!("{}", write)));
+        Stmt::Expr(sb_call("sb_access", var, vec![tag_var(&*name), write], Ty::Unit))
+    }).collect()
+}
+
+/// `write` is set when `e` is (part of) a place being written or mutably
+/// borrowed.
+fn collect_derefs(tracked: &[Name], e: &Expr, write: bool, out: &mut Vec<(Name, Ty, bool)>) {
+    match e.kind {
+        ExprKind::Deref(ref inner) => {
+            if let ExprKind::Var(ref name) = inner.kind {
+                if tracked.contains(name) {
+                    match out.iter().position(|&(ref n, _, _)| n == name) {
+                        Some(i) => out[i].2 |= write,
+                        None => out.push((name.clone(), inner.ty.clone(), write)),
+                    }
+                }
+            }
+            collect_derefs(tracked, &**inner, false, out);
+        },
+        ExprKind::Field(ref inner, _) | ExprKind::Grouped(ref inner) =>
+            collect_derefs(tracked, &**inner, write, out),
+        ExprKind::Index(ref base, ref idx) => {
+            collect_derefs(tracked, &**base, write, out);
+            collect_derefs(tracked, &**idx, false, out);
+        },
+        ExprKind::Assign(ref lhs, ref rhs) | ExprKind::AssignOp(_, ref lhs, ref rhs) => {
+            collect_derefs(tracked, &**lhs, true, out);
+            collect_derefs(tracked, &**rhs, false, out);
+        },
+        ExprKind::AddrOf(ref inner) =>
+            collect_derefs(tracked, &**inner, is_mut_pointer(&e.ty), out),
+        // The right side of `&&` and `||` doesn't always run.
+        ExprKind::BinOp(ref op, ref a, ref b) => {
+            collect_derefs(tracked, &**a, false, out);
+            if *op != "BiAnd" && *op != "BiOr" {
+                collect_derefs(tracked, &**b, false, out);
+            }
+        },
+        ExprKind::StructLiteral(ref fields) =>
+            for f in fields.iter() { collect_derefs(tracked, &f.expr, false, out) },
+        ExprKind::EnumLiteral(_, _, ref es) |
+        ExprKind::TupleLiteral(ref es) |
+        ExprKind::Vec(ref es) |
+        ExprKind::Call(_, _, ref es) =>
+            for e in es.iter() { collect_derefs(tracked, e, false, out) },
+        ExprKind::UnOp(_, ref a) |
+        ExprKind::Cast(ref a) |
+        ExprKind::Return(ref a) |
+        ExprKind::UnsizeLen(_, ref a) |
+        ExprKind::Match(ref a, _) |
+        ExprKind::While(ref a, _, _) |
+        ExprKind::For(_, ref a, _, _) => collect_derefs(tracked, &**a, false, out),
+        ExprKind::Range(ref a, ref b) => {
+            if let Some(ref a) = *a { collect_derefs(tracked, &**a, false, out) }
+            if let Some(ref b) = *b { collect_derefs(tracked, &**b, false, out) }
+        },
+        ExprKind::Block(_) |
+        ExprKind::Var(_) |
+        ExprKind::Const(_) |
+        ExprKind::SimpleLiteral(_) |
+        ExprKind::Break |
+        ExprKind::Continue |
+        ExprKind::Placeholder(_) => {},
+    }
+}
@@ -0,0 +1,229 @@
+//! `--crust-check-only`: walk the type-checked crate and report every
+//! construct that `trans` can't translate, instead of translating it.
+//!
+//! Translation stops at the first unsupported construct in an item, so its
+//! diagnostics show one problem at a time.  This pass finds them all, with a
+//! suggested rewrite for each.  Types are checked where a value is bound (in
+//! patterns, including fn arguments), in struct fields and at casts, so a
+//! trait object passed around is reported once rather than at every use.
+
+use std::collections::HashSet;
+
+use rustc::middle::ty::{self, Ty};
+use syntax::ast::*;
+use syntax::codemap::Span;
+use syntax::visit::{self, FnKind, Visitor};
+
+use ir;
+use trans;
+
+struct Checker<'a, 'tcx: 'a> {
+    tcx: &'a ty::ctxt<'tcx>,
+    // Path of the item being checked, for `Diagnostic::item`.
+    item: String,
+    diags: Vec<ir::Diagnostic>,
+    // Spans (as byte offsets) and constructs already reported.
+    seen: HashSet<(u32, u32, &'static str)>,
+}
+
+pub fn check_crate(tcx: &ty::ctxt) -> Vec<ir::Diagnostic> {
+    let mut checker = Checker {
+        tcx: tcx,
+        item: String::new(),
+        diags: Vec::new(),
+        seen: HashSet::new(),
+    };
+    visit::walk_crate(&mut checker, tcx.map.krate());
+    checker.diags
+}
+
+impl<'a, 'tcx> Checker<'a, 'tcx> {
+    fn report(&mut self, sp: Span, construct: &'static str, message: &str, help: &str) {
+        if !self.seen.insert((sp.lo.0, sp.hi.0, construct)) {
+            return;
+        }
+        self.diags.push(ir::Diagnostic {
+            item: self.item.clone(),
+            span: trans::source_span(self.tcx.sess.codemap(), sp),
+            construct: String::from_str(construct),
+            message: String::from_str(message),
+            help: Some(String::from_str(help)),
+        });
+    }
+
+    fn check_node_ty(&mut self, sp: Span, id: NodeId) {
+        let opt_ty = self.tcx.node_types().get(&id).map(|&t| t);
+        if let Some(t) = opt_ty {
+            self.check_ty(sp, t);
+        }
+    }
+
+    fn check_ty(&mut self, sp: Span, t: Ty<'tcx>) {
+        let mut found = Vec::new();
+        ty::walk_ty(t, |t| {
+            match t.sty {
+                ty::ty_trait(..) => found.push("ty_trait"),
+                ty::ty_closure(..) => found.push("ty_closure"),
+                ty::ty_bare_fn(..) => found.push("ty_bare_fn"),
+                ty::ty_uniq(..) => found.push("ty_uniq"),
+                ty::ty_rptr(r, _) if !region_ok(r) => found.push("Region"),
+                _ => {},
+            }
+        });
+        for construct in found.into_iter() {
+            let (message, help) = match construct {
+                "ty_trait" => ("trait objects are not supported",
+                               "make the code generic over the trait instead"),
+                "ty_closure" => ("closure types are not supported",
+                                 "use a named fn, or a struct with a method"),
+                "ty_bare_fn" => ("fn pointers are not supported",
+                                 "call the fn directly, or take a type implementing a trait"),
+                "ty_uniq" => ("`Box` is not supported",
+                              "allocate through a raw pointer, as the collections in core do"),
+                _ => ("unsupported region kind",
+                      "name the lifetime in the signature"),
+            };
+            self.report(sp, construct, message, help);
+        }
+    }
+}
+
+/// Whether `trans` has a name for the region.
+fn region_ok(r: &ty::Region) -> bool {
+    match *r {
+        ty::ReEarlyBound(..) | ty::ReStatic | ty::ReScope(..) | ty::ReEmpty => true,
+        ty::ReLateBound(_, br) => bound_region_ok(br),
+        ty::ReFree(ref fr) => bound_region_ok(fr.bound_region),
+        _ => false,
+    }
+}
+
+fn bound_region_ok(br: ty::BoundRegion) -> bool {
+    match br {
+        ty::BrAnon(_) | ty::BrNamed(..) => true,
+        _ => false,
+    }
+}
+
+impl<'a, 'tcx, 'v> Visitor<'v> for Checker<'a, 'tcx> {
+    fn visit_item(&mut self, i: &'v Item) {
+        let old_item = ::std::mem::replace(&mut self.item, self.tcx.map.path_to_string(i.id));
+        visit::walk_item(self, i);
+        self.item = old_item;
+    }
+
+    fn visit_fn(&mut self, fk: FnKind<'v>, fd: &'v FnDecl, b: &'v Block, s: Span, id: NodeId) {
+        let old_item = match fk {
+            visit::FkMethod(..) =>
+                Some(::std::mem::replace(&mut self.item, self.tcx.map.path_to_string(id))),
+            _ => None,
+        };
+        visit::walk_fn(self, fk, fd, b, s);
+        if let Some(old_item) = old_item {
+            self.item = old_item;
+        }
+    }
+
+    fn visit_struct_field(&mut self, f: &'v StructField) {
+        self.check_node_ty(f.span, f.node.id);
+        visit::walk_struct_field(self, f);
+    }
+
+    fn visit_variant(&mut self, v: &'v Variant, g: &'v Generics) {
+        if let StructVariantKind(_) = v.node.kind {
+            self.report(v.span, "StructVariantKind", "struct-like enum variants are not supported",
+                        "use a tuple variant, or a tuple variant holding a struct");
+        }
+        visit::walk_variant(self, v, g);
+    }
+
+    fn visit_pat(&mut self, p: &'v Pat) {
+        self.check_node_ty(p.span, p.id);
+        match p.node {
+            PatWild(PatWildMulti) =>
+                self.report(p.span, "PatWildMulti", "`..` patterns are not supported",
+                            "write `_` for each remaining field"),
+            PatIdent(_, _, Some(_)) =>
+                self.report(p.span, "PatIdent", "`name @ pattern` bindings are not supported",
+                            "bind the whole value, then match it again in the arm"),
+            PatStruct(..) =>
+                self.report(p.span, "PatStruct", "struct patterns are not supported",
+                            "bind the value and read its fields"),
+            PatBox(..) =>
+                self.report(p.span, "PatBox", "box patterns are not supported",
+                            "dereference the box before matching"),
+            PatRange(..) =>
+                self.report(p.span, "PatRange", "range patterns are not supported",
+                            "compare the value in an `if` instead"),
+            PatVec(..) =>
+                self.report(p.span, "PatVec", "slice patterns are not supported",
+                            "check the length, then index the slice"),
+            PatEnum(_, None) =>
+                self.report(p.span, "PatEnum", "`Variant(..)` patterns are not supported",
+                            "write `_` for each of the variant's fields"),
+            PatQPath(..) =>
+                self.report(p.span, "PatQPath", "qualified path patterns are not supported",
+                            "bind the value and compare it with `==`"),
+            PatMac(..) =>
+                self.report(p.span, "PatMac", "macros in pattern position are not supported",
+                            "write the expanded pattern out"),
+            PatWild(PatWildSingle) | PatIdent(_, _, None) | PatEnum(_, Some(_)) |
+            PatTup(..) | PatRegion(..) | PatLit(..) => {},
+        }
+        match p.node {
+            // Literal and range patterns are evaluated as constants, so their
+            // expressions need not translate.
+            PatLit(_) | PatRange(..) => {},
+            _ => visit::walk_pat(self, p),
+        }
+    }
+
+    fn visit_expr(&mut self, ex: &'v Expr) {
+        match ex.node {
+            ExprClosure(..) =>
+                self.report(ex.span, "ExprClosure", "closures are not supported",
+                            "use a named fn, or a struct with a method, and pass it explicitly"),
+            ExprBox(..) =>
+                self.report(ex.span, "ExprBox", "`box` expressions are not supported",
+                            "allocate through a raw pointer, as the collections in core do"),
+            // `if let` is expanded into a `match`, and any `else if` after it
+            // into guarded arms.  Guards are dropped by `trans`.
+            ExprMatch(_, ref arms, MatchSource::IfLetDesugar { .. }) => {
+                if arms.iter().any(|a| a.guard.is_some()) {
+                    self.report(ex.span, "ExprIfLet", "`if let` followed by `else if` is not supported",
+                                "nest the `if` inside an `else` block, or use `match`");
+                }
+            },
+            ExprMatch(_, ref arms, _) => {
+                for guard in arms.iter().filter_map(|a| a.guard.as_ref()) {
+                    self.report(guard.span, "guard", "match guards are not supported",
+                                "move the condition into an `if` in the arm body");
+                }
+            },
+            ExprInlineAsm(..) =>
+                self.report(ex.span, "ExprInlineAsm", "inline assembly is not supported",
+                            "move it into its own fn and replace that with a model (--crust-stubs)"),
+            ExprRepeat(..) =>
+                self.report(ex.span, "ExprRepeat", "`[value; n]` array expressions are not supported",
+                            "write the elements out, or fill the array in a loop"),
+            ExprBreak(Some(_)) | ExprAgain(Some(_)) =>
+                self.report(ex.span, "ExprBreak", "labeled `break` and `continue` are not supported",
+                            "set a flag and test it in the outer loop, or move the inner loop into a fn"),
+            ExprStruct(_, _, Some(_)) =>
+                self.report(ex.span, "ExprStruct", "struct update syntax (`..base`) is not supported",
+                            "list every field"),
+            ExprLit(ref lit) => match lit.node {
+                LitFloat(..) | LitFloatUnsuffixed(..) =>
+                    self.report(ex.span, "LitFloat", "float literals are not supported",
+                                "use integer or fixed-point arithmetic"),
+                LitBinary(..) =>
+                    self.report(ex.span, "LitBinary", "byte string literals are not supported",
+                                "use an array of byte literals"),
+                _ => {},
+            },
+            ExprCast(..) => self.check_node_ty(ex.span, ex.id),
+            _ => {},
+        }
+        visit::walk_expr(self, ex);
+    }
+}
@@ -0,0 +1,259 @@
+//! Function contracts, written as attributes holding Rust expressions:
+//!
+//!     #[crust_requires = "i < v.len()"]
+//!     #[crust_ensures = "result == old(v[i])"]
+//!     fn get(v: &[u8], i: usize) -> u8 { ... }
+//!
+//! (Attributes can't hold bare expressions, hence the strings.)  Before type
+//! checking, the body of each function with a contract is rewritten to
+//!
+//!     {
+//!         ::__crust::requires(PRE_1 && ... && PRE_n);
+//!         let __crust_old_0 = E_0;        // one per `old(E_0)` in the ensures
+//!         let result = BODY;
+//!         ::__crust::ensures(POST_1 && ... && POST_n);
+//!         result
+//!     }
+//!
+//! so the conditions are type checked and translated like any other code, and
+//! the two calls mark the contract in the IR.  `old(E)` is evaluated on entry,
+//! so `E` must be `Copy` (or be written as `old(E.clone())`).  Since the
+//! postcondition is checked at the end of the body, and `ensures` is also
+//! where crust leaves the contract (see `crust_contract_depth`), functions
+//! with a contract can't `return` early.  Default methods of a trait can have
+//! contracts; required methods can't, as they have no body to wrap.
+
+use std::collections::HashMap;
+
+use rustc::session::Session;
+use syntax::ast::*;
+use syntax::attr::AttrMetaMethods;
+use syntax::codemap::Span;
+use syntax::fold::{self, Folder};
+use syntax::parse;
+use syntax::ptr::P;
+use syntax::util::small_vector::SmallVector;
+use syntax::visit::{self, Visitor};
+
+pub fn expand(sess: &Session, krate: Crate) -> Crate {
+    let cfg = krate.config.clone();
+    ContractExpander { sess: sess, cfg: cfg }.fold_crate(krate)
+}
+
+struct ContractExpander<'a> {
+    sess: &'a Session,
+    cfg: CrateConfig,
+}
+
+impl<'a> ContractExpander<'a> {
+    fn parse_expr(&self, name: &str, src: String) -> P<Expr> {
+        parse::parse_expr_from_source_str(format!("<{}>", name),
+                                          src,
+                                          self.cfg.clone(),
+                                          &self.sess.parse_sess)
+    }
+
+    fn expand_body(&self, attrs: &[Attribute], body: P<Block>, span: Span) -> P<Block> {
+        let mut pres = Vec::new();
+        let mut posts = Vec::new();
+        for attr in attrs.iter() {
+            let conds = if attr.check_name("crust_requires") {
+                &mut pres
+            } else if attr.check_name("crust_ensures") {
+                &mut posts
+            } else {
+                continue;
+            };
+            match attr.value_str() {
+                Some(src) => conds.push(self.parse_expr(&*attr.name(), src.to_string())),
+                None => self.sess.span_err(attr.span,
+                                           "expected a condition: `#[crust_... = \"EXPR\"]`"),
+            }
+        }
+        if pres.len() == 0 && posts.len() == 0 {
+            return body;
+        }
+
+        let mut finder = ReturnFinder { found: None };
+        visit::walk_block(&mut finder, &*body);
+        if let Some(ret_span) = finder.found {
+            self.sess.span_err(ret_span,
+                               "`return` in a function with a contract; \
+                                it would skip the contract's exit check");
+        }
+
+        let mut olds = OldRewriter { expander: self, inits: Vec::new() };
+        let posts = posts.into_iter().map(|e| olds.fold_expr(e)).collect::<Vec<_>>();
+
+        // Build the wrapper from source, with placeholders for the pieces we
+        // already have as ASTs.
+        let mut pieces = HashMap::new();
+        let pre_names = pres.into_iter().enumerate().map(|(i, e)| {
+            let name = format!("__crust_pre_{}", i);
+            pieces.insert(name.clone(), e);
+            name
+        }).collect::<Vec<_>>();
+        let post_names = posts.into_iter().enumerate().map(|(i, e)| {
+            let name = format!("__crust_post_{}", i);
+            pieces.insert(name.clone(), e);
+            name
+        }).collect::<Vec<_>>();
+        let mut src = String::new();
+        src.push_str(&*format!("{{ ::__crust::requires({});\n", conjunction(&*pre_names)));
+        for (i, init) in olds.inits.into_iter().enumerate() {
+            let name = format!("__crust_old_init_{}", i);
+            src.push_str(&*format!("let __crust_old_{} = {};\n", i, name));
+            pieces.insert(name, init);
+        }
+        src.push_str("let result = __crust_body;\n");
+        src.push_str(&*format!("::__crust::ensures({});\n", conjunction(&*post_names)));
+        src.push_str("result }");
+        pieces.insert(String::from_str("__crust_body"), P(Expr {
+            id: DUMMY_NODE_ID,
+            node: ExprBlock(body),
+            span: span,
+        }));
+
+        let wrapper = Fill { pieces: pieces }.fold_expr(self.parse_expr("crust contract", src));
+        match wrapper.node {
+            ExprBlock(ref b) => b.clone(),
+            _ => panic!("contract wrapper is not a block"),
+        }
+    }
+}
+
+fn conjunction(names: &[String]) -> String {
+    if names.len() == 0 {
+        String::from_str("true")
+    } else {
+        names.connect(" && ")
+    }
+}
+
+impl<'a> Folder for ContractExpander<'a> {
+    fn fold_item(&mut self, i: P<Item>) -> SmallVector<P<Item>> {
+        let i = i.map(|Item { ident, attrs, id, node, vis, span }| {
+            let node = match node {
+                ItemFn(decl, unsafety, abi, generics, body) => {
+                    let body = self.expand_body(&*attrs, body, span);
+                    ItemFn(decl, unsafety, abi, generics, body)
+                },
+                node => node,
+            };
+            Item { ident: ident, attrs: attrs, id: id, node: node, vis: vis, span: span }
+        });
+        fold::noop_fold_item(i, self)
+    }
+
+    fn fold_impl_item(&mut self, i: P<ImplItem>) -> SmallVector<P<ImplItem>> {
+        let i = i.map(|ImplItem { id, ident, vis, attrs, node, span }| {
+            let node = match node {
+                MethodImplItem(sig, body) => {
+                    let body = self.expand_body(&*attrs, body, span);
+                    MethodImplItem(sig, body)
+                },
+                node => node,
+            };
+            ImplItem { id: id, ident: ident, vis: vis, attrs: attrs, node: node, span: span }
+        });
+        fold::noop_fold_impl_item(i, self)
+    }
+
+    fn fold_trait_item(&mut self, i: P<TraitItem>) -> SmallVector<P<TraitItem>> {
+        let i = i.map(|TraitItem { id, ident, attrs, node, span }| {
+            let node = match node {
+                MethodTraitItem(sig, Some(body)) => {
+                    let body = self.expand_body(&*attrs, body, span);
+                    MethodTraitItem(sig, Some(body))
+                },
+                MethodTraitItem(sig, None) => {
+                    for attr in attrs.iter() {
+                        if attr.check_name("crust_requires") || attr.check_name("crust_ensures") {
+                            self.sess.span_err(attr.span,
+                                               "contract on a required trait method; \
+                                                put it on a default body or on each impl");
+                        }
+                    }
+                    MethodTraitItem(sig, None)
+                },
+                node => node,
+            };
+            TraitItem { id: id, ident: ident, attrs: attrs, node: node, span: span }
+        });
+        fold::noop_fold_trait_item(i, self)
+    }
+}
+
+/// Replaces each `old(E)` with a fresh `__crust_old_N`, collecting the `E`s.
+struct OldRewriter<'b, 'a: 'b> {
+    expander: &'b ContractExpander<'a>,
+    inits: Vec<P<Expr>>,
+}
+
+impl<'b, 'a> Folder for OldRewriter<'b, 'a> {
+    fn fold_expr(&mut self, e: P<Expr>) -> P<Expr> {
+        let is_old = match e.node {
+            ExprCall(ref func, ref args) => args.len() == 1 && path_name(&**func) == Some("old"),
+            _ => false,
+        };
+        if !is_old {
+            return e.map(|e| fold::noop_fold_expr(e, self));
+        }
+        let arg = match e.node {
+            ExprCall(_, ref args) => args[0].clone(),
+            _ => unreachable!(),
+        };
+        let name = format!("__crust_old_{}", self.inits.len());
+        self.inits.push(arg);
+        self.expander.parse_expr("crust contract", name)
+    }
+}
+
+/// Substitutes ASTs for the placeholder variables in the contract wrapper.
+struct Fill {
+    pieces: HashMap<String, P<Expr>>,
+}
+
+impl Folder for Fill {
+    fn fold_expr(&mut self, e: P<Expr>) -> P<Expr> {
+        let piece = match path_name(&*e) {
+            Some(name) => self.pieces.remove(name),
+            None => None,
+        };
+        match piece {
+            Some(piece) => piece,
+            None => e.map(|e| fold::noop_fold_expr(e, self)),
+        }
+    }
+}
+
+/// The name of a path expression consisting of a single plain identifier.
+fn path_name(e: &Expr) -> Option<&str> {
+    match e.node {
+        ExprPath(None, ref path) if !path.global && path.segments.len() == 1 =>
+            Some(path.segments[0].identifier.as_str()),
+        _ => None,
+    }
+}
+
+struct ReturnFinder {
+    found: Option<Span>,
+}
+
+impl<'v> Visitor<'v> for ReturnFinder {
+    fn visit_expr(&mut self, e: &'v Expr) {
+        match e.node {
+            ExprRet(_) => {
+                if self.found.is_none() {
+                    self.found = Some(e.span);
+                }
+            },
+            // A `return` inside a closure returns from the closure.
+            ExprClosure(..) => {},
+            _ => visit::walk_expr(self, e),
+        }
+    }
+
+    // Nor does one in a nested fn.
+    fn visit_item(&mut self, _: &'v Item) {}
+}
@@ -0,0 +1,66 @@
+#![crate_type = "lib"]
+#![crate_name = "__crust_models"]
+#![feature(no_std)]
+#![feature(core)]
+#![feature(collections)]
+#![no_std]
+extern crate core;
+extern crate collections;
+extern crate __crust;
+
+use core::prelude::{Ord, SliceExt};
+use core::{mem, ptr, slice};
+use collections::vec::Vec;
+
+// Models for use with `rbmc --crust-stubs`.  Each one has the signature of
+// the function it stands in for, with the `self` argument (if any) made
+// explicit.
+
+// core$slice$sort  __crust_models$slice_sort
+//
+// Produces some permutation of `v` that is in order, without committing to a
+// particular algorithm.
+pub fn slice_sort<T: Ord>(v: &mut [T]) {
+    let len = v.len();
+    let mut i = 0;
+    while i < len {
+        let j: usize = __crust::nondet();
+        __crust::assume(i <= j && j < len);
+        v.swap(i, j);
+        i += 1;
+    }
+
+    let mut i = 1;
+    while i < len {
+        __crust::assume(v[i - 1] <= v[i]);
+        i += 1;
+    }
+}
+
+// Models for the bounded input helpers in `__crust`.  crust calls
+// `__crust_models$F` in place of any `__crust$F` that has one.
+
+pub fn nondet_vec<T>(max_len: usize) -> Vec<T> {
+    let len: usize = __crust::nondet();
+    __crust::assume(len <= max_len);
+    let mut v = Vec::with_capacity(len);
+    // The vector's fresh allocation counts as uninitialized under
+    // `crust -uninit-check`, so copy the elements in from a nondet buffer.
+    unsafe {
+        ptr::copy_nonoverlapping(__crust::nondet_buffer(len) as *const T, v.as_mut_ptr(), len);
+        v.set_len(len);
+    }
+    v
+}
+
+pub fn nondet_slice<T>(max_len: usize) -> &'static [T] {
+    let len: usize = __crust::nondet();
+    __crust::assume(len <= max_len);
+    unsafe { slice::from_raw_parts(__crust::nondet_buffer(len), len) }
+}
+
+pub fn nondet_str(max_len: usize) -> &'static str {
+    let bytes: &'static [u8] = nondet_slice(max_len);
+    __crust::assume_utf8(bytes);
+    unsafe { mem::transmute(bytes) }
+}
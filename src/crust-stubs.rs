@@ -2,8 +2,12 @@
 #![crate_name = "__crust"]
 #![feature(no_std)]
 #![feature(core)]
+#![feature(collections)]
 #![no_std]
 extern crate core;
+extern crate collections;
+
+use collections::vec::Vec;
 
 pub fn nondet<T>() -> T {
     unsafe { core::intrinsics::abort() }
@@ -12,6 +16,69 @@ pub fn nondet<T>() -> T {
 pub fn assume(cond: bool) { }
 pub fn assert(cond: bool) { }
 
+// Bounded inputs.  `nondet_in_range` returns an integer in `[lo, hi)`; the
+// others have a nondeterministic length of at most `max_len` and arbitrary
+// contents (for `nondet_str`, any valid UTF-8).  They are built in one step,
+// without a loop for CBMC to unroll.  The collections are modeled in
+// src/crust-models.rs on top of `nondet_buffer` and `assume_utf8`.
+pub fn nondet_in_range<T>(lo: T, hi: T) -> T {
+    unsafe { core::intrinsics::abort() }
+}
+pub fn nondet_vec<T>(max_len: usize) -> Vec<T> {
+    unsafe { core::intrinsics::abort() }
+}
+pub fn nondet_slice<T>(max_len: usize) -> &'static [T] {
+    unsafe { core::intrinsics::abort() }
+}
+pub fn nondet_str(max_len: usize) -> &'static str {
+    unsafe { core::intrinsics::abort() }
+}
+
+// Fresh memory for `len` values of `T`, with arbitrary contents.
+pub fn nondet_buffer<T>(len: usize) -> *mut T {
+    unsafe { core::intrinsics::abort() }
+}
+pub fn assume_utf8(bytes: &[u8]) { }
+
+// Pointer facts behind the `AsPtr` checks in src/crust.rs.  `in_allocation`
+// holds when `len` bytes from `p` lie within the object `p` points into.
+pub fn in_allocation(p: *const u8, len: usize) -> bool {
+    unsafe { core::intrinsics::abort() }
+}
+pub fn same_allocation(p: *const u8, q: *const u8) -> bool {
+    unsafe { core::intrinsics::abort() }
+}
+
+// Fails if the two values overlap in memory.  `rbmc --crust-alias-checks`
+// inserts calls to this after each `&mut` created in unsafe code.
+pub fn assert_disjoint<T, U>(p: &T, q: &U) { }
+
+// Borrow-stack events, inserted by `rbmc --crust-borrow-model` (see
+// src/borrows.rs).  The tags are handed out by crust.
+pub fn sb_push<T>(p: *const T, kind: usize) -> usize {
+    unsafe { core::intrinsics::abort() }
+}
+pub fn sb_retag<T>(p: *const T, parent: usize, kind: usize) -> usize {
+    unsafe { core::intrinsics::abort() }
+}
+pub fn sb_access<T>(p: *const T, tag: usize, write: bool) { }
+
+// Fails if anything allocated through `alloc::heap` is still live.
+pub fn assert_no_leaks() { }
+
+// From here on, let up to `max` allocations fail (return null).
+pub fn allow_alloc_failures(max: usize) { }
+
+// Contract markers, inserted by rbmc for `#[crust_requires]` and
+// `#[crust_ensures]`.
+pub fn requires(cond: bool) { }
+pub fn ensures(cond: bool) { }
+
+// Loop annotations, recognized at the top of a loop body.  `invariant` is
+// checked on every iteration; `unwind` bounds the number of iterations.
+pub fn invariant(cond: bool) { }
+pub fn unwind(bound: usize) { }
+
 pub fn unreachable() -> ! {
     unsafe { core::intrinsics::abort() }
 }
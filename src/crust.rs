@@ -2,18 +2,23 @@
 #![crate_name = "__crust2"]
 #![feature(no_std)]
 #![feature(core)]
+#![feature(collections)]
 #![no_std]
 extern crate core;
+extern crate collections;
 extern crate __crust;
 
 use core::mem;
 use core::prelude::Sized;
 use core::ptr;
 use core::raw::Repr;
+use core::str::StrExt;
+use collections::vec::Vec;
 
 pub trait AsPtr {
     fn as_ptr_len(&self) -> (usize, usize);
     fn try_deref(&self);
+    fn align(&self) -> usize;
 }
 
 pub fn assert_not_null<T: AsPtr>(p: &T) {
@@ -30,6 +35,30 @@ pub fn assert_not_aliased<T: AsPtr, U: AsPtr>(p1: &T, p2: &U) {
     __crust::assert(p2 + l2 <= p1 || p1 + l1 <= p2);
 }
 
+pub fn assert_aligned<T: AsPtr>(p: &T) {
+    __crust::assert(p.as_ptr_len().0 % p.align() == 0);
+}
+
+// `len` bytes starting at `p` all lie within the allocation `p` points into.
+pub fn assert_in_allocation<T: AsPtr>(p: &T, len: usize) {
+    __crust::assert(__crust::in_allocation(p.as_ptr_len().0 as *const u8, len));
+}
+
+pub fn assert_same_allocation<T: AsPtr, U: AsPtr>(p1: &T, p2: &U) {
+    __crust::assert(__crust::same_allocation(p1.as_ptr_len().0 as *const u8,
+                                             p2.as_ptr_len().0 as *const u8));
+}
+
+// The precondition of `ptr::copy_nonoverlapping(src, dst, count)`.
+pub fn assert_nonoverlapping_copy<T>(src: *const T, dst: *mut T, count: usize) {
+    let len = count * mem::size_of::<T>();
+    let dst = dst as *const T;
+    assert_in_allocation(&src, len);
+    assert_in_allocation(&dst, len);
+    let (s, d) = (src as usize, dst as usize);
+    __crust::assert(s + len <= d || d + len <= s);
+}
+
 
 impl<'a, T: Sized> AsPtr for &'a T {
     fn as_ptr_len(&self) -> (usize, usize) {
@@ -41,6 +70,10 @@ impl<'a, T: Sized> AsPtr for &'a T {
             mem::forget(ptr::read(*self as *const T));
         }
     }
+
+    fn align(&self) -> usize {
+        mem::min_align_of::<T>()
+    }
 }
 
 impl<'a, T: Sized> AsPtr for &'a mut T {
@@ -53,6 +86,10 @@ impl<'a, T: Sized> AsPtr for &'a mut T {
             mem::forget(ptr::read(*self as *mut T as *const T));
         }
     }
+
+    fn align(&self) -> usize {
+        mem::min_align_of::<T>()
+    }
 }
 
 
@@ -72,6 +109,10 @@ impl<'a, T: Sized> AsPtr for &'a [T] {
             }
         }
     }
+
+    fn align(&self) -> usize {
+        mem::min_align_of::<T>()
+    }
 }
 
 impl<'a, T: Sized> AsPtr for &'a mut [T] {
@@ -89,4 +130,77 @@ impl<'a, T: Sized> AsPtr for &'a mut [T] {
             }
         }
     }
+
+    fn align(&self) -> usize {
+        mem::min_align_of::<T>()
+    }
+}
+
+impl<'a> AsPtr for &'a str {
+    fn as_ptr_len(&self) -> (usize, usize) {
+        self.as_bytes().as_ptr_len()
+    }
+
+    fn try_deref(&self) {
+        self.as_bytes().try_deref()
+    }
+
+    fn align(&self) -> usize {
+        1
+    }
+}
+
+impl<T: Sized> AsPtr for *const T {
+    fn as_ptr_len(&self) -> (usize, usize) {
+        (*self as usize, mem::size_of::<T>())
+    }
+
+    fn try_deref(&self) {
+        unsafe {
+            mem::forget(ptr::read(*self));
+        }
+    }
+
+    fn align(&self) -> usize {
+        mem::min_align_of::<T>()
+    }
+}
+
+impl<T: Sized> AsPtr for *mut T {
+    fn as_ptr_len(&self) -> (usize, usize) {
+        (*self as *const T as usize, mem::size_of::<T>())
+    }
+
+    fn try_deref(&self) {
+        unsafe {
+            mem::forget(ptr::read(*self as *const T));
+        }
+    }
+
+    fn align(&self) -> usize {
+        mem::min_align_of::<T>()
+    }
+}
+
+// The whole buffer, including spare capacity.  (`Box` has no impl: rbmc can't
+// translate boxes yet.)
+impl<T: Sized> AsPtr for Vec<T> {
+    fn as_ptr_len(&self) -> (usize, usize) {
+        (self.as_ptr() as usize, self.capacity() * mem::size_of::<T>())
+    }
+
+    fn try_deref(&self) {
+        let p = self.as_ptr();
+        let len = self.len();
+        unsafe {
+            if len > 0 {
+                mem::forget(ptr::read(p));
+                mem::forget(ptr::read(p.offset(len as isize - 1)));
+            }
+        }
+    }
+
+    fn align(&self) -> usize {
+        mem::min_align_of::<T>()
+    }
 }
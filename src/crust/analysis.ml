@@ -312,10 +312,15 @@ and walk_pattern t_bindings w_state patt =
   | `Addr_of p -> walk_pattern t_bindings w_state p
   | `Ref _ -> w_state
 and walk_fn w_state fn_name m_args  = 
+  match Env.redirect_fn fn_name m_args with
+  | Some (r_name,r_args) -> walk_fn w_state r_name r_args
+  | None ->
   if Intrinsics.is_intrinsic_fn fn_name then 
     add_fn_instance w_state (fn_name,m_args)
   else if  Intrinsics.is_crust_intrinsic fn_name then
     w_state
+  else if fn_name = "__crust$line" || fn_name = "__crust$panic" then
+    w_state
   else if fn_name = "drop_glue" then
     match m_args with
     | [t] -> begin
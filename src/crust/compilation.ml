@@ -61,6 +61,15 @@ let mangle_fn_name fn_name mono_args =
   | [] -> fn_name
   | _ -> fn_name ^ "_" ^ (String.concat "_" (List.map adt_type_name mono_args))
 
+(* `__crust$nondet` at a primitive type with invalid values calls the
+   constrained version in crust_intrinsics.h.  (Enums are covered by their
+   arbitrary-value constructors, which only pick existing variants.) *)
+let valid_nondet_fn fn_name (mono_args : Types.mono_type list) =
+  match fn_name,mono_args with
+  | "__crust$nondet",[`Char] -> Some "nondet_crust_char"
+  | "__crust$nondet",[`Bool] -> Some "nondet_crust_bool"
+  | _ -> None
+
 let rec type_to_string : c_types -> string = function
   | `Int n -> "rs_i" ^ Types.string_of_intsize n
   | `UInt n -> "rs_u" ^ Types.string_of_intsize n
@@ -76,6 +85,39 @@ let rec type_to_string : c_types -> string = function
   | `Fixed_Vec (n,t) -> "rs_f" ^ (string_of_int n) ^ (adt_type_name t)
 
 
+(* inverse of rbmc's str_ literal encoding: "str_" followed by hex bytes *)
+let decode_str_literal l =
+  let hex = String.sub l 4 (String.length l - 4) in
+  let b = Buffer.create (String.length hex / 2) in
+  for i = 0 to (String.length hex / 2) - 1 do
+    Buffer.add_char b (Char.chr (int_of_string ("0x" ^ String.sub hex (2 * i) 2)))
+  done;
+  Buffer.contents b
+
+(* exact C string literal contents, e.g. for a #line file name.  Octal
+   escapes are always three digits, so the next character can't extend them,
+   and `?` is escaped so no trigraph can form *)
+let c_octal_escape str =
+  let b = Buffer.create (String.length str) in
+  String.iter (fun c ->
+      match c with
+      | '"' | '\\' | '?' -> Buffer.add_char b '\\'; Buffer.add_char b c
+      | ' '..'~' -> Buffer.add_char b c
+      | _ -> Buffer.add_string b (Printf.sprintf "\\%03o" (Char.code c))
+    ) str;
+  Buffer.contents b
+
+(* for use inside a C string literal, e.g. an assertion description *)
+let c_string_escape str =
+  let b = Buffer.create (String.length str) in
+  String.iter (fun c ->
+      match c with
+      | '"' | '\\' -> Buffer.add_char b '\\'; Buffer.add_char b c
+      | ' '..'~' -> Buffer.add_char b c
+      | _ -> Buffer.add_char b '?'
+    ) str;
+  Buffer.contents b
+
 let string_of_binop : Ir.bin_op -> string = function
   | `BiAdd -> "+"
   | `BiSub -> "-"
@@ -218,6 +260,25 @@ class typedef_emitter buf =
     method private dump_fields : ((string * c_types) list) -> unit = List.iter self#dump_field_def
   end
 
+let panic_check = ref false;;
+let uninit_check = ref false;;
+let unwind = ref false;;
+
+(* The bound from the `__crust$unwind` marker at the top of a loop body, if
+   there is one.  Lifting the loop condition can wrap the body in another
+   block. *)
+let rec loop_unwind_bound ((_,body) : CRep.all_expr) =
+  match body with
+  | `Block (stmts,_) ->
+    let rec find = function
+      | `Expr (_,`Call ("__crust$unwind",_,_,[(_,`Literal n)])) :: _ -> Some n
+      | `Expr (_,`Call ("__crust$line",_,_,_)) :: rest -> find rest
+      | `Expr ((_,`Block _) as b) :: _ -> loop_unwind_bound b
+      | _ -> None
+    in
+    find stmts
+  | _ -> None
+
 class expr_emitter buf (t_bindings : (string * Types.mono_type) list) = 
   object (self)
     inherit Emit.emitter buf
@@ -237,13 +298,38 @@ class expr_emitter buf (t_bindings : (string * Types.mono_type) list) =
       | `Match (_,m_arm) ->
         self#put_many "else " self#dump_match m_arm
       | `While (cond,expr) ->
+        (* a loop with an unwinding bound counts its iterations, failing an
+           unwinding assertion and cutting off the path once it goes past
+           the bound *)
+        let bound = loop_unwind_bound expr in
+        (match bound with
+         | Some _ ->
+           self#open_block ();
+           self#put_i "rs_usize crust_unwind_iter = 0;";
+           self#newline ()
+         | None -> ());
         self#put_i "while(";
         self#dump_simple_expr @@ snd cond;
         self#put ") ";
         self#open_block ();
+        (match bound with
+         | Some n ->
+           self#put_i "crust_unwind_iter++;";
+           self#newline ();
+           self#put_i @@ Printf.sprintf
+               "__CPROVER_assert(crust_unwind_iter <= %s, \"unwinding bound\");" n;
+           self#newline ();
+           self#put_i @@ Printf.sprintf "__CPROVER_assume(crust_unwind_iter <= %s);" n;
+           self#newline ()
+         | None -> ());
         self#dump_expr expr;
         self#close_block ();
-        self#newline ()
+        self#newline ();
+        (match bound with
+         | Some _ ->
+           self#close_block ();
+           self#newline ()
+         | None -> ())
       | `Return (_,e) ->
         self#put_i "return ";
         self#dump_simple_expr e;
@@ -254,25 +340,47 @@ class expr_emitter buf (t_bindings : (string * Types.mono_type) list) =
       | (_,`Block _) as e -> self#dump_expr e
       | e -> self#dump_expr e;
         self#newline ~post:";" ()
+    method private is_raw_ptr t =
+      match TypeUtil.to_monomorph t_bindings t with
+      | `Ptr _ | `Ptr_Mut _ -> true
+      | _ -> false
+    (* With -uninit-check, reading a whole value through a raw pointer checks
+       that its bytes were initialized.  Places that are only written, or whose
+       address or a field is taken, go through dump_place instead. *)
+    method private dump_place = function
+      | `Deref (_,e) ->
+        self#put_i "(*";
+        self#dump_simple_expr e;
+        self#put_i ")"
+      | e -> self#dump_simple_expr e
     method dump_simple_expr = function
       | `Var s -> self#put_i s
       | `Literal l -> self#put_i l
-      | `Deref (_,e) -> 
-        self#put_i "(*";
+      | `Deref (t,e) when !uninit_check && self#is_raw_ptr t ->
+        self#put_i "(*CRUST_CHECKED_READ(";
         self#dump_simple_expr e;
-        self#put_i ")"
+        self#put_i "))"
+      | `Deref _ as d -> self#dump_place d
       | `Address_of (_,e) ->
         self#put_i "&";
-        self#dump_simple_expr e
+        self#dump_place e
       | `Assignment (lhs,(_,rhs)) ->
         self#put_i "((";
-        self#dump_simple_expr lhs;
+        (match lhs with
+         | `Deref (t,p) when !uninit_check && self#is_raw_ptr t ->
+           self#put_i "crust_mark_init(";
+           self#dump_simple_expr p;
+           self#put_i ", sizeof(*";
+           self#dump_simple_expr p;
+           self#put_i ")), "
+         | _ -> ());
+        self#dump_place lhs;
         self#put_i " = ";
         self#dump_simple_expr rhs;
         self#put_all [ "),"; CRep.literal_unit_name; ")" ]
       | `Assign_Op (op,e1,(_,e2)) ->
         self#put_i "((";
-        self#dump_simple_expr e1;
+        self#dump_place e1;
         self#put_all [ string_of_binop op; "=" ];
         self#dump_simple_expr e2;
         self#put_all [ ")," ; CRep.literal_unit_name; ")" ]
@@ -282,13 +390,30 @@ class expr_emitter buf (t_bindings : (string * Types.mono_type) list) =
         self#dump_simple_expr expr;
         self#put ")"
       | `Struct_Field (s,f) ->
-        self#dump_simple_expr s;
+        self#dump_place s;
         self#put_i ".";
         self#put f
       | `Call (fn_name,_,inst,args) ->
         let mono_args = List.map (TypeUtil.to_monomorph t_bindings) inst in
         let mangled_fname = mangle_fn_name fn_name mono_args in
-        if Intrinsics.is_intrinsic_fn fn_name then
+        if fn_name = "__crust$line" then
+          self#handle_line args
+        else if fn_name = "__crust$panic" then
+          self#handle_panic args
+        else if valid_nondet_fn fn_name mono_args <> None then begin
+          match valid_nondet_fn fn_name mono_args with
+          | Some v_name -> self#put_all [ v_name; "()" ]
+          | None -> assert false
+        end
+        else if Env.redirect_fn fn_name mono_args <> None then begin
+          match Env.redirect_fn fn_name mono_args with
+          | Some (r_name,r_args) ->
+            self#put_all [ mangle_fn_name r_name r_args; "(" ];
+            self#put_many ", " self#dump_args args;
+            self#put ")"
+          | None -> assert false
+        end
+        else if Intrinsics.is_intrinsic_fn fn_name then
           self#handle_intrinsic fn_name mangled_fname (List.map c_type_of_monomorph  mono_args) args
         else if Intrinsics.is_crust_intrinsic fn_name then
           let method_name = Intrinsics.intrinsic_name fn_name in
@@ -328,6 +453,39 @@ class expr_emitter buf (t_bindings : (string * Types.mono_type) list) =
         self#put @@ string_of_unop op;
         self#dump_simple_expr e;
         self#put ")"
+    method handle_line args =
+      match List.map snd args with
+      | [`Literal lo_line; `Literal lo_col; `Literal hi_line; `Literal hi_col; `Literal file] ->
+        (* the directive needs a line of its own, which C allows even in the
+           middle of an expression *)
+        let file = decode_str_literal file in
+        self#put_i "(";
+        self#newline ();
+        self#put @@ Printf.sprintf "#line %s \"%s\"" lo_line (c_octal_escape file);
+        self#newline ();
+        (* the file name is left out, since it could contain a `*/` *)
+        self#put_i @@ Printf.sprintf "/* %s:%s-%s:%s */ %s)"
+            lo_line lo_col hi_line hi_col CRep.literal_unit_name
+      | _ -> self#put_i CRep.literal_unit_name
+    method handle_panic args =
+      if !panic_check then begin
+        let desc = match List.map snd args with
+          | [`Literal msg; `Literal file; `Literal line] ->
+            Printf.sprintf "panic at %s:%s: %s"
+              (decode_str_literal file) line (decode_str_literal msg)
+          | _ -> "panic"
+        in
+        (* the panicking call that follows is then unreachable, so only this
+           assertion fails.  When unwinding, the call must still happen; it
+           skips its own assertion instead. *)
+        if !unwind then
+          self#put_i @@ Printf.sprintf "(__CPROVER_assert(0, \"%s\"), crust_panic_reported = 1, %s)"
+              (c_string_escape desc) CRep.literal_unit_name
+        else
+          self#put_i @@ Printf.sprintf "(__CPROVER_assert(0, \"%s\"), __CPROVER_assume(0), %s)"
+              (c_string_escape desc) CRep.literal_unit_name
+      end else
+        self#put_i CRep.literal_unit_name
     method handle_drop fn_name m_args args = 
       let arg_buf = Buffer.create 100 in
       self#put_i "(";
@@ -380,7 +538,13 @@ class expr_emitter buf (t_bindings : (string * Types.mono_type) list) =
         let type_string = type_to_string m_type in
         self#put_i @@ Printf.sprintf "%s %s = " type_string v_name;
         self#dump_expr (expr :> CRep.all_expr);
-        self#newline ~post:";" ()
+        self#newline ~post:";" ();
+        (match expr with
+         | (_,`Call (("core$intrinsics$uninit" | "core$mem$uninitialized"),_,_,_))
+           when !uninit_check ->
+           self#put_i @@ Printf.sprintf "crust_mark_uninit(&%s, sizeof(%s))" v_name v_name;
+           self#newline ~post:";" ()
+         | _ -> ())
       | `Declare (v_name,r_type) ->
         let m_type = to_monomorph_c_type t_bindings r_type in
         let type_string = type_to_string m_type in
@@ -551,7 +715,7 @@ let sig_of_fdef fn_def mono_args buf =
 let emit_fn_def out_channel buf (fn_name,mono_args) = 
   if fn_name = "crust_abort" then begin
     Buffer.add_string buf @@ type_to_string `Unit;
-    Buffer.add_string buf " crust_abort() { __CPROVER_assume(0); }\n";
+    Buffer.add_string buf " crust_abort() { return crust_panic(); }\n";
     Buffer.output_buffer out_channel buf;
     Buffer.clear buf
     end 
@@ -791,11 +955,22 @@ let emit out_channel t_set f_set statics =
   emit_common_typedefs out_channel;
   begin
     if !gcc_mode then begin
-      Printf.fprintf out_channel "#define assert(x)\n#define __CPROVER_assume(x) 0\n"
+      Printf.fprintf out_channel "#define assert(x)\n#define __CPROVER_assume(x) 0\n";
+      (* gcc only checks that the output compiles, so the shadow state in
+         crust_intrinsics.h and the checks on it just need to be valid C *)
+      Printf.fprintf out_channel "#define __CPROVER_assert(x, msg) 0\n";
+      Printf.fprintf out_channel "#define __CPROVER_bool rs_bool\n";
+      Printf.fprintf out_channel "#define __CPROVER_constant_infinity_uint 1\n";
+      Printf.fprintf out_channel "#define __CPROVER_POINTER_OBJECT(p) 0\n";
+      Printf.fprintf out_channel "#define __CPROVER_POINTER_OFFSET(p) 0\n";
+      Printf.fprintf out_channel "#define __CPROVER_OBJECT_SIZE(p) 0\n"
     end else ()
   end;
   dump_includes out_channel;
   Printf.fprintf out_channel "#define CRUST_MAX_MEM %d\n" !crust_mem_limit;
+  if !panic_check then Printf.fprintf out_channel "#define CRUST_PANIC_CHECK\n";
+  if !uninit_check then Printf.fprintf out_channel "#define CRUST_UNINIT_CHECK\n";
+  if !unwind then Printf.fprintf out_channel "#define CRUST_UNWIND\n";
   Printf.fprintf out_channel "%s %s;\n" (type_to_string `Unit) CRep.literal_unit_name;
   List.iter (emit_typedefs out_channel) t_list;
   (
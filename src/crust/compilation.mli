@@ -1,3 +1,6 @@
 val emit : out_channel -> Analysis.TISet.t -> Analysis.FISet.t -> SSet.t -> unit
 val crust_mem_limit : int ref
 val gcc_mode : bool ref
+val panic_check : bool ref
+val uninit_check : bool ref
+val unwind : bool ref
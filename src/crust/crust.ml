@@ -81,11 +81,15 @@ let do_it f =
     ("-test-compile", mk_set `Test_Compile, "Generate C test cases");
     ("-mut-length", Arg.Set_int RustGen.mut_action_len, "Generate up to n mutative calls");
     ("-immut-length", Arg.Set_int RustGen.immut_action_len, "Generate up to n immutable calls");
+    ("-leak-check", Arg.Set RustGen.leak_check, "End each generated driver by asserting that no allocations are live");
     ("-no-assume-ident", Arg.Set RustGen.assume_ident_init, "Do not assume that values returned from crust_init are interchangeable");
     ("-o", Arg.String (fun f_name ->
          output_channel := open_out f_name
        ), "Output to file");
     ("-max-memory", Arg.Set_int Compilation.crust_mem_limit, "Maximum bound of memory that can be allocated");
+    ("-uninit-check", Arg.Set Compilation.uninit_check, "Track which heap bytes are initialized and check reads through raw pointers");
+    ("-panic-check", Arg.Set Compilation.panic_check, "Report reachable panics as assertion failures instead of ignoring those paths");
+    ("-unwind", Arg.Set Compilation.unwind, "Let panics unwind through the cleanup added by Preprocess --unwind");
     ("-test-size", Arg.Set_int test_chunk_size, "Number of test cases per file");
     ("-test-case-prefix", Arg.Set_string test_output_file, "Prefix for test case files generated in -driver-gen");
     ("-dump-api", mk_set `Dump_Api, "Show the inferred public api and quit");
@@ -21,6 +21,34 @@ let driver_env = ref [];;
 
 let is_abstract name = Hashtbl.mem abstract_impl name
 
+(* rbmc emits an arbitrary-value constructor after each struct and enum.
+   `__crust$nondet` at one of those types calls it instead. *)
+let arbitrary_fn fn_name (m_args : Types.mono_type list) =
+  match fn_name,m_args with
+  | "__crust$nondet",[`Adt_type { Types.type_name = t_name; Types.type_param = t_params; _ }] ->
+    let a_name = t_name ^ "$__crust_arbitrary" in
+    if Hashtbl.mem fn_env a_name then Some (a_name,t_params) else None
+  | _ -> None
+
+(* Helpers in the `__crust` stub crate may be modeled in Rust, by a function of
+   the same name in `__crust_models` (src/crust-models.rs). *)
+let model_fn fn_name =
+  let prefix = "__crust$" in
+  let p_len = String.length prefix in
+  if String.length fn_name > p_len && String.sub fn_name 0 p_len = prefix then
+    let m_name = "__crust_models$" ^ String.sub fn_name p_len (String.length fn_name - p_len) in
+    if Hashtbl.mem fn_env m_name then Some m_name else None
+  else None
+
+(* The function to call in place of `fn_name`, if any *)
+let redirect_fn fn_name m_args =
+  match arbitrary_fn fn_name m_args with
+  | Some r -> Some r
+  | None ->
+    match model_fn fn_name with
+    | Some m_name -> Some (m_name,m_args)
+    | None -> None
+
 let rec set_env = function 
   | [] -> ()
   | ((`Enum_def {
@@ -20,6 +20,7 @@ val set_env : Ir.module_expr list -> unit
 val get_adt_drop : string -> string option
 val is_abstract_fn : string -> bool
 val is_static_var : string -> bool
+val redirect_fn : string -> Types.mono_type list -> (string * Types.mono_type list) option
 
 val associated_types : Types.associated_type list EnvMap.t
 
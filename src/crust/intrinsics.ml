@@ -101,17 +101,94 @@ let i_list = arith_intrinsics @ [
     i_params = [];
     i_body = Inline ("(__CPROVER_assert({arg1}, \"{arg1}\")," ^ CRep.literal_unit_name ^ ")")
   };
+  {
+    i_name = "__crust$invariant";
+    i_params = [];
+    i_body = Inline ("(__CPROVER_assert({arg1}, \"loop invariant\")," ^ CRep.literal_unit_name ^ ")")
+  };
+  {
+    (* the bound itself is applied where the loop is emitted *)
+    i_name = "__crust$unwind";
+    i_params = [];
+    i_body = Inline CRep.literal_unit_name
+  };
+  {
+    i_name = "__crust$requires";
+    i_params = [];
+    i_body = Inline ("crust_requires({arg1})")
+  };
+  {
+    i_name = "__crust$ensures";
+    i_params = [];
+    i_body = Inline ("crust_ensures({arg1})")
+  };
   {
     i_name = "__crust$nondet";
     i_params = [ "t1" ];
     i_body = Template ("{t1} {mname}();")
   };
+  {
+    i_name = "__crust$nondet_in_range";
+    i_params = [ "t1" ];
+    i_body = Template ("{t1} {mname}({t1} lo, {t1} hi) {\n" ^
+                       "\t {t1} to_ret;\n" ^
+                       "\t __CPROVER_assume(lo <= to_ret && to_ret < hi);\n" ^
+                       "\t return to_ret;\n" ^
+                       "}")
+  };
+  {
+    (* malloc'd memory is already nondeterministic *)
+    i_name = "__crust$nondet_buffer";
+    i_params = [ "t1" ];
+    i_body = Template ("{t1}* {mname}(rs_usize len) {\n" ^
+                       "\t if(len == 0 || sizeof({t1}) == 0) { return ({t1}*)1; }\n" ^
+                       "\t __CPROVER_assume(len * sizeof({t1}) < CRUST_MAX_MEM);\n" ^
+                       "\t return ({t1}*)malloc(len * sizeof({t1}));\n" ^
+                       "}")
+  };
+  {
+    i_name = "__crust$assume_utf8";
+    i_params = [];
+    i_body = Inline ("crust_assume_utf8(({arg1}).field0, ({arg1}).field1)")
+  };
+  {
+    i_name = "__crust$in_allocation";
+    i_params = [];
+    i_body = Inline "crust_in_allocation({arg1}, {arg2})"
+  };
+  {
+    i_name = "__crust$same_allocation";
+    i_params = [];
+    i_body = Inline "crust_same_allocation({arg1}, {arg2})"
+  };
+  (* Borrow-stack events, from `rbmc --crust-borrow-model` *)
+  {
+    i_name = "__crust$sb_push";
+    i_params = [ "t1" ];
+    i_body = Inline "crust_sb_push({arg1}, sizeof({t1}), {arg2})"
+  };
+  {
+    i_name = "__crust$sb_retag";
+    i_params = [ "t1" ];
+    i_body = Inline "crust_sb_retag({arg1}, sizeof({t1}), {arg2}, {arg3})"
+  };
+  {
+    i_name = "__crust$sb_access";
+    i_params = [ "t1" ];
+    i_body = Inline "crust_sb_access({arg1}, sizeof({t1}), {arg2}, {arg3})"
+  };
+  {
+    i_name = "__crust$assert_disjoint";
+    i_params = [ "t1"; "u1" ];
+    i_body = Inline "crust_assert_disjoint({arg1}, sizeof({t1}), {arg2}, sizeof({u1}))"
+  };
   {
     i_name = "core$intrinsics$write_bytes";
     i_params = [ "t1" ];
     i_body = Template (
         "rs_unit {mname}({t1} * dst, rs_u8 val, rs_usize count) {\n" ^
         "\t memset(dst, val, count * sizeof({t1}));\n" ^
+        "\t crust_mark_init(dst, count * sizeof({t1}));\n" ^
         "\t return UNIT;\n" ^
         "}")
   };
@@ -162,7 +239,7 @@ let i_list = arith_intrinsics @ [
   {
     i_name = "core$intrinsics$offset";
     i_params = ["t1"];
-    i_body = Inline "({arg1} + {arg2})"
+    i_body = Inline "CRUST_OFFSET({arg1}, {arg2})"
   };
   {
     i_name = "core$intrinsics$assume";
@@ -176,10 +253,26 @@ let i_list = arith_intrinsics @ [
     i_params = [];
     i_body = Inline ("(__CPROVER_assume(0)," ^ CRep.literal_unit_name ^ ")")
   };
+  (* Unwinding, from `Preprocess --unwind` *)
+  {
+    i_name = "__crust$unwinding";
+    i_params = [];
+    i_body = Inline "crust_unwinding"
+  };
+  {
+    i_name = "__crust$begin_cleanup";
+    i_params = [];
+    i_body = Inline "crust_begin_cleanup()"
+  };
+  {
+    i_name = "__crust$resume_unwind";
+    i_params = [];
+    i_body = Inline "crust_resume_unwind()"
+  };
   {
     i_name = "core$panicking$panic";
     i_params = [];
-    i_body = Inline ("(__CPROVER_assume(0)," ^ CRep.literal_unit_name ^ ")")
+    i_body = Inline "crust_panic()"
   };
 (*  {
     i_name = "core$intrinsics$move_val_init";
@@ -195,6 +288,7 @@ let i_list = arith_intrinsics @ [
     i_body = Template ("rs_unit {mname}({t1}* dst, {t1} src) {\n" ^
                        "\t if(sizeof({t1}) == 0) { return; } \n" ^
                        "\t *dst = src;\n" ^
+                       "\t crust_mark_init(dst, sizeof({t1}));\n" ^
                        "\t return " ^ CRep.literal_unit_name ^ ";\n" ^
                        "}"
                       )
@@ -213,18 +307,21 @@ let i_list = arith_intrinsics @ [
   {
     i_name = "core$intrinsics$copy";
     i_params = [ "t1" ];
-    i_body = Inline ("(memcpy({arg2}, {arg1}, {arg3} * sizeof({t1}))," ^ CRep.literal_unit_name ^ ")")
+    i_body = Inline ("(memcpy({arg2}, {arg1}, {arg3} * sizeof({t1}))," ^
+                     "crust_copy_init({arg2}, {arg1}, {arg3} * sizeof({t1})))")
   };
   {
     i_name = "core$ptr$read";
     i_params = [ "t1" ];
-    i_body = Template ("{t1} {mname}({t1} const *ptr) { {t1} to_ret; if(sizeof({t1}) == 0) { return to_ret;} to_ret = *ptr; return to_ret; }")
+    i_body = Template ("{t1} {mname}({t1} const *ptr) { {t1} to_ret; if(sizeof({t1}) == 0) { return to_ret;} " ^
+                       "crust_check_init(ptr, sizeof({t1})); to_ret = *ptr; return to_ret; }")
   };
   {
     i_name = "core$intrinsics$copy_nonoverlapping";
     i_params = [ "t1" ];
     i_body = Template ("rs_unit {mname}(const {t1}* src_r, {t1}* dst_r, size_t n_elem) {\n" ^
                        "\t size_t n = 0;\n" ^
+                       "\t crust_check_copy(src_r, dst_r, n_elem * sizeof({t1}));\n" ^
                        "\t rs_u8 *dst = (rs_u8* )dst_r;\n" ^
                        "\t rs_u8 *src = (rs_u8* )src_r;\n" ^
                        "\t size_t bounds = CRUST_MAX_MEM * sizeof({t1});\n" ^
@@ -234,9 +331,49 @@ let i_list = arith_intrinsics @ [
                        "\t\t *(dst + n) = *(src + n);\n" ^
                        "\t\t n_iter++;\n" ^
                        "\t }\n" ^
+                       "\t crust_copy_init(dst_r, src_r, n_elem * sizeof({t1}));\n" ^
                        "\t return "^ CRep.literal_unit_name ^ ";\n" ^
                        "}")
   };
+  (* The Rust allocator goes through the shadow allocator in
+     crust_intrinsics.h, which checks that every deallocation matches a live
+     allocation. *)
+  {
+    i_name = "alloc$heap$allocate";
+    i_params = [];
+    i_body = Template "rs_u8* {mname}(rs_usize size, rs_usize align) { return crust_allocate(size, align); }"
+  };
+  {
+    i_name = "alloc$heap$deallocate";
+    i_params = [];
+    i_body = Template ("rs_unit {mname}(rs_u8* ptr, rs_usize old_size, rs_usize align) {\n" ^
+                       "\t return crust_deallocate(ptr, old_size, align);\n" ^
+                       "}")
+  };
+  {
+    i_name = "alloc$heap$reallocate";
+    i_params = [];
+    i_body = Template ("rs_u8* {mname}(rs_u8* ptr, rs_usize old_size, rs_usize size, rs_usize align) {\n" ^
+                       "\t return crust_reallocate(ptr, old_size, size, align);\n" ^
+                       "}")
+  };
+  {
+    i_name = "alloc$heap$reallocate_inplace";
+    i_params = [];
+    i_body = Template ("rs_usize {mname}(rs_u8* ptr, rs_usize old_size, rs_usize size, rs_usize align) {\n" ^
+                       "\t return crust_reallocate_inplace(ptr, old_size, size, align);\n" ^
+                       "}")
+  };
+  {
+    i_name = "__crust$allow_alloc_failures";
+    i_params = [];
+    i_body = Inline "crust_allow_alloc_failures({arg1})"
+  };
+  {
+    i_name = "__crust$assert_no_leaks";
+    i_params = [];
+    i_body = Inline "crust_assert_no_leaks()"
+  };
   {
     i_name = "libc$funcs$c95$stdlib$free";
     i_params = [];
@@ -249,8 +386,10 @@ let i_list = arith_intrinsics @ [
         "int {mname}(libc$types$common$c95$c_void **out_ptr, size_t _align, size_t size) {\n" ^
         "\t assert(size != 0);\n" ^
         "\t __CPROVER_assume(size < CRUST_MAX_MEM);\n" ^
+        "\t if(crust_alloc_fails()) { return 1; }\n" ^
         "\t libc$types$common$c95$c_void* ret = (libc$types$common$c95$c_void*)malloc(size);\n" ^
         "\t if(ret == NULL) { return 1; }\n" ^
+        "\t crust_mark_uninit(ret, size);\n" ^
         "\t *out_ptr = ret;\n" ^
         "\t return 0;\n" ^
         "}"
@@ -261,7 +400,10 @@ let i_list = arith_intrinsics @ [
     i_params = [];
     i_body = Template ("void *{mname}(size_t to_alloc) {\n" ^
                        "\t __CPROVER_assume(to_alloc < CRUST_MAX_MEM);\n" ^
-                       "\t return malloc(to_alloc);\n" ^
+                       "\t if(crust_alloc_fails()) { return NULL; }\n" ^
+                       "\t void *ret = malloc(to_alloc);\n" ^
+                       "\t crust_mark_uninit(ret, to_alloc);\n" ^
+                       "\t return ret;\n" ^
                        "}"
                       )
   };
@@ -271,6 +413,7 @@ let i_list = arith_intrinsics @ [
     i_body = Template (
         "rs_u8* {mname}(libc$types$common$c95$c_void* ptr, size_t new_size) {\n" ^
         "    __CPROVER_assume(new_size < CRUST_MAX_MEM);\n" ^
+        "    if(crust_alloc_fails()) { return NULL; }\n" ^
         "    if(ptr == NULL) {\n" ^
         "        return malloc(new_size);\n" ^
         "    } else {\n" ^
@@ -104,6 +104,7 @@ type struct_def = {
 	s_lifetime_param : Types.lifetime list;
 	s_tparam : Types.type_param list;
 	struct_fields : (string * Types.r_type) list;
+	field_vis : visibility list;
 	drop_fn : string option;
   }
 
@@ -364,16 +364,18 @@ let parse_fn =
         )
     | _ -> raise (Parse_failure ("parse_impl", tokens))
   in
-  let vis_of_string tok tokens = 
-    match tok with
-    | "pub" -> `Public
-    | "priv" -> `Private
+  (* a pub fn that is not reachable from outside the crate is not API *)
+  let vis_of_string tok export tokens = 
+    match tok,export with
+    | "pub","exported" -> `Public
+    | "pub","internal"
+    | "priv",_ -> `Private
     | _ -> raise @@ Parse_failure ("vis_of_string", tokens)
   in
   fun tokens cb ->
     arg_counter := 0;
     match tokens with
-    | "fn"::vis::fn_name::t ->
+    | "fn"::vis::export::fn_name::t ->
 	 let parse_function = parse_lifetimes >> parse_type_params >> parse_args >>
              parse_return >> (maybe_parse parse_impl) >> parse_preds >> parse_body in
 	 parse_function t (fun ((((((lifetime,t_params),args),ret_type),impl_info),_preds),body) ->
@@ -385,7 +387,7 @@ let parse_fn =
 						 Ir.fn_args = args;
 						 Ir.fn_body = body;
 						 Ir.fn_impl = impl_info;
-						 Ir.fn_vis = vis_of_string vis tokens (* second arg is for errors *)
+						 Ir.fn_vis = vis_of_string vis export tokens (* second arg is for errors *)
 					   })
 	)
     | "abstract_fn"::fn_name::t ->
@@ -400,15 +402,21 @@ let parse_fn =
         }))
     | _ -> raise (Parse_failure ("parse_fn", tokens))
 
+let parse_visibility tokens cb = match tokens with
+  | "pub"::t -> cb `Public t
+  | "priv"::t -> cb `Private t
+  | _ -> raise (Parse_failure ("parse_visibility",tokens))
+
 let parse_struct_def tokens cb = match tokens with
   | "struct"::name::t ->
-	 let p_fun = (parse_lifetimes >> parse_type_params >> (parse_n (consume_name >> parse_type)) >> (maybe_parse consume_name)) in
+	 let p_fun = (parse_lifetimes >> parse_type_params >> (parse_n (parse_visibility >> consume_name >> parse_type)) >> (maybe_parse consume_name)) in
 	 p_fun t (fun (((lifetimes,t_params),struct_fields),drop_fn) rest ->
 			  cb (`Struct_def {
 				Ir.struct_name = name;
 				Ir.s_lifetime_param = lifetimes;
 				Ir.s_tparam = t_params;
-				Ir.struct_fields = struct_fields;
+				Ir.struct_fields = List.map (fun ((_,f_name),f_type) -> (f_name,f_type)) struct_fields;
+				Ir.field_vis = List.map (fun ((vis,_),_) -> vis) struct_fields;
 				Ir.drop_fn = drop_fn
 			  }) rest
 			 )
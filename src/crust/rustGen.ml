@@ -6,6 +6,8 @@ let skip_interfere_check = ref false;;
 let skip_symm_break = ref false;;
 let skip_interesting_check = ref false;;
 let skip_copy_use = ref false;;
+(* end each driver by checking that everything it allocated was freed *)
+let leak_check = ref false;;
 
 module MTSet = TypeUtil.MTSet
 
@@ -405,6 +407,7 @@ class rust_pp buf output_file_prefix num_tests = object(self)
     let test_name = fresh_name () in
     self#put_all [ "fn "; test_name; "() "];
     self#open_block ();
+    if !leak_check then self#open_block ();
     let init_name = rust_name @@ Env.crust_init_name_e () in
     let put_args () = 
       self#put_all [ init_name; "(" ];
@@ -454,6 +457,13 @@ class rust_pp buf output_file_prefix num_tests = object(self)
       self#newline ();
       self#close_block ()
     done;
+    if !leak_check then begin
+      (* after the block, so everything in it has been dropped *)
+      self#newline ();
+      self#close_block ();
+      self#newline ();
+      self#put "__crust::assert_no_leaks();"
+    end;
     self#newline ();
     self#close_block ();
     self#newline ()
@@ -461,7 +471,12 @@ class rust_pp buf output_file_prefix num_tests = object(self)
   method emit_blocks bs =
     List.iteri (fun i b ->
       self#put (Printf.sprintf "fn __crust_test_%d() {\n" i);
-      self#emit_expr b;
+      if !leak_check then begin
+        self#put "{\n";
+        self#emit_expr b;
+        self#put ";\n}\n__crust::assert_no_leaks()";
+      end else
+        self#emit_expr b;
       self#put ";\n}\n\n";
     ) bs
 
@@ -532,7 +547,28 @@ class rust_pp buf output_file_prefix num_tests = object(self)
         | `UInt _ -> self#put lit;
         | `Int _ -> self#put lit;
         | _ -> raise (Unexpected "lit type variant"))
-    (* `Struct_Literal *)
+    | `Struct_Literal fields -> begin
+        (match fst e with
+        | `Adt_type { Types.type_name = t_name; _ } -> self#put (unmangle_name t_name)
+        | _ -> raise (Unexpected "ty for struct literal"));
+        let is_tuple_field (name, _) =
+          String.length name > 5 && Str.string_before name 5 = "field"
+        in
+        if fields = [] then ()
+        else if List.for_all is_tuple_field fields then begin
+          self#put "(";
+          self#put_many ", " (fun (_, e') -> self#emit_expr e') fields;
+          self#put ")";
+        end else begin
+          self#put " { ";
+          self#put_many ", " (fun (name, e') ->
+              self#put name;
+              self#put ": ";
+              self#emit_expr e'
+            ) fields;
+          self#put " }";
+        end
+      end
     (* `Enum_Literal *)
     | `Match (expr, arms) -> begin
         self#put "match ";
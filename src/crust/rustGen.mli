@@ -6,6 +6,7 @@ val skip_interesting_check : bool ref
 val skip_symm_break : bool ref
 val skip_interfere_check : bool ref
 val skip_copy_use : bool ref
+val leak_check : bool ref
 
 val mut_action_len : int ref
 val immut_action_len : int ref
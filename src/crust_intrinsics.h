@@ -10,14 +10,397 @@ rs_i32 nondet_crust_i32();
 rs_i64 nondet_crust_i64();
 rs_isize nondet_crust_isize();
 
-rs_char nondet_crust_char();
+/* Unlike the integers, these types have invalid bit patterns, which safe Rust
+ * can never produce; rule them out.  An uninitialized local is nondet. */
+rs_char nondet_crust_char() {
+  rs_char c;
+  __CPROVER_assume(c <= 0x10ffff && (c < 0xd800 || c > 0xdfff));
+  return c;
+}
 
-rs_bool nondet_crust_bool();
+rs_bool nondet_crust_bool() {
+  rs_bool b;
+  __CPROVER_assume(b <= 1);
+  return b;
+}
 
 rs_f32 nondet_crust_f32();
 rs_f64 nondet_crust_f64();
 
+/* Where a panic can't be traced back to its message, e.g. `crust_abort`.
+ * With CRUST_PANIC_CHECK (crust -panic-check) reaching it is an error;
+ * otherwise the path is silently dropped.
+ *
+ * With CRUST_UNWIND (crust -unwind), the panic unwinds instead: it sets
+ * `crust_unwinding` and returns, and the checks Preprocess --unwind adds after
+ * each call drop the live locals and return to the caller.  Drops run with
+ * the flag clear; a panic during them aborts, which is an error. */
+rs_bool crust_unwinding = 0;
+int crust_cleanup_depth = 0;
+/* set by a traced panic, which has already asserted */
+int crust_panic_reported = 0;
+
+rs_unit crust_panic(void) {
+#ifdef CRUST_UNWIND
+  __CPROVER_assert(crust_cleanup_depth == 0, "panic while unwinding");
+  __CPROVER_assume(crust_cleanup_depth == 0);
+#endif
+#ifdef CRUST_PANIC_CHECK
+  __CPROVER_assert(crust_panic_reported, "panic");
+#endif
+  crust_panic_reported = 0;
+#ifdef CRUST_UNWIND
+  crust_unwinding = 1;
+#else
+  __CPROVER_assume(0);
+#endif
+  return UNIT;
+}
+
+rs_unit crust_begin_cleanup(void) {
+  crust_unwinding = 0;
+  crust_cleanup_depth++;
+  return UNIT;
+}
+
+rs_unit crust_resume_unwind(void) {
+  crust_cleanup_depth--;
+  crust_unwinding = 1;
+  return UNIT;
+}
+
 rs_unit crust_assert(int cond) {
   assert(cond);
   return UNIT;
 }
+
+/* Restricts p[0..len] to well-formed UTF-8: no stray continuation bytes,
+ * overlong encodings, surrogates or code points above U+10FFFF.  Each byte is
+ * constrained on its own by a quantifier, so there is no loop to unwind:
+ * a lead byte must be followed by the right number of continuation bytes
+ * (with a narrower range for the first one after E0, ED, F0 and F4), and a
+ * continuation byte must be within reach of a lead byte before it. */
+#define CRUST_UTF8_CONT(b) (((b) & 0xc0) == 0x80)
+#define CRUST_UTF8_TAIL(b) ((b) >= 0xf0 ? 3 : (b) >= 0xe0 ? 2 : (b) >= 0xc0 ? 1 : 0)
+#define CRUST_UTF8_LO(b) ((b) == 0xe0 ? 0xa0 : (b) == 0xf0 ? 0x90 : 0x80)
+#define CRUST_UTF8_HI(b) ((b) == 0xed ? 0x9f : (b) == 0xf4 ? 0x8f : 0xbf)
+#define CRUST_UTF8_OK_AT(p, len, i) \
+  ((p)[i] != 0xc0 && (p)[i] != 0xc1 && (p)[i] < 0xf5 && \
+   (i) + CRUST_UTF8_TAIL((p)[i]) < (len) && \
+   (CRUST_UTF8_TAIL((p)[i]) < 1 || \
+    ((p)[(i) + 1] >= CRUST_UTF8_LO((p)[i]) && (p)[(i) + 1] <= CRUST_UTF8_HI((p)[i]))) && \
+   (CRUST_UTF8_TAIL((p)[i]) < 2 || CRUST_UTF8_CONT((p)[(i) + 2])) && \
+   (CRUST_UTF8_TAIL((p)[i]) < 3 || CRUST_UTF8_CONT((p)[(i) + 3])) && \
+   (!CRUST_UTF8_CONT((p)[i]) || \
+    ((i) >= 1 && CRUST_UTF8_TAIL((p)[(i) - 1]) >= 1) || \
+    ((i) >= 2 && CRUST_UTF8_TAIL((p)[(i) - 2]) >= 2) || \
+    ((i) >= 3 && CRUST_UTF8_TAIL((p)[(i) - 3]) >= 3)))
+
+rs_unit crust_assume_utf8(const rs_u8 *p, rs_usize len) {
+  __CPROVER_assume(__CPROVER_forall { rs_usize i; (i < len) ==> CRUST_UTF8_OK_AT(p, len, i) });
+  return UNIT;
+}
+
+/* Function contracts.  The outermost call with a contract is the function
+ * being checked, so its precondition is assumed; the preconditions of calls
+ * it makes are obligations on it, and are asserted. */
+int crust_contract_depth = 0;
+
+rs_unit crust_requires(int cond) {
+  if (crust_contract_depth++ == 0) {
+    __CPROVER_assume(cond);
+  } else {
+    assert(cond);
+  }
+  return UNIT;
+}
+
+rs_unit crust_ensures(int cond) {
+  assert(cond);
+  crust_contract_depth--;
+  return UNIT;
+}
+
+/* Pointer predicates, for the `AsPtr` checks in `__crust2` and for checking
+ * `ptr::offset` and `copy_nonoverlapping` calls.  A zero-length range is
+ * always fine, so dangling pointers to empty buffers pass. */
+int crust_in_allocation(const void *p, rs_usize len) {
+  return len == 0 ||
+    (__CPROVER_POINTER_OFFSET(p) >= 0 &&
+     __CPROVER_POINTER_OFFSET(p) + len <= __CPROVER_OBJECT_SIZE(p));
+}
+
+int crust_same_allocation(const void *p, const void *q) {
+  return __CPROVER_POINTER_OBJECT(p) == __CPROVER_POINTER_OBJECT(q);
+}
+
+rs_unit crust_check_copy(const void *src, const void *dst, rs_usize len) {
+  __CPROVER_assert(crust_in_allocation(src, len), "copy_nonoverlapping: source out of bounds");
+  __CPROVER_assert(crust_in_allocation(dst, len), "copy_nonoverlapping: destination out of bounds");
+  __CPROVER_assert(len == 0 || !crust_same_allocation(src, dst) ||
+                   __CPROVER_POINTER_OFFSET(src) + len <= __CPROVER_POINTER_OFFSET(dst) ||
+                   __CPROVER_POINTER_OFFSET(dst) + len <= __CPROVER_POINTER_OFFSET(src),
+                   "copy_nonoverlapping: ranges overlap");
+  return UNIT;
+}
+
+/* Checks emitted by `rbmc --crust-alias-checks`: a new `&mut` (p) must not
+ * overlap a reference argument (q). */
+rs_unit crust_assert_disjoint(const void *p, rs_usize p_len, const void *q, rs_usize q_len) {
+  __CPROVER_assert(p_len == 0 || q_len == 0 || !crust_same_allocation(p, q) ||
+                   __CPROVER_POINTER_OFFSET(p) + p_len <= __CPROVER_POINTER_OFFSET(q) ||
+                   __CPROVER_POINTER_OFFSET(q) + q_len <= __CPROVER_POINTER_OFFSET(p),
+                   "&mut aliases a reference argument");
+  return UNIT;
+}
+
+/* `p` advanced by `n` elements of `size` bytes.  The result may point one
+ * past the end, but no further. */
+void *crust_offset(const void *p, rs_isize n, rs_usize size) {
+  rs_isize bytes = n * (rs_isize)size;
+  if (bytes != 0) {
+    rs_isize off = __CPROVER_POINTER_OFFSET(p) + bytes;
+    __CPROVER_assert(off >= 0 && off <= (rs_isize)__CPROVER_OBJECT_SIZE(p),
+                     "ptr::offset out of bounds");
+  }
+  return (char *)p + bytes;
+}
+
+/* `__typeof__` and `sizeof` don't evaluate `p`, so each argument is evaluated
+ * once. */
+#define CRUST_OFFSET(p, n) \
+  ((__typeof__(p))crust_offset((p), (rs_isize)(n), sizeof(*(p))))
+
+/* Shadow "uninitialized" bits, one per byte, indexed by CBMC object and
+ * offset.  Memory counts as initialized unless marked.  With
+ * CRUST_UNINIT_CHECK (crust -uninit-check), fresh allocations and
+ * `mem::uninitialized` locals are marked, writes clear the marks, copies carry
+ * them along, and reading a value asserts that none of its bytes are marked.
+ * Otherwise all of this compiles to nothing. */
+__CPROVER_bool crust_uninit[__CPROVER_constant_infinity_uint];
+
+#define CRUST_SHADOW_INDEX(p) \
+  ((((rs_u64)__CPROVER_POINTER_OBJECT(p)) << 32) + __CPROVER_POINTER_OFFSET(p))
+
+rs_unit crust_set_uninit(const void *p, rs_usize n, __CPROVER_bool uninit) {
+#ifdef CRUST_UNINIT_CHECK
+  const rs_u8 *b = p;
+  rs_usize i;
+  for (i = 0; i < n; i++) {
+    crust_uninit[CRUST_SHADOW_INDEX(b + i)] = uninit;
+  }
+#endif
+  return UNIT;
+}
+
+#define crust_mark_uninit(p, n) crust_set_uninit((p), (n), 1)
+#define crust_mark_init(p, n) crust_set_uninit((p), (n), 0)
+
+/* memmove semantics, so it also suits `copy` */
+rs_unit crust_copy_init(void *dst, const void *src, rs_usize n) {
+#ifdef CRUST_UNINIT_CHECK
+  rs_u8 *d = dst;
+  const rs_u8 *s = src;
+  rs_usize i;
+  if (__CPROVER_POINTER_OBJECT(d) == __CPROVER_POINTER_OBJECT(s) &&
+      __CPROVER_POINTER_OFFSET(d) > __CPROVER_POINTER_OFFSET(s)) {
+    for (i = n; i > 0; i--) {
+      crust_uninit[CRUST_SHADOW_INDEX(d + i - 1)] = crust_uninit[CRUST_SHADOW_INDEX(s + i - 1)];
+    }
+  } else {
+    for (i = 0; i < n; i++) {
+      crust_uninit[CRUST_SHADOW_INDEX(d + i)] = crust_uninit[CRUST_SHADOW_INDEX(s + i)];
+    }
+  }
+#endif
+  return UNIT;
+}
+
+rs_unit crust_check_init(const void *p, rs_usize n) {
+#ifdef CRUST_UNINIT_CHECK
+  const rs_u8 *b = p;
+  rs_usize i;
+  for (i = 0; i < n; i++) {
+    __CPROVER_assert(!crust_uninit[CRUST_SHADOW_INDEX(b + i)], "read of uninitialized memory");
+  }
+#endif
+  return UNIT;
+}
+
+/* `*p` as a value, checked first; crust emits this for raw pointer reads */
+#define CRUST_CHECKED_READ(p) (crust_check_init((p), sizeof(*(p))), (p))
+
+/* Borrow stacks for `rbmc --crust-borrow-model` (src/borrows.rs), in the
+ * style of Stacked Borrows.  Each location (a CBMC object and offset; a value
+ * is tracked by its first byte) has a stack of up to CRUST_SB_DEPTH borrows
+ * above its owner, whose tag is 0.  An access through a tag pops the borrows
+ * above it; a read only pops from the first unique borrow up.  Accessing
+ * through a tag that was already popped, or writing through a shared
+ * reference, fails.  Pushing onto a full stack hands out an untracked tag,
+ * which never fails. */
+#ifndef CRUST_SB_DEPTH
+#define CRUST_SB_DEPTH 4
+#endif
+#define CRUST_SB_UNIQUE 0
+#define CRUST_SB_SHARED_RO 1
+#define CRUST_SB_SHARED_RW 2
+#define CRUST_SB_UNTRACKED ((rs_usize)-1)
+
+rs_usize crust_sb_len[__CPROVER_constant_infinity_uint];
+rs_usize crust_sb_tag[__CPROVER_constant_infinity_uint];
+rs_usize crust_sb_kind[__CPROVER_constant_infinity_uint];
+rs_usize crust_sb_next_tag = 0;
+
+#define CRUST_SB_SLOT(loc, i) ((loc) * CRUST_SB_DEPTH + (i))
+
+rs_unit crust_sb_access(const void *p, rs_usize size, rs_usize tag, rs_bool write) {
+  rs_u64 loc;
+  rs_usize i, n, found;
+  if (size == 0 || tag == CRUST_SB_UNTRACKED) return UNIT;
+  loc = CRUST_SHADOW_INDEX(p);
+  n = crust_sb_len[loc];
+  /* the number of entries up to and including the tag's */
+  found = 0;
+  if (tag != 0) {
+    found = CRUST_SB_DEPTH + 1;
+    for (i = 0; i < CRUST_SB_DEPTH; i++) {
+      if (i < n && crust_sb_tag[CRUST_SB_SLOT(loc, i)] == tag) found = i + 1;
+    }
+    __CPROVER_assert(found <= CRUST_SB_DEPTH,
+                     "access through a pointer invalidated by a later borrow");
+    if (found > CRUST_SB_DEPTH) return UNIT;
+    __CPROVER_assert(!write || crust_sb_kind[CRUST_SB_SLOT(loc, found - 1)] != CRUST_SB_SHARED_RO,
+                     "write through a shared reference");
+  }
+  if (write) {
+    n = found;
+  } else {
+    for (i = CRUST_SB_DEPTH; i > found; i--) {
+      if (i <= n && crust_sb_kind[CRUST_SB_SLOT(loc, i - 1)] == CRUST_SB_UNIQUE) n = i - 1;
+    }
+  }
+  crust_sb_len[loc] = n;
+  return UNIT;
+}
+
+rs_usize crust_sb_push(const void *p, rs_usize size, rs_usize kind) {
+  rs_u64 loc;
+  rs_usize n;
+  if (size == 0) return CRUST_SB_UNTRACKED;
+  loc = CRUST_SHADOW_INDEX(p);
+  n = crust_sb_len[loc];
+  if (n == CRUST_SB_DEPTH) return CRUST_SB_UNTRACKED;
+  crust_sb_next_tag++;
+  crust_sb_tag[CRUST_SB_SLOT(loc, n)] = crust_sb_next_tag;
+  crust_sb_kind[CRUST_SB_SLOT(loc, n)] = kind;
+  crust_sb_len[loc] = n + 1;
+  return crust_sb_next_tag;
+}
+
+/* A new borrow of `*p` derived from `parent`: creating anything but a shared
+ * reference counts as a write through the parent. */
+rs_usize crust_sb_retag(const void *p, rs_usize size, rs_usize parent, rs_usize kind) {
+  crust_sb_access(p, size, parent, kind != CRUST_SB_SHARED_RO);
+  return crust_sb_push(p, size, kind);
+}
+
+/* Allocation failure.  Off until a harness calls
+ * `__crust::allow_alloc_failures(n)`; after that, up to `n` allocations may
+ * nondeterministically fail. */
+rs_usize crust_alloc_failures_left = 0;
+
+rs_unit crust_allow_alloc_failures(rs_usize max) {
+  crust_alloc_failures_left = max;
+  return UNIT;
+}
+
+int crust_alloc_fails(void) {
+  if (crust_alloc_failures_left > 0 && nondet_crust_bool()) {
+    crust_alloc_failures_left--;
+    return 1;
+  }
+  return 0;
+}
+
+/* Shadow state for the Rust allocator (`alloc::heap`), indexed by CBMC
+ * object.  A deallocation may pass any size between the one requested and
+ * the usable size, and must pass the alignment it was allocated with. */
+#define CRUST_ALLOC_NONE 0
+#define CRUST_ALLOC_LIVE 1
+#define CRUST_ALLOC_FREED 2
+
+unsigned char crust_alloc_state[__CPROVER_constant_infinity_uint];
+rs_usize crust_alloc_req[__CPROVER_constant_infinity_uint];
+rs_usize crust_alloc_usable[__CPROVER_constant_infinity_uint];
+rs_usize crust_alloc_align[__CPROVER_constant_infinity_uint];
+rs_usize crust_live_allocs = 0;
+
+rs_u8 *crust_allocate(rs_usize size, rs_usize align) {
+  rs_u8 *p;
+  rs_usize obj;
+  __CPROVER_assert(size != 0, "zero-sized allocation");
+  __CPROVER_assume(size < CRUST_MAX_MEM);
+  if (crust_alloc_fails()) {
+    return NULL;
+  }
+  p = malloc(size);
+  crust_mark_uninit(p, size);
+  obj = __CPROVER_POINTER_OBJECT(p);
+  crust_alloc_state[obj] = CRUST_ALLOC_LIVE;
+  crust_alloc_req[obj] = size;
+  crust_alloc_usable[obj] = size;
+  crust_alloc_align[obj] = align;
+  crust_live_allocs++;
+  return p;
+}
+
+rs_unit crust_check_allocation(rs_u8 *p, rs_usize old_size, rs_usize align) {
+  rs_usize obj = __CPROVER_POINTER_OBJECT(p);
+  __CPROVER_assert(crust_alloc_state[obj] != CRUST_ALLOC_FREED, "double free");
+  __CPROVER_assert(crust_alloc_state[obj] == CRUST_ALLOC_LIVE,
+                   "deallocating memory that was not allocated");
+  __CPROVER_assert(__CPROVER_POINTER_OFFSET(p) == 0,
+                   "deallocating a pointer into the middle of an allocation");
+  __CPROVER_assert(crust_alloc_req[obj] <= old_size && old_size <= crust_alloc_usable[obj],
+                   "deallocating with the wrong size");
+  __CPROVER_assert(crust_alloc_align[obj] == align, "deallocating with the wrong alignment");
+  return UNIT;
+}
+
+rs_unit crust_deallocate(rs_u8 *p, rs_usize old_size, rs_usize align) {
+  crust_check_allocation(p, old_size, align);
+  crust_alloc_state[__CPROVER_POINTER_OBJECT(p)] = CRUST_ALLOC_FREED;
+  crust_live_allocs--;
+  /* CBMC's --pointer-check then catches any later access */
+  free(p);
+  return UNIT;
+}
+
+rs_u8 *crust_reallocate(rs_u8 *p, rs_usize old_size, rs_usize size, rs_usize align) {
+  rs_u8 *q;
+  crust_check_allocation(p, old_size, align);
+  q = crust_allocate(size, align);
+  /* on failure the old allocation stays live */
+  if (q == NULL) {
+    return NULL;
+  }
+  memcpy(q, p, old_size < size ? old_size : size);
+  crust_copy_init(q, p, old_size < size ? old_size : size);
+  crust_deallocate(p, old_size, align);
+  return q;
+}
+
+/* Never moves or grows the allocation, but shrinking always succeeds. */
+rs_usize crust_reallocate_inplace(rs_u8 *p, rs_usize old_size, rs_usize size, rs_usize align) {
+  rs_usize obj = __CPROVER_POINTER_OBJECT(p);
+  crust_check_allocation(p, old_size, align);
+  if (size <= crust_alloc_usable[obj]) {
+    crust_alloc_req[obj] = size;
+  }
+  return crust_alloc_usable[obj];
+}
+
+rs_unit crust_assert_no_leaks(void) {
+  __CPROVER_assert(crust_live_allocs == 0, "memory leak");
+  return UNIT;
+}
@@ -2,27 +2,34 @@ fn crust_abort() -> ! {
     unsafe { core::intrinsics::abort() }
 }
 
+// Preprocess marks each call with its arguments, so `crust -panic-check` can
+// report the message and location.  The message can be any value, as with
+// `panic!`; only a literal one is forwarded.
+fn crust_panic<M>(msg: M, file: &'static str, line: u32) -> ! {
+    crust_abort()
+}
+
 macro_rules! panic {
     () => ({
-        $crate::crust_abort()
+        $crate::crust_panic("explicit panic", file!(), line!())
     });
     ($msg:expr) => ({
-        $crate::crust_abort()
+        $crate::crust_panic($msg, file!(), line!())
     });
     ($fmt:expr, $($arg:tt)+) => ({
-        $crate::crust_abort()
+        $crate::crust_panic($fmt, file!(), line!())
     })
 }
 
 macro_rules! assert {
     ($cond:expr) => (
         if !$cond {
-            $crate::crust_abort()
+            $crate::crust_panic(concat!("assertion failed: ", stringify!($cond)), file!(), line!())
         }
         );
     ($cond:expr, $($arg::tt)+) => (
         if !$cond {
-            $crate::crust_abort()
+            $crate::crust_panic(concat!("assertion failed: ", stringify!($cond)), file!(), line!())
         }
         )
 }
@@ -0,0 +1,91 @@
+//! Definitions to leave out of the IR, read from the `--crust-filter` file.
+//!
+//! Each non-blank line is a rule; lines starting with `#` are comments.
+//!
+//!     PATTERN         leave out definitions whose mangled name matches
+//!     !PATTERN        keep them after all (overrides earlier lines)
+//!     only PATTERN    leave out everything that doesn't match some `only` line
+//!
+//! Patterns are globs over mangled names, as in the .filter files: `*` matches
+//! any run of characters (including `$`) and `?` matches exactly one.  For
+//! `PATTERN` and `!PATTERN`, the last matching line wins.
+
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+struct Rule {
+    keep: bool,
+    pattern: Vec<char>,
+}
+
+pub struct Filter {
+    rules: Vec<Rule>,
+    only: Vec<Vec<char>>,
+}
+
+impl Filter {
+    pub fn empty() -> Filter {
+        Filter {
+            rules: Vec::new(),
+            only: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Filter {
+        let mut filter = Filter::empty();
+        let f = io::BufReader::new(fs::File::open(path).unwrap());
+        for line in f.lines() {
+            let l = line.unwrap();
+            let l = l.trim();
+            if l.is_empty() || l.starts_with("#") {
+                continue;
+            }
+
+            if l.starts_with("only ") {
+                filter.only.push(l[5..].trim().chars().collect());
+            } else if l.starts_with("!") {
+                filter.rules.push(Rule { keep: true, pattern: l[1..].trim().chars().collect() });
+            } else {
+                filter.rules.push(Rule { keep: false, pattern: l.chars().collect() });
+            }
+        }
+        filter
+    }
+
+    /// Whether the definition named `name` should be left out.
+    pub fn excludes(&self, name: &str) -> bool {
+        let name = name.chars().collect::<Vec<_>>();
+        if self.only.len() > 0 && !self.only.iter().any(|p| glob_match(&**p, &*name)) {
+            return true;
+        }
+        match self.rules.iter().rev().find(|r| glob_match(&*r.pattern, &*name)) {
+            Some(rule) => !rule.keep,
+            None => false,
+        }
+    }
+}
+
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    // Iterative matcher: on a mismatch, backtrack to the most recent `*` and
+    // let it absorb one more character.
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((sp, sn)) = star {
+            p = sp + 1;
+            n = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
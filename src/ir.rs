@@ -0,0 +1,297 @@
+//! Typed form of the intermediate representation produced by `trans`.  See
+//! `text` for the printer that turns it into the format read by Preprocess
+//! and crust.
+
+pub type Name = String;
+pub type Region = Name;
+
+// Source range of a node, recorded only when `--crust-spans` is given.  Lines
+// are 1-based and columns 0-based, as in the codemap.
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub file: String,
+    pub lo_line: usize,
+    pub lo_col: usize,
+    pub hi_line: usize,
+    pub hi_col: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Visibility {
+    Public,
+    Private,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mutability {
+    Imm,
+    Mut,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IntSize {
+    Bits(usize),
+    PtrSize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Substs {
+    pub regions: Vec<Region>,
+    pub types: Vec<Ty>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Projection {
+    pub name: Name,
+    pub substs: Substs,
+}
+
+#[derive(Clone, Debug)]
+pub enum Ty {
+    Bool,
+    Char,
+    Int(IntSize),
+    Uint(IntSize),
+    Float(usize),
+    Adt(Name, Substs),
+    Str,
+    Vec(Box<Ty>),
+    FixedVec(usize, Box<Ty>),
+    Ptr(Mutability, Box<Ty>),
+    Ref(Region, Mutability, Box<Ty>),
+    Unit,
+    Tuple(Vec<Ty>),
+    Abstract(Projection),
+    Var(Name),
+    // An AST type that typeck never resolved.  Carries the debug repr.
+    Missing(String),
+}
+
+#[derive(Clone, Debug)]
+pub enum Return {
+    Value(Ty),
+    Diverging,
+    // No return type was written.
+    Default,
+}
+
+#[derive(Clone, Debug)]
+pub struct Generics {
+    pub lifetimes: Vec<Name>,
+    pub ty_params: Vec<Name>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Predicate {
+    Impl(Name, Substs),
+    Eq(Ty, Ty),
+    EqProj(Projection, Ty),
+}
+
+#[derive(Clone, Debug)]
+pub struct ImplClause {
+    pub trait_name: Name,
+    pub item: Option<Name>,
+    pub lifetimes: Vec<Region>,
+    pub ty_args: Vec<Ty>,
+    // Type parameters of the method itself, appended after `ty_args`.
+    pub fn_ty_params: Vec<Name>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Pat {
+    pub ty: Ty,
+    pub kind: PatKind,
+}
+
+#[derive(Clone, Debug)]
+pub enum PatKind {
+    Wild,
+    Var(Name),
+    RefVar(Name),
+    Const(Name),
+    Enum(Name, usize, Vec<Pat>),
+    Tuple(Vec<Pat>),
+    AddrOf(Box<Pat>),
+    SimpleLiteral(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Expr {
+    pub ty: Ty,
+    pub kind: ExprKind,
+    pub span: Option<Span>,
+}
+
+impl Expr {
+    pub fn new(ty: Ty, kind: ExprKind) -> Expr {
+        Expr {
+            ty: ty,
+            kind: kind,
+            span: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ExprKind {
+    Var(Name),
+    Const(Name),
+    SimpleLiteral(String),
+    StructLiteral(Vec<Field>),
+    EnumLiteral(Name, usize, Vec<Expr>),
+    TupleLiteral(Vec<Expr>),
+    Vec(Vec<Expr>),
+    Call(Name, Substs, Vec<Expr>),
+    BinOp(String, Box<Expr>, Box<Expr>),
+    UnOp(String, Box<Expr>),
+    Deref(Box<Expr>),
+    AddrOf(Box<Expr>),
+    Cast(Box<Expr>),
+    Field(Box<Expr>, Name),
+    Index(Box<Expr>, Box<Expr>),
+    Range(Option<Box<Expr>>, Option<Box<Expr>>),
+    Match(Box<Expr>, Vec<MatchArm>),
+    Block(Block),
+    While(Box<Expr>, Block, LoopInfo),
+    For(Pat, Box<Expr>, Block, LoopInfo),
+    Assign(Box<Expr>, Box<Expr>),
+    AssignOp(String, Box<Expr>, Box<Expr>),
+    Return(Box<Expr>),
+    Break,
+    Continue,
+    UnsizeLen(usize, Box<Expr>),
+    // Extra nesting left by a chain of autorefs.  Has the type of the inner
+    // expression.
+    Grouped(Box<Expr>),
+    // Unit value standing in for a missing expression (empty block tail, bare
+    // `return`, `if` without `else`).  The tag records where it came from.
+    Placeholder(&'static str),
+}
+
+// Annotations on a loop, taken from the `__crust::invariant` and
+// `__crust::unwind` calls at the top of its body.
+#[derive(Clone, Debug)]
+pub struct LoopInfo {
+    pub invariants: Vec<Expr>,
+    pub unwind: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Field {
+    pub name: Name,
+    pub expr: Expr,
+}
+
+#[derive(Clone, Debug)]
+pub struct MatchArm {
+    pub pat: Pat,
+    pub body: Expr,
+}
+
+#[derive(Clone, Debug)]
+pub struct Block {
+    pub unsafety: bool,
+    pub stmts: Vec<Stmt>,
+    pub expr: Box<Expr>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Stmt {
+    Let(Pat, Option<Expr>, Option<Span>),
+    Expr(Expr),
+}
+
+// The explicit `self` argument of a method.  A self type of `None` is the
+// `Self` parameter of the enclosing trait (in default methods).
+#[derive(Clone, Debug)]
+pub enum SelfArg {
+    Value(Option<Ty>, Name),
+    Region(Region, Mutability, Option<Ty>, Name),
+    Explicit(Ty, Name),
+}
+
+#[derive(Clone, Debug)]
+pub struct FnDef {
+    pub vis: Visibility,
+    pub exported: bool,
+    pub name: Name,
+    pub generics: Generics,
+    pub self_arg: Option<SelfArg>,
+    pub args: Vec<Pat>,
+    pub ret: Return,
+    pub impl_clause: Option<ImplClause>,
+    pub preds: Vec<Predicate>,
+    pub body: Block,
+    pub span: Option<Span>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FieldDef {
+    pub vis: Visibility,
+    pub name: Name,
+    pub ty: Ty,
+}
+
+#[derive(Clone, Debug)]
+pub struct StructDef {
+    pub name: Name,
+    pub generics: Generics,
+    pub fields: Vec<FieldDef>,
+    pub dtor: Option<Name>,
+    pub span: Option<Span>,
+}
+
+#[derive(Clone, Debug)]
+pub struct VariantDef {
+    pub name: Name,
+    pub args: Vec<Ty>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EnumDef {
+    pub name: Name,
+    pub generics: Generics,
+    pub variants: Vec<VariantDef>,
+    pub dtor: Option<Name>,
+    pub span: Option<Span>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Item {
+    Struct(StructDef),
+    Enum(EnumDef),
+    Fn(FnDef),
+    AbstractFn(Name, Generics, Vec<Pat>, Return),
+    AbstractType(Name, Generics),
+    ExternFn(String, Name, Generics, Vec<Pat>, Return),
+    AssociatedType(Generics, ImplClause, Ty),
+    UseDefault(Generics, ImplClause),
+    Impl(Generics, ImplClause, Vec<Predicate>),
+    Const(Name, Ty, Expr),
+    Static(Name, Ty, Expr),
+    Comment(String),
+    // Translation of the named item panicked.
+    Error(Diagnostic),
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub item: Name,
+    pub span: Option<Span>,
+    // The kind of construct that couldn't be translated, e.g. `ExprClosure`,
+    // or "other" if the message doesn't say.
+    pub construct: String,
+    pub message: String,
+    // How to rewrite the construct so it translates, if known.
+    pub help: Option<String>,
+}
+
+// Everything produced for one source item.
+#[derive(Clone, Debug)]
+pub enum Entry {
+    Single(Option<Item>),
+    // The members of an impl, trait, or extern block, followed by the `impl`
+    // declaration itself for trait impls.
+    Group(Vec<Item>, Option<Item>),
+}
@@ -0,0 +1,537 @@
+//! JSON printer for the IR, selected with `--crust-format=json`.  Output is
+//! JSON Lines: a header object, then one object per item, in the same order
+//! as the text format.
+//!
+//! Schema (version 2).  Every object has a `"kind"` field naming its variant;
+//! the remaining fields are listed here.
+//!
+//!     header:     format = "crust-ir", version, crate
+//!     items:      struct {name, generics, fields, dtor}
+//!                 enum {name, generics, variants, dtor}
+//!                 fn {vis, exported, name, generics, self_arg, args, ret,
+//!                     impl_clause, preds, body}
+//!                 abstract_fn {name, generics, args, ret}
+//!                 abstract_type {name, generics}
+//!                 extern_fn {abi, name, generics, args, ret}
+//!                 associated_type {generics, impl_clause, ty}
+//!                 use_default {generics, impl_clause}
+//!                 impl {generics, impl_clause, preds}
+//!                 const / static {name, ty, expr}
+//!                 comment {text}
+//!                 error {diagnostic}
+//!     types:      bool, char, str, unit, int/uint {size}, float {bits},
+//!                 adt {name, substs}, vec {elem}, fixed_vec {len, elem},
+//!                 ptr/ref {region (ref only), mutbl, pointee},
+//!                 tuple {elems}, abstract {projection}, var {name},
+//!                 missing {repr}
+//!     exprs:      {ty, expr}, where `expr` is one of the expression kinds in
+//!                 `ir::ExprKind`, named as in the text format.
+//!     patterns:   {ty, pat}, likewise for `ir::PatKind`.
+//!
+//! Integer sizes are a number of bits or the string "size".  Returns are
+//! {"kind":"value","ty":...}, {"kind":"bottom"} or {"kind":"default"}.
+//!
+//! With `--crust-spans`, expressions, `let` statements, and struct, enum and
+//! fn items carry an extra `span` field: {file, lo: [line, col], hi: [line,
+//! col]}.  The field is omitted when no span was recorded.
+//!
+//! Loops (`while` and `for`) also have `invariants`, a list of boolean
+//! expressions, and `unwind`, the loop's unwinding bound or null.
+//!
+//! Diagnostics (in `error` items and in the `--crust-diagnostics` file) are
+//! {item, span, construct, message, help}, with a null span when unknown and
+//! a null help unless `--crust-check-only` has a suggestion.
+//!
+//! Version 2 replaced `error {name, message}` with `error {diagnostic}`, and
+//! added loop `invariants` and `unwind` and the diagnostic `help` field.
+
+use ir::*;
+
+pub const SCHEMA_VERSION: usize = 2;
+
+pub trait Json {
+    fn json(&self) -> String;
+}
+
+pub fn quote(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&*format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn obj(kind: &str, fields: &[(&str, String)]) -> String {
+    let mut result = format!("{{\"kind\":{}", quote(kind));
+    for &(ref name, ref value) in fields.iter() {
+        result.push_str(&*format!(",{}:{}", quote(*name), value));
+    }
+    result.push('}');
+    result
+}
+
+fn fields_only(fields: &[(&str, String)]) -> String {
+    let parts = fields.iter()
+                      .map(|&(ref name, ref value)| format!("{}:{}", quote(*name), value))
+                      .collect::<Vec<_>>();
+    format!("{{{}}}", parts.connect(","))
+}
+
+pub fn header(crate_name: &str) -> String {
+    fields_only(&[("format", quote("crust-ir")),
+                  ("version", format!("{}", SCHEMA_VERSION)),
+                  ("crate", quote(crate_name))])
+}
+
+fn with_span(mut fields: Vec<(&'static str, String)>,
+             span: &Option<Span>) -> Vec<(&'static str, String)> {
+    if let Some(ref span) = *span {
+        fields.push(("span", span.json()));
+    }
+    fields
+}
+
+impl Json for Span {
+    fn json(&self) -> String {
+        fields_only(&[("file", self.file.json()),
+                      ("lo", format!("[{},{}]", self.lo_line, self.lo_col)),
+                      ("hi", format!("[{},{}]", self.hi_line, self.hi_col))])
+    }
+}
+
+impl Json for String {
+    fn json(&self) -> String {
+        quote(&**self)
+    }
+}
+
+impl Json for usize {
+    fn json(&self) -> String {
+        format!("{}", self)
+    }
+}
+
+impl Json for bool {
+    fn json(&self) -> String {
+        format!("{}", self)
+    }
+}
+
+impl<T: Json> Json for Box<T> {
+    fn json(&self) -> String {
+        (**self).json()
+    }
+}
+
+impl<T: Json> Json for Option<T> {
+    fn json(&self) -> String {
+        match *self {
+            Some(ref x) => x.json(),
+            None => format!("null"),
+        }
+    }
+}
+
+impl<T: Json> Json for Vec<T> {
+    fn json(&self) -> String {
+        let parts = self.iter().map(|x| x.json()).collect::<Vec<_>>();
+        format!("[{}]", parts.connect(","))
+    }
+}
+
+impl Json for Visibility {
+    fn json(&self) -> String {
+        match *self {
+            Visibility::Public => quote("pub"),
+            Visibility::Private => quote("priv"),
+        }
+    }
+}
+
+impl Json for Mutability {
+    fn json(&self) -> String {
+        match *self {
+            Mutability::Imm => quote("imm"),
+            Mutability::Mut => quote("mut"),
+        }
+    }
+}
+
+impl Json for IntSize {
+    fn json(&self) -> String {
+        match *self {
+            IntSize::Bits(bits) => format!("{}", bits),
+            IntSize::PtrSize => quote("size"),
+        }
+    }
+}
+
+impl Json for Substs {
+    fn json(&self) -> String {
+        fields_only(&[("regions", self.regions.json()),
+                      ("types", self.types.json())])
+    }
+}
+
+impl Json for Projection {
+    fn json(&self) -> String {
+        fields_only(&[("name", self.name.json()),
+                      ("substs", self.substs.json())])
+    }
+}
+
+impl Json for Ty {
+    fn json(&self) -> String {
+        match *self {
+            Ty::Bool => obj("bool", &[]),
+            Ty::Char => obj("char", &[]),
+            Ty::Int(size) => obj("int", &[("size", size.json())]),
+            Ty::Uint(size) => obj("uint", &[("size", size.json())]),
+            Ty::Float(bits) => obj("float", &[("bits", bits.json())]),
+            Ty::Adt(ref name, ref substs) =>
+                obj("adt", &[("name", name.json()),
+                             ("substs", substs.json())]),
+            Ty::Str => obj("str", &[]),
+            Ty::Vec(ref ty) => obj("vec", &[("elem", ty.json())]),
+            Ty::FixedVec(len, ref ty) =>
+                obj("fixed_vec", &[("len", len.json()),
+                                   ("elem", ty.json())]),
+            Ty::Ptr(mutbl, ref ty) =>
+                obj("ptr", &[("mutbl", mutbl.json()),
+                             ("pointee", ty.json())]),
+            Ty::Ref(ref r, mutbl, ref ty) =>
+                obj("ref", &[("region", r.json()),
+                             ("mutbl", mutbl.json()),
+                             ("pointee", ty.json())]),
+            Ty::Unit => obj("unit", &[]),
+            Ty::Tuple(ref tys) => obj("tuple", &[("elems", tys.json())]),
+            Ty::Abstract(ref proj) => obj("abstract", &[("projection", proj.json())]),
+            Ty::Var(ref name) => obj("var", &[("name", name.json())]),
+            Ty::Missing(ref repr) => obj("missing", &[("repr", repr.json())]),
+        }
+    }
+}
+
+impl Json for Return {
+    fn json(&self) -> String {
+        match *self {
+            Return::Value(ref ty) => obj("value", &[("ty", ty.json())]),
+            Return::Diverging => obj("bottom", &[]),
+            Return::Default => obj("default", &[]),
+        }
+    }
+}
+
+impl Json for Generics {
+    fn json(&self) -> String {
+        fields_only(&[("lifetimes", self.lifetimes.json()),
+                      ("ty_params", self.ty_params.json())])
+    }
+}
+
+impl Json for Predicate {
+    fn json(&self) -> String {
+        match *self {
+            Predicate::Impl(ref name, ref substs) =>
+                obj("ty_impl", &[("trait_name", name.json()),
+                                 ("substs", substs.json())]),
+            Predicate::Eq(ref a, ref b) =>
+                obj("ty_eq", &[("lhs", a.json()),
+                               ("rhs", b.json())]),
+            Predicate::EqProj(ref proj, ref ty) =>
+                obj("ty_eq_proj", &[("projection", proj.json()),
+                                    ("ty", ty.json())]),
+        }
+    }
+}
+
+impl Json for ImplClause {
+    fn json(&self) -> String {
+        fields_only(&[("trait_name", self.trait_name.json()),
+                      ("item", self.item.json()),
+                      ("lifetimes", self.lifetimes.json()),
+                      ("ty_args", self.ty_args.json()),
+                      ("fn_ty_params", self.fn_ty_params.json())])
+    }
+}
+
+impl Json for Pat {
+    fn json(&self) -> String {
+        fields_only(&[("ty", self.ty.json()),
+                      ("pat", self.kind.json())])
+    }
+}
+
+impl Json for PatKind {
+    fn json(&self) -> String {
+        match *self {
+            PatKind::Wild => obj("wild", &[]),
+            PatKind::Var(ref name) => obj("var", &[("name", name.json())]),
+            PatKind::RefVar(ref name) => obj("ref_var", &[("name", name.json())]),
+            PatKind::Const(ref name) => obj("const", &[("name", name.json())]),
+            PatKind::Enum(ref name, idx, ref args) =>
+                obj("enum", &[("variant", name.json()),
+                              ("index", idx.json()),
+                              ("args", args.json())]),
+            PatKind::Tuple(ref args) => obj("tuple", &[("args", args.json())]),
+            PatKind::AddrOf(ref pat) => obj("addr_of", &[("pat", pat.json())]),
+            PatKind::SimpleLiteral(ref lit) => obj("simple_literal", &[("value", lit.json())]),
+        }
+    }
+}
+
+impl Json for Expr {
+    fn json(&self) -> String {
+        match self.kind {
+            // Grouping only matters to the text format.
+            ExprKind::Grouped(ref e) => e.json(),
+            ExprKind::Placeholder(tag) =>
+                fields_only(&*with_span(vec![("ty", self.ty.json()),
+                                             ("expr", obj("simple_literal",
+                                                          &[("value", quote(tag))]))],
+                                        &self.span)),
+            ref kind => fields_only(&*with_span(vec![("ty", self.ty.json()),
+                                                     ("expr", kind.json())],
+                                                &self.span)),
+        }
+    }
+}
+
+impl Json for ExprKind {
+    fn json(&self) -> String {
+        match *self {
+            ExprKind::Var(ref name) => obj("var", &[("name", name.json())]),
+            ExprKind::Const(ref name) => obj("const", &[("name", name.json())]),
+            ExprKind::SimpleLiteral(ref lit) => obj("simple_literal", &[("value", lit.json())]),
+            ExprKind::StructLiteral(ref fields) =>
+                obj("struct_literal", &[("fields", fields.json())]),
+            ExprKind::EnumLiteral(ref name, idx, ref args) =>
+                obj("enum_literal", &[("variant", name.json()),
+                                      ("index", idx.json()),
+                                      ("args", args.json())]),
+            ExprKind::TupleLiteral(ref es) => obj("tuple_literal", &[("elems", es.json())]),
+            ExprKind::Vec(ref es) => obj("vec", &[("elems", es.json())]),
+            ExprKind::Call(ref name, ref substs, ref args) =>
+                obj("call", &[("name", name.json()),
+                              ("substs", substs.json()),
+                              ("args", args.json())]),
+            ExprKind::BinOp(ref op, ref a, ref b) =>
+                obj("binop", &[("op", op.json()),
+                               ("lhs", a.json()),
+                               ("rhs", b.json())]),
+            ExprKind::UnOp(ref op, ref a) =>
+                obj("unop", &[("op", op.json()),
+                              ("arg", a.json())]),
+            ExprKind::Deref(ref e) => obj("deref", &[("arg", e.json())]),
+            ExprKind::AddrOf(ref e) => obj("addr_of", &[("arg", e.json())]),
+            ExprKind::Cast(ref e) => obj("cast", &[("arg", e.json())]),
+            ExprKind::Field(ref e, ref name) =>
+                obj("field", &[("base", e.json()),
+                               ("name", name.json())]),
+            ExprKind::Index(ref a, ref b) =>
+                obj("index", &[("base", a.json()),
+                               ("index", b.json())]),
+            ExprKind::Range(ref low, ref high) =>
+                obj("range", &[("low", low.json()),
+                               ("high", high.json())]),
+            ExprKind::Match(ref e, ref arms) =>
+                obj("match", &[("scrutinee", e.json()),
+                               ("arms", arms.json())]),
+            ExprKind::Block(ref b) => b.json(),
+            ExprKind::While(ref cond, ref body, ref info) =>
+                obj("while", &[("cond", cond.json()),
+                               ("body", body.json()),
+                               ("invariants", info.invariants.json()),
+                               ("unwind", info.unwind.json())]),
+            ExprKind::For(ref pat, ref e, ref body, ref info) =>
+                obj("for", &[("pat", pat.json()),
+                             ("iter", e.json()),
+                             ("body", body.json()),
+                             ("invariants", info.invariants.json()),
+                             ("unwind", info.unwind.json())]),
+            ExprKind::Assign(ref l, ref r) =>
+                obj("assign", &[("lhs", l.json()),
+                                ("rhs", r.json())]),
+            ExprKind::AssignOp(ref op, ref l, ref r) =>
+                obj("assign_op", &[("op", op.json()),
+                                   ("lhs", l.json()),
+                                   ("rhs", r.json())]),
+            ExprKind::Return(ref e) => obj("return", &[("arg", e.json())]),
+            ExprKind::Break => obj("break", &[]),
+            ExprKind::Continue => obj("continue", &[]),
+            ExprKind::UnsizeLen(len, ref e) =>
+                obj("unsize_len", &[("len", len.json()),
+                                    ("arg", e.json())]),
+            ExprKind::Grouped(_) |
+            ExprKind::Placeholder(_) => panic!("expression kind must be printed with its type"),
+        }
+    }
+}
+
+impl Json for Field {
+    fn json(&self) -> String {
+        fields_only(&[("name", self.name.json()),
+                      ("expr", self.expr.json())])
+    }
+}
+
+impl Json for MatchArm {
+    fn json(&self) -> String {
+        fields_only(&[("pat", self.pat.json()),
+                      ("body", self.body.json())])
+    }
+}
+
+impl Json for Block {
+    fn json(&self) -> String {
+        obj("block", &[("unsafe", self.unsafety.json()),
+                       ("stmts", self.stmts.json()),
+                       ("expr", self.expr.json())])
+    }
+}
+
+impl Json for Stmt {
+    fn json(&self) -> String {
+        match *self {
+            Stmt::Let(ref pat, ref init, ref span) =>
+                obj("let", &*with_span(vec![("pat", pat.json()),
+                                            ("init", init.json())],
+                                       span)),
+            Stmt::Expr(ref e) => obj("expr", &[("expr", e.json())]),
+        }
+    }
+}
+
+impl Json for SelfArg {
+    fn json(&self) -> String {
+        // A missing self type is the trait's `Self` parameter.
+        let self_ty = |ty: &Option<Ty>| match *ty {
+            Some(ref ty) => ty.json(),
+            None => Ty::Var(format!("s_0")).json(),
+        };
+        match *self {
+            SelfArg::Value(ref ty, ref name) =>
+                obj("value", &[("ty", self_ty(ty)),
+                               ("name", name.json())]),
+            SelfArg::Region(ref r, mutbl, ref ty, ref name) =>
+                obj("region", &[("region", r.json()),
+                                ("mutbl", mutbl.json()),
+                                ("ty", self_ty(ty)),
+                                ("name", name.json())]),
+            SelfArg::Explicit(ref ty, ref name) =>
+                obj("explicit", &[("ty", ty.json()),
+                                  ("name", name.json())]),
+        }
+    }
+}
+
+impl Json for FieldDef {
+    fn json(&self) -> String {
+        fields_only(&[("vis", self.vis.json()),
+                      ("name", self.name.json()),
+                      ("ty", self.ty.json())])
+    }
+}
+
+impl Json for VariantDef {
+    fn json(&self) -> String {
+        fields_only(&[("name", self.name.json()),
+                      ("args", self.args.json())])
+    }
+}
+
+impl Json for Diagnostic {
+    fn json(&self) -> String {
+        fields_only(&[("item", self.item.json()),
+                      ("span", self.span.json()),
+                      ("construct", self.construct.json()),
+                      ("message", self.message.json()),
+                      ("help", self.help.json())])
+    }
+}
+
+impl Json for Item {
+    fn json(&self) -> String {
+        match *self {
+            Item::Struct(ref s) =>
+                obj("struct", &*with_span(vec![("name", s.name.json()),
+                                               ("generics", s.generics.json()),
+                                               ("fields", s.fields.json()),
+                                               ("dtor", s.dtor.json())],
+                                          &s.span)),
+            Item::Enum(ref e) =>
+                obj("enum", &*with_span(vec![("name", e.name.json()),
+                                             ("generics", e.generics.json()),
+                                             ("variants", e.variants.json()),
+                                             ("dtor", e.dtor.json())],
+                                        &e.span)),
+            Item::Fn(ref f) =>
+                obj("fn", &*with_span(vec![("vis", f.vis.json()),
+                                           ("exported", f.exported.json()),
+                                           ("name", f.name.json()),
+                                           ("generics", f.generics.json()),
+                                           ("self_arg", f.self_arg.json()),
+                                           ("args", f.args.json()),
+                                           ("ret", f.ret.json()),
+                                           ("impl_clause", f.impl_clause.json()),
+                                           ("preds", f.preds.json()),
+                                           ("body", f.body.json())],
+                                      &f.span)),
+            Item::AbstractFn(ref name, ref generics, ref args, ref ret) =>
+                obj("abstract_fn", &[("name", name.json()),
+                                     ("generics", generics.json()),
+                                     ("args", args.json()),
+                                     ("ret", ret.json())]),
+            Item::AbstractType(ref name, ref generics) =>
+                obj("abstract_type", &[("name", name.json()),
+                                       ("generics", generics.json())]),
+            Item::ExternFn(ref abi, ref name, ref generics, ref args, ref ret) =>
+                obj("extern_fn", &[("abi", abi.json()),
+                                   ("name", name.json()),
+                                   ("generics", generics.json()),
+                                   ("args", args.json()),
+                                   ("ret", ret.json())]),
+            Item::AssociatedType(ref generics, ref clause, ref ty) =>
+                obj("associated_type", &[("generics", generics.json()),
+                                         ("impl_clause", clause.json()),
+                                         ("ty", ty.json())]),
+            Item::UseDefault(ref generics, ref clause) =>
+                obj("use_default", &[("generics", generics.json()),
+                                     ("impl_clause", clause.json())]),
+            Item::Impl(ref generics, ref clause, ref preds) =>
+                obj("impl", &[("generics", generics.json()),
+                              ("impl_clause", clause.json()),
+                              ("preds", preds.json())]),
+            Item::Const(ref name, ref ty, ref expr) =>
+                obj("const", &[("name", name.json()),
+                               ("ty", ty.json()),
+                               ("expr", expr.json())]),
+            Item::Static(ref name, ref ty, ref expr) =>
+                obj("static", &[("name", name.json()),
+                                ("ty", ty.json()),
+                                ("expr", expr.json())]),
+            Item::Comment(ref msg) => obj("comment", &[("text", msg.json())]),
+            Item::Error(ref diag) => obj("error", &[("diagnostic", diag.json())]),
+        }
+    }
+}
+
+/// One line per item.  Groups are flattened; an entry with no items prints
+/// nothing.
+pub fn entry_lines(entry: &Entry) -> Vec<String> {
+    match *entry {
+        Entry::Single(ref opt_item) => opt_item.iter().map(|i| i.json()).collect(),
+        Entry::Group(ref items, ref opt_tail) =>
+            items.iter().chain(opt_tail.iter()).map(|i| i.json()).collect(),
+    }
+}
@@ -25,7 +25,6 @@ extern crate arena;
 
 use std::io::prelude::*;
 use std::collections::HashMap;
-use std::collections::HashSet;
 use std::env;
 use std::fs;
 use std::io;
@@ -53,6 +52,7 @@ use rustc::metadata;
 use rustc::metadata::creader::CrateReader;
 use rustc::middle::{stability, ty, reachable};
 use rustc::middle::dependency_format;
+use rustc::middle::privacy::ExportedItems;
 use rustc::middle;
 use rustc::plugin::registry::Registry;
 use rustc::plugin;
@@ -65,34 +65,53 @@ use rustc_trans::back::write;
 //use rustc_trans::trans;
 use rustc_typeck as typeck;
 
+mod borrows;
+mod check;
+mod contracts;
+mod filter;
+mod ir;
+mod json;
+mod stubs;
+mod text;
 mod trans;
 
 fn main() {
     run_compiler(&env::args().collect::<Vec<_>>()[1..]);
 }
 
-fn build_filter_list(matches : &getopts::Matches) -> HashSet<String> {
+fn build_filter(matches : &getopts::Matches) -> filter::Filter {
     match matches.opt_str("crust-filter") {
-        None => HashSet::new(),
-        Some(ref f_name) => {
-            let mut to_ret = HashSet::new();
-            let mut f = io::BufReader::new(fs::File::open(&PathBuf::from(f_name)).unwrap());
-            for line in f.lines() {
-                let l = line.unwrap();
-                if l.as_slice().is_empty() {
-                    continue;
-                } else {
-                    to_ret.insert(String::from_str(l.trim()));
-                }
-            }
-            to_ret
-        }
+        None => filter::Filter::empty(),
+        Some(ref f_name) => filter::Filter::load(&PathBuf::from(f_name)),
+    }
+}
+
+fn build_stubs(matches : &getopts::Matches) -> stubs::StubMap {
+    match matches.opt_str("crust-stubs") {
+        None => stubs::StubMap::empty(),
+        Some(ref f_name) => stubs::StubMap::load(&PathBuf::from(f_name)),
     }
 }
 
 fn run_compiler(args: &[String]) {
     let mut tool_opts = config::optgroups();
-    tool_opts.push(getopts::optopt("", "crust-filter", "Filter function definitions from file", ""));
+    tool_opts.push(getopts::optopt("", "crust-filter", "Filter definitions using the rules in FILE", "FILE"));
+    tool_opts.push(getopts::optopt("", "crust-stubs",
+                                   "Replace function bodies with calls to the models listed in FILE", "FILE"));
+    tool_opts.push(getopts::optopt("", "crust-format", "Output format for the IR", "text|json"));
+    tool_opts.push(getopts::optflag("", "crust-spans", "Record source spans in the IR"));
+    tool_opts.push(getopts::optopt("", "crust-diagnostics",
+                                   "Write translation failures to FILE as JSON Lines", "FILE"));
+    tool_opts.push(getopts::optflag("", "crust-strict", "Exit with an error if any item fails to translate"));
+    tool_opts.push(getopts::optflag("", "crust-verbose", "Print translation debugging notes to stderr"));
+    tool_opts.push(getopts::optflag("", "crust-alias-checks",
+                                    "Check each `&mut` created in unsafe code against the reference arguments"));
+    tool_opts.push(getopts::optflag("", "crust-borrow-model",
+                                    "Emit the events for crust's borrow-stack model"));
+    tool_opts.push(getopts::optflag("", "crust-check-only",
+                                    "Report the constructs that can't be translated, without emitting IR"));
+    tool_opts.push(getopts::optflag("", "crust-inline-deps",
+                                    "Also translate the generic and inlined items used from other crates"));
     let matches = getopts::getopts(args, tool_opts.as_slice()).unwrap();
 
     let sopts = config::build_session_options(&matches);
@@ -113,7 +132,25 @@ fn run_compiler(args: &[String]) {
         }
         _ => panic!("multiple input filenames provided")
     };
-    let filter_fn = build_filter_list(&matches);
+    let format = match matches.opt_str("crust-format").as_ref().map(|s| s.as_slice()) {
+        None | Some("text") => trans::Format::Text,
+        Some("json") => trans::Format::Json,
+        Some(f) => panic!("unknown --crust-format `{}` (expected `text` or `json`)", f),
+    };
+    let trans_opts = trans::Options {
+        filter: build_filter(&matches),
+        stubs: build_stubs(&matches),
+        format: format,
+        spans: matches.opt_present("crust-spans"),
+        diagnostics_file: matches.opt_str("crust-diagnostics").map(|p| PathBuf::from(p)),
+        output: None,
+        verbose: matches.opt_present("crust-verbose"),
+        alias_checks: matches.opt_present("crust-alias-checks"),
+        borrow_model: matches.opt_present("crust-borrow-model"),
+        inline_deps: matches.opt_present("crust-inline-deps"),
+    };
+    let strict = matches.opt_present("crust-strict");
+    let check_only = matches.opt_present("crust-check-only");
 
     let descriptions = syntax::diagnostics::registry::Registry::new(&[]);
     let sess = session::build_session(sopts, input_file_path, descriptions);
@@ -121,7 +158,10 @@ fn run_compiler(args: &[String]) {
     let odir = matches.opt_str("out-dir").map(|o| PathBuf::from(o));
     let ofile = matches.opt_str("o").map(|o| PathBuf::from(o));
 
-    compile_input(sess, cfg, &input, &odir, &ofile, filter_fn);
+    let failures = compile_input(sess, cfg, &input, &odir, &ofile, trans_opts, check_only);
+    if (strict || check_only) && failures > 0 {
+        env::set_exit_status(1);
+    }
 }
 
 pub fn compile_input(sess: session::Session,
@@ -129,7 +169,8 @@ pub fn compile_input(sess: session::Session,
                      input: &Input,
                      outdir: &Option<PathBuf>,
                      output: &Option<PathBuf>,
-                     filter_fn: HashSet<String>) {
+                     mut trans_opts: trans::Options,
+                     check_only: bool) -> usize {
     let (outputs, expanded_crate, id) = {
         let krate = driver::phase_1_parse_input(&sess, cfg, input);
         let outputs = driver::build_output_filenames(input,
@@ -141,24 +182,39 @@ pub fn compile_input(sess: session::Session,
                                        input);
         let expanded_crate
             = match driver::phase_2_configure_and_expand(&sess, krate, id.as_slice(), None) {
-                None => return,
+                None => return 0,
                 Some(k) => k
             };
+        // Contracts are rewritten into code before type checking.
+        let expanded_crate = contracts::expand(&sess, expanded_crate);
 
         (outputs, expanded_crate, id)
     };
 
+    // With neither `-o` nor `--out-dir`, the IR goes to stdout.
+    if outdir.is_some() || output.is_some() {
+        trans_opts.output = Some(match outputs.single_output_file {
+            Some(ref path) => path.clone(),
+            None => outputs.with_extension("ir"),
+        });
+    }
+
     let mut forest = ast_map::Forest::new(expanded_crate);
     let (ast_map, arenas) =
         (driver::assign_node_ids_and_map(&sess, &mut forest),
          rustc::middle::ty::CtxtArenas::new());
-    let (tcx, name) = phase_3_run_analysis_passes(sess,
-                                                  ast_map,
-                                                  &arenas,
-                                                  id,
-                                                  rustc_resolve::MakeGlobMap::No);
-
-    trans::process(&tcx, filter_fn, name);
+    let (tcx, exported_items, name) = phase_3_run_analysis_passes(sess,
+                                                                  ast_map,
+                                                                  &arenas,
+                                                                  id,
+                                                                  rustc_resolve::MakeGlobMap::No);
+
+    if check_only {
+        let diags = check::check_crate(&tcx);
+        trans::report_diagnostics(&*diags, &trans_opts.diagnostics_file);
+        return diags.len();
+    }
+    trans::process(&tcx, &exported_items, name, trans_opts)
 }
 
 /// Run the resolution, typechecking, region checking and other
@@ -169,7 +225,7 @@ pub fn phase_3_run_analysis_passes<'tcx>(sess: Session,
                                          arenas: &'tcx ty::CtxtArenas<'tcx>,
                                          name: String,
                                          make_glob_map: resolve::MakeGlobMap)
-                                         -> (ty::ctxt<'tcx>, String) {
+                                         -> (ty::ctxt<'tcx>, ExportedItems, String) {
     let time_passes = sess.time_passes();
     let krate = ast_map.krate();
 
@@ -233,17 +289,13 @@ pub fn phase_3_run_analysis_passes<'tcx>(sess: Session,
     time(time_passes, "const checking", (), |_|
          middle::check_const::check_crate(&ty_cx));
 
-    /*
     let (exported_items, public_items) =
             time(time_passes, "privacy checking", (), |_|
                  rustc_privacy::check_crate(&ty_cx, &export_map, external_exports));
-                 */
 
-    /*
     // Do not move this check past lint
     time(time_passes, "stability index", (), |_|
          ty_cx.stability.borrow_mut().build(&ty_cx.sess, krate, &public_items));
-         */
 
     time(time_passes, "intrinsic checking", (), |_|
          middle::intrinsicck::check_crate(&ty_cx));
@@ -300,6 +352,6 @@ pub fn phase_3_run_analysis_passes<'tcx>(sess: Session,
     // The above three passes generate errors w/o aborting
     ty_cx.sess.abort_if_errors();
 
-    (ty_cx, name)
+    (ty_cx, exported_items, name)
 }
 
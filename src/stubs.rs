@@ -0,0 +1,55 @@
+//! Functions whose bodies are replaced by models, read from the
+//! `--crust-stubs` file.
+//!
+//! Each non-blank line names a function to stub and the function that models
+//! it, both as mangled names; lines starting with `#` are comments.
+//!
+//!     core$slice$sort     __crust_models$slice_sort
+//!
+//! The stubbed function keeps its signature, but its body becomes a call to
+//! the model, forwarding every argument (including `self`) and every type
+//! parameter in order.  Preprocess checks that the two signatures agree when
+//! given the same file with `--stub-models`.  A stubbed function that never
+//! returns (`-> !`) needs a model that never returns either.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+pub struct StubMap {
+    models: HashMap<String, String>,
+}
+
+impl StubMap {
+    pub fn empty() -> StubMap {
+        StubMap {
+            models: HashMap::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> StubMap {
+        let mut stubs = StubMap::empty();
+        let f = io::BufReader::new(fs::File::open(path).unwrap());
+        for line in f.lines() {
+            let l = line.unwrap();
+            let l = l.trim();
+            if l.is_empty() || l.starts_with("#") {
+                continue;
+            }
+
+            let words = l.split(|c: char| c.is_whitespace()).filter(|w| !w.is_empty()).collect::<Vec<_>>();
+            if words.len() != 2 {
+                panic!("bad line in stub file (expected `TARGET MODEL`): {}", l);
+            }
+            stubs.models.insert(String::from_str(words[0]), String::from_str(words[1]));
+        }
+        stubs
+    }
+
+    /// The model to call in place of the body of `name`, if it is stubbed.
+    pub fn model_for(&self, name: &str) -> Option<&str> {
+        self.models.get(name).map(|m| m.as_slice())
+    }
+}
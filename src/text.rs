@@ -0,0 +1,465 @@
+//! Printer for the textual IR format.  Lists are printed with a length prefix,
+//! and every expression and pattern is prefixed with its type.
+
+use ir::*;
+
+pub trait Print {
+    fn print(&self) -> String;
+}
+
+impl Print for String {
+    fn print(&self) -> String {
+        self.clone()
+    }
+}
+
+impl<T: Print> Print for Box<T> {
+    fn print(&self) -> String {
+        (**self).print()
+    }
+}
+
+impl<T: Print> Print for Option<T> {
+    fn print(&self) -> String {
+        match *self {
+            Some(ref x) => format!("1 {}", x.print()),
+            None => format!("0"),
+        }
+    }
+}
+
+impl<T: Print> Print for Vec<T> {
+    fn print(&self) -> String {
+        let mut result = format!("{}", self.len());
+        for item in self.iter() {
+            result.push_str(" ");
+            result.push_str(item.print().as_slice());
+        }
+        result
+    }
+}
+
+impl Print for Visibility {
+    fn print(&self) -> String {
+        match *self {
+            Visibility::Public => format!("pub"),
+            Visibility::Private => format!("priv"),
+        }
+    }
+}
+
+impl Print for IntSize {
+    fn print(&self) -> String {
+        match *self {
+            IntSize::Bits(bits) => format!("{}", bits),
+            IntSize::PtrSize => format!("size"),
+        }
+    }
+}
+
+impl Print for Substs {
+    fn print(&self) -> String {
+        format!("{} {}",
+                self.regions.print(),
+                self.types.print())
+    }
+}
+
+impl Print for Projection {
+    fn print(&self) -> String {
+        format!("abstract {} {}",
+                self.name,
+                self.substs.print())
+    }
+}
+
+fn print_ptr(kind: &str, mutbl: Mutability) -> String {
+    match mutbl {
+        Mutability::Imm => format!("{}", kind),
+        Mutability::Mut => format!("{}_mut", kind),
+    }
+}
+
+impl Print for Ty {
+    fn print(&self) -> String {
+        let s = match *self {
+            Ty::Bool => format!("bool"),
+            Ty::Char => format!("char"),
+            Ty::Int(size) => format!("int {}", size.print()),
+            Ty::Uint(size) => format!("uint {}", size.print()),
+            Ty::Float(bits) => format!("float {}", bits),
+            Ty::Adt(ref name, ref substs) => format!("adt {} {}", name, substs.print()),
+            Ty::Str => format!("str"),
+            Ty::Vec(ref ty) => format!("vec {}", ty.print()),
+            Ty::FixedVec(len, ref ty) => format!("fixed_vec {} {}", len, ty.print()),
+            Ty::Ptr(mutbl, ref ty) => format!("{} {}",
+                                              print_ptr("ptr", mutbl),
+                                              ty.print()),
+            Ty::Ref(ref r, mutbl, ref ty) => format!("{} {} {}",
+                                                     print_ptr("ref", mutbl),
+                                                     r,
+                                                     ty.print()),
+            Ty::Unit => format!("unit"),
+            Ty::Tuple(ref tys) => format!("tuple {}", tys.print()),
+            Ty::Abstract(ref proj) => proj.print(),
+            Ty::Var(ref name) => format!("var {}", name),
+            Ty::Missing(ref repr) => return format!("[[no_ty_to_ty {}]]", repr),
+        };
+        format!("[{}]", s)
+    }
+}
+
+impl Print for Return {
+    fn print(&self) -> String {
+        match *self {
+            Return::Value(ref ty) => ty.print(),
+            Return::Diverging => format!("bottom"),
+            Return::Default => format!("unit"),
+        }
+    }
+}
+
+impl Print for Generics {
+    fn print(&self) -> String {
+        format!("{} {}",
+                self.lifetimes.print(),
+                self.ty_params.print())
+    }
+}
+
+impl Print for Predicate {
+    fn print(&self) -> String {
+        match *self {
+            Predicate::Impl(ref name, ref substs) =>
+                format!("ty_impl {} {}", name, substs.print()),
+            Predicate::Eq(ref a, ref b) =>
+                format!("ty_eq {} {}", a.print(), b.print()),
+            Predicate::EqProj(ref proj, ref ty) =>
+                format!("ty_eq_proj {} {}", proj.print(), ty.print()),
+        }
+    }
+}
+
+impl Print for ImplClause {
+    fn print(&self) -> String {
+        let mut tys = self.ty_args.iter().map(|t| t.print()).collect::<Vec<_>>();
+        tys.extend(self.fn_ty_params.iter().map(|p| format!("var {}", p)));
+        format!("{}{} {} {}",
+                self.trait_name,
+                match self.item {
+                    Some(ref name) => format!("${}", name),
+                    None => format!(""),
+                },
+                self.lifetimes.print(),
+                tys.print())
+    }
+}
+
+impl Print for Pat {
+    fn print(&self) -> String {
+        format!("({} {})",
+                self.ty.print(),
+                self.kind.print())
+    }
+}
+
+impl Print for PatKind {
+    fn print(&self) -> String {
+        match *self {
+            PatKind::Wild => format!("wild"),
+            PatKind::Var(ref name) => format!("var {}", name),
+            PatKind::RefVar(ref name) => format!("ref_var {}", name),
+            PatKind::Const(ref name) => format!("const {}", name),
+            PatKind::Enum(ref name, idx, ref args) =>
+                format!("enum {} {} {}", name, idx, args.print()),
+            PatKind::Tuple(ref args) => format!("tuple {}", args.print()),
+            PatKind::AddrOf(ref pat) => format!("addr_of {}", pat.print()),
+            PatKind::SimpleLiteral(ref lit) => format!("simple_literal {}", lit),
+        }
+    }
+}
+
+impl Print for Expr {
+    fn print(&self) -> String {
+        match self.kind {
+            ExprKind::Grouped(ref e) => format!("(( {} ))", e.print()),
+            ExprKind::Placeholder(tag) => format!("{} simple_literal {}", self.ty.print(), tag),
+            ref kind => format!("({} {})", self.ty.print(), kind.print()),
+        }
+    }
+}
+
+impl Print for ExprKind {
+    fn print(&self) -> String {
+        match *self {
+            ExprKind::Var(ref name) => format!("var {}", name),
+            ExprKind::Const(ref name) => format!("const {}", name),
+            ExprKind::SimpleLiteral(ref lit) => format!("simple_literal {}", lit),
+            ExprKind::StructLiteral(ref fields) => format!("struct_literal {}", fields.print()),
+            ExprKind::EnumLiteral(ref name, idx, ref args) =>
+                format!("enum_literal {} {} {}", name, idx, args.print()),
+            ExprKind::TupleLiteral(ref es) => format!("tuple_literal {}", es.print()),
+            ExprKind::Vec(ref es) => format!("vec {}", es.print()),
+            ExprKind::Call(ref name, ref substs, ref args) =>
+                format!("call {} {} {}", name, substs.print(), args.print()),
+            ExprKind::BinOp(ref op, ref a, ref b) =>
+                format!("binop {} {} {}", op, a.print(), b.print()),
+            ExprKind::UnOp(ref op, ref a) => format!("unop {} {}", op, a.print()),
+            ExprKind::Deref(ref e) => format!("deref {}", e.print()),
+            ExprKind::AddrOf(ref e) => format!("addr_of {}", e.print()),
+            ExprKind::Cast(ref e) => format!("cast {}", e.print()),
+            ExprKind::Field(ref e, ref name) => format!("field {} {}", e.print(), name),
+            ExprKind::Index(ref a, ref b) => format!("index {} {}", a.print(), b.print()),
+            ExprKind::Range(ref low, ref high) =>
+                format!("range {} {}", low.print(), high.print()),
+            ExprKind::Match(ref e, ref arms) => format!("match {} {}", e.print(), arms.print()),
+            ExprKind::Block(ref b) => b.print(),
+            ExprKind::While(ref cond, ref body, ref info) =>
+                format!("while {} {} {}", cond.print(), Ty::Unit.print(),
+                        loop_body(body, info).print()),
+            ExprKind::For(ref pat, ref e, ref body, ref info) =>
+                format!("for {} {} {}", pat.print(), e.print(), loop_body(body, info).print()),
+            ExprKind::Assign(ref l, ref r) => format!("assign {} {}", l.print(), r.print()),
+            ExprKind::AssignOp(ref op, ref l, ref r) =>
+                format!("assign_op {} {} {}", op, l.print(), r.print()),
+            ExprKind::Return(ref e) => format!("return {}", e.print()),
+            ExprKind::Break => format!("break"),
+            ExprKind::Continue => format!("continue"),
+            ExprKind::UnsizeLen(len, ref e) => format!("unsize_len {} {}", len, e.print()),
+            ExprKind::Grouped(_) |
+            ExprKind::Placeholder(_) => panic!("expression kind must be printed with its type"),
+        }
+    }
+}
+
+impl Print for Field {
+    fn print(&self) -> String {
+        format!("{} {}", self.name, self.expr.print())
+    }
+}
+
+impl Print for MatchArm {
+    fn print(&self) -> String {
+        format!("{{ {} >> {} }}", self.pat.print(), self.body.print())
+    }
+}
+
+fn str_literal(s: &str) -> Expr {
+    let mut lit = String::from_str("str_");
+    for b in s.bytes() {
+        lit.push_str(&*format!("{:02x}", b));
+    }
+    Expr::new(Ty::Str, ExprKind::SimpleLiteral(lit))
+}
+
+fn uint_literal(n: usize) -> Expr {
+    Expr::new(Ty::Uint(IntSize::PtrSize), ExprKind::SimpleLiteral(format!("{}", n)))
+}
+
+fn marker_call(name: &str, args: Vec<Expr>) -> Stmt {
+    let call = ExprKind::Call(String::from_str(name),
+                              Substs { regions: vec![], types: vec![] },
+                              args);
+    Stmt::Expr(Expr::new(Ty::Unit, call))
+}
+
+// Spans are carried through Preprocess as calls to the `__crust$line`
+// intrinsic, which crust turns into a `#line` directive.
+fn line_marker(span: &Span) -> String {
+    let args = vec![uint_literal(span.lo_line),
+                    uint_literal(span.lo_col),
+                    uint_literal(span.hi_line),
+                    uint_literal(span.hi_col),
+                    str_literal(&*span.file)];
+    marker_call("__crust$line", args).print()
+}
+
+// Loop annotations go back to being calls at the top of the body, which is
+// where Preprocess and crust look for them.
+fn loop_body(body: &Block, info: &LoopInfo) -> Block {
+    let mut stmts = Vec::new();
+    if let Some(bound) = info.unwind {
+        stmts.push(marker_call("__crust$unwind", vec![uint_literal(bound)]));
+    }
+    for inv in info.invariants.iter() {
+        stmts.push(marker_call("__crust$invariant", vec![inv.clone()]));
+    }
+    stmts.extend(body.stmts.iter().cloned());
+    Block {
+        unsafety: body.unsafety,
+        stmts: stmts,
+        expr: body.expr.clone(),
+    }
+}
+
+fn stmt_span(stmt: &Stmt) -> Option<&Span> {
+    match *stmt {
+        Stmt::Let(_, _, ref span) => span.as_ref(),
+        Stmt::Expr(ref e) => e.span.as_ref(),
+    }
+}
+
+fn print_block(block: &Block, span: Option<&Span>) -> String {
+    let mut stmts = Vec::new();
+    if let Some(span) = span {
+        stmts.push(line_marker(span));
+    }
+    for stmt in block.stmts.iter() {
+        if let Some(span) = stmt_span(stmt) {
+            stmts.push(line_marker(span));
+        }
+        stmts.push(stmt.print());
+    }
+    if let Some(ref span) = block.expr.span {
+        stmts.push(line_marker(span));
+    }
+    format!("{} {{\n{}\t{}\n}}\n",
+            if block.unsafety { "unsafe" } else { "block" },
+            stmts.print(),
+            block.expr.print())
+}
+
+impl Print for Block {
+    fn print(&self) -> String {
+        print_block(self, None)
+    }
+}
+
+impl Print for Stmt {
+    fn print(&self) -> String {
+        match *self {
+            Stmt::Let(ref pat, ref init, _) => format!("\tlet {} {};\n", pat.print(), init.print()),
+            Stmt::Expr(ref e) => format!("\texpr {};\n", e.print()),
+        }
+    }
+}
+
+fn print_self_ty(self_ty: &Option<Ty>) -> String {
+    match *self_ty {
+        Some(ref ty) => ty.print(),
+        None => format!("var s_0"),
+    }
+}
+
+impl Print for SelfArg {
+    fn print(&self) -> String {
+        match *self {
+            SelfArg::Value(ref self_ty, ref name) =>
+                format!("({} var {})", print_self_ty(self_ty), name),
+            SelfArg::Region(ref r, mutbl, ref self_ty, ref name) =>
+                format!("({} {} {} var {})",
+                        print_ptr("ref", mutbl),
+                        r,
+                        print_self_ty(self_ty),
+                        name),
+            SelfArg::Explicit(ref ty, ref name) =>
+                format!("({} var {})", ty.print(), name),
+        }
+    }
+}
+
+impl Print for FnDef {
+    fn print(&self) -> String {
+        let mut args = self.self_arg.iter().map(|a| a.print()).collect::<Vec<_>>();
+        args.extend(self.args.iter().map(|a| a.print()));
+        format!("fn {} {} {} {} (args {}) return {} {} preds {} body {} {}\n",
+                self.vis.print(),
+                if self.exported { "exported" } else { "internal" },
+                self.name,
+                self.generics.print(),
+                args.print(),
+                self.ret.print(),
+                self.impl_clause.print(),
+                self.preds.print(),
+                self.ret.print(),
+                print_block(&self.body, self.span.as_ref()))
+    }
+}
+
+impl Print for FieldDef {
+    fn print(&self) -> String {
+        format!("{} {} {}",
+                self.vis.print(),
+                self.name,
+                self.ty.print())
+    }
+}
+
+impl Print for VariantDef {
+    fn print(&self) -> String {
+        format!("{} {}", self.name, self.args.print())
+    }
+}
+
+impl Print for Item {
+    fn print(&self) -> String {
+        match *self {
+            Item::Struct(ref s) =>
+                format!("struct {} {} {} {};",
+                        s.name,
+                        s.generics.print(),
+                        s.fields.print(),
+                        s.dtor.print()),
+            Item::Enum(ref e) =>
+                format!("enum {} {} {} {};",
+                        e.name,
+                        e.generics.print(),
+                        e.variants.print(),
+                        e.dtor.print()),
+            Item::Fn(ref f) => f.print(),
+            Item::AbstractFn(ref name, ref generics, ref args, ref ret) =>
+                format!("abstract_fn {} {} args {} return {}",
+                        name,
+                        generics.print(),
+                        args.print(),
+                        ret.print()),
+            Item::AbstractType(ref name, ref generics) =>
+                format!("abstract_type {} {}", name, generics.print()),
+            Item::ExternFn(ref abi, ref name, ref generics, ref args, ref ret) =>
+                format!("extern_fn {} {} {} (args {}) return {}",
+                        abi,
+                        name,
+                        generics.print(),
+                        args.print(),
+                        ret.print()),
+            Item::AssociatedType(ref generics, ref clause, ref ty) =>
+                format!("associated_type {} {} {}",
+                        generics.print(),
+                        clause.print(),
+                        ty.print()),
+            Item::UseDefault(ref generics, ref clause) =>
+                format!("use_default {} {}", generics.print(), clause.print()),
+            Item::Impl(ref generics, ref clause, ref preds) =>
+                format!("impl {} {} {}",
+                        generics.print(),
+                        clause.print(),
+                        preds.print()),
+            Item::Const(ref name, ref ty, ref expr) =>
+                format!("const {} {} {}", name, ty.print(), expr.print()),
+            Item::Static(ref name, ref ty, ref expr) =>
+                format!("static {} {} {}", name, ty.print(), expr.print()),
+            Item::Comment(ref msg) => format!("# {}", msg),
+            Item::Error(ref diag) => format!("# error with {}: {}", diag.item, diag.message),
+        }
+    }
+}
+
+impl Print for Entry {
+    fn print(&self) -> String {
+        match *self {
+            Entry::Single(ref opt_item) =>
+                opt_item.as_ref().map_or(format!(""), |i| i.print()),
+            Entry::Group(ref items, ref opt_tail) => {
+                let mut result = String::new();
+                for item in items.iter() {
+                    result.push_str(item.print().as_slice());
+                    result.push_str("\n");
+                }
+                if let Some(ref tail) = *opt_tail {
+                    result.push_str(tail.print().as_slice());
+                }
+                result
+            },
+        }
+    }
+}
@@ -1,10 +1,15 @@
 use std::any::Any;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::PathBuf;
 
 use rustc::metadata::csearch;
 use rustc::middle::astencode;
 use rustc::middle::const_eval;
 use rustc::middle::def;
+use rustc::middle::privacy::ExportedItems;
 use rustc::middle::region;
 use rustc::middle::subst::ParamSpace::*;
 use rustc::middle::subst::ParamSpace;
@@ -17,51 +22,119 @@ use syntax::ast::*;
 use syntax::ast_map;
 use syntax::ast_util;
 use syntax::ast_util::local_def;
+use syntax::attr::AttrMetaMethods;
 use syntax::codemap::{self, Span};
+use syntax::parse::token::special_idents;
 use syntax::ptr::P;
 use syntax::visit::Visitor;
 use syntax::visit::{FnKind, FkItemFn, FkMethod, FkFnBlock};
 use syntax::visit;
 
+use borrows;
+use filter::Filter;
+use ir;
+use json::{self, Json};
+use stubs::StubMap;
+use text::Print;
+
+/// Output format selected with `--crust-format`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+pub struct Options {
+    /// Definitions to leave out, by mangled name.
+    pub filter: Filter,
+    /// Functions whose bodies are replaced by calls to models.
+    pub stubs: StubMap,
+    pub format: Format,
+    /// Record source spans on items, statements and expressions.
+    pub spans: bool,
+    /// Write diagnostics here as JSON Lines instead of to stderr.
+    pub diagnostics_file: Option<PathBuf>,
+    /// Write the IR here instead of to stdout.
+    pub output: Option<PathBuf>,
+    /// Print debugging notes to stderr.
+    pub verbose: bool,
+    /// Check each `&mut` created in unsafe code against the reference
+    /// arguments of the enclosing function.
+    pub alias_checks: bool,
+    /// Add the events for crust's borrow-stack model (see borrows.rs).
+    pub borrow_model: bool,
+    /// Also translate the items of other crates that this one uses, as far as
+    /// their metadata carries them.
+    pub inline_deps: bool,
+}
+
 struct TransCtxt<'a, 'tcx: 'a> {
     tcx: &'a ty::ctxt<'tcx>,
+    exported_items: &'a ExportedItems,
     observed_abstract_fns: HashMap<String, DefId>,
     observed_abstract_types: HashMap<String, DefId>,
     crate_name: String,
+    stubs: StubMap,
+    format: Format,
+    // Record source spans on items, statements and expressions.
+    spans: bool,
+    diagnostics: Vec<ir::Diagnostic>,
+    out: Box<Write + 'a>,
+    verbose: bool,
+    // The function being translated, for diagnostics about its annotations.
+    cur_fn: ir::Name,
+    // Unwinding bound for loops in the current function, from its
+    // `#[crust_unwind]` attribute.
+    default_unwind: Option<usize>,
+    alias_checks: bool,
+    borrow_model: bool,
+    // Number of unsafe blocks (or fns) around the expression being translated.
+    unsafe_depth: usize,
+    // References that `&mut`s made in unsafe code are checked against.
+    ref_args: Vec<RefArg>,
+    // For naming the temporaries of `&mut` checks.
+    mut_ref_counter: usize,
+    inline_deps: bool,
+    // Definitions in other crates still to be translated, and all those queued
+    // so far.
+    dep_queue: Vec<Dep>,
+    deps_seen: HashSet<Dep>,
+    // Foreign trait impls whose `Impl` declaration has been emitted.
+    dep_impls: HashSet<DefId>,
+    // Decoded items, by their local `NodeId`, with the definition each came
+    // from.
+    inlined: HashMap<NodeId, DefId>,
+}
+
+/// A definition in another crate that the output refers to.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Dep {
+    /// A fn, method or const, translated from its inlined AST.
+    Item(DefId),
+    /// A struct or enum, translated from its type.
+    Adt(DefId),
 }
 
 trait Trans {
-    fn trans(&self, trcx: &mut TransCtxt) -> String;
+    type Out;
+    fn trans(&self, trcx: &mut TransCtxt) -> Self::Out;
 }
 
 trait TransExtra<E> {
-    fn trans_extra(&self, trcx: &mut TransCtxt, extra: E) -> String;
-}
-
-/*
-impl<T: Trans, E> TransExtra<E> for T {
-    fn trans_extra(&self, trcx: &mut TransCtxt, _: E) -> String {
-        self.trans(trcx)
-    }
-}
-*/
-
-/*
-impl<T: TransExtra<()>> Trans for T {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
-        self.trans_extra(trcx, ())
-    }
+    type Out;
+    fn trans_extra(&self, trcx: &mut TransCtxt, extra: E) -> Self::Out;
 }
-*/
 
 impl<T: Trans> Trans for Option<T> {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
-        self.as_slice().trans(trcx)
+    type Out = Option<T::Out>;
+    fn trans(&self, trcx: &mut TransCtxt) -> Option<T::Out> {
+        self.as_ref().map(|x| x.trans(trcx))
     }
 }
 
 impl<T: Trans> Trans for Vec<T> {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = Vec<T::Out>;
+    fn trans(&self, trcx: &mut TransCtxt) -> Vec<T::Out> {
         self.as_slice().trans(trcx)
     }
 }
@@ -69,81 +142,63 @@ impl<T: Trans> Trans for Vec<T> {
 struct SliceIndex;
 
 impl<T: TransExtra<usize>> TransExtra<SliceIndex> for Vec<T> {
-    fn trans_extra(&self, trcx: &mut TransCtxt, _: SliceIndex) -> String {
+    type Out = Vec<<T as TransExtra<usize>>::Out>;
+    fn trans_extra(&self, trcx: &mut TransCtxt, _: SliceIndex) -> Vec<<T as TransExtra<usize>>::Out> {
         self.as_slice().trans_extra(trcx, SliceIndex)
     }
 }
 
-impl<T: TransExtra<E>, E: Copy> TransExtra<E> for Vec<T> {
-    fn trans_extra(&self, trcx: &mut TransCtxt, extra: E) -> String {
-        self.as_slice().trans_extra(trcx, extra)
-    }
-}
-
 impl<'a, T: Trans> Trans for &'a [T] {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
-        let mut result = format!("{}", self.len());
-        for item in self.iter() {
-            result.push_str(" ");
-            result.push_str(item.trans(trcx).as_slice());
-        }
-        result
+    type Out = Vec<T::Out>;
+    fn trans(&self, trcx: &mut TransCtxt) -> Vec<T::Out> {
+        self.iter().map(|x| x.trans(trcx)).collect()
     }
 }
 
 impl<'a, T: TransExtra<usize>> TransExtra<SliceIndex> for &'a [T] {
-    fn trans_extra(&self, trcx: &mut TransCtxt, _: SliceIndex) -> String {
-        let mut result = format!("{}", self.len());
-        for (idx, item) in self.iter().enumerate() {
-            result.push_str(" ");
-            result.push_str(item.trans_extra(trcx, idx).as_slice());
-        }
-        result
-    }
-}
-
-impl<'a, T: TransExtra<E>, E: Copy> TransExtra<E> for &'a [T] {
-    fn trans_extra(&self, trcx: &mut TransCtxt, extra: E) -> String {
-        let mut result = format!("{}", self.len());
-        for item in self.iter() {
-            result.push_str(" ");
-            result.push_str(item.trans_extra(trcx, extra).as_slice());
-        }
-        result
+    type Out = Vec<<T as TransExtra<usize>>::Out>;
+    fn trans_extra(&self, trcx: &mut TransCtxt, _: SliceIndex) -> Vec<<T as TransExtra<usize>>::Out> {
+        self.iter().enumerate().map(|(idx, item)| item.trans_extra(trcx, idx)).collect()
     }
 }
 
 impl<T: Trans> Trans for P<T> {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = T::Out;
+    fn trans(&self, trcx: &mut TransCtxt) -> T::Out {
         (**self).trans(trcx)
     }
 }
 
 impl<T: TransExtra<E>, E> TransExtra<E> for P<T> {
-    fn trans_extra(&self, trcx: &mut TransCtxt, extra: E) -> String {
+    type Out = <T as TransExtra<E>>::Out;
+    fn trans_extra(&self, trcx: &mut TransCtxt, extra: E) -> <T as TransExtra<E>>::Out {
         (**self).trans_extra(trcx, extra)
     }
 }
 
 impl Trans for String {
+    type Out = String;
     fn trans(&self, trcx: &mut TransCtxt) -> String {
         self.clone()
     }
 }
 
 impl Trans for Ident {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = ir::Name;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Name {
         format!("{}", self.as_str())
     }
 }
 
 impl Trans for Name {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = ir::Name;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Name {
         format!("{}", self.as_str())
     }
 }
 
 impl Trans for ParamSpace {
+    type Out = String;
     fn trans(&self, trcx: &mut TransCtxt) -> String {
         format!("{}",
                 match *self {
@@ -154,8 +209,19 @@ impl Trans for ParamSpace {
     }
 }
 
+impl Trans for Mutability {
+    type Out = ir::Mutability;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Mutability {
+        match *self {
+            MutMutable => ir::Mutability::Mut,
+            MutImmutable => ir::Mutability::Imm,
+        }
+    }
+}
+
 impl TransExtra<ParamSpace> for Generics {
-    fn trans_extra(&self, trcx: &mut TransCtxt, space: ParamSpace) -> String {
+    type Out = ir::Generics;
+    fn trans_extra(&self, trcx: &mut TransCtxt, space: ParamSpace) -> ir::Generics {
         let mut lifetimes = vec![];
         for i in 0..self.lifetimes.len() {
             //lifetimes.push(format!("{}{}", space.trans(trcx), i));
@@ -167,9 +233,31 @@ impl TransExtra<ParamSpace> for Generics {
             ty_params.push(format!("{}{}", space.trans(trcx), i));
         }
 
-        format!("{} {}",
-                lifetimes.trans(trcx),
-                ty_params.trans(trcx))
+        ir::Generics {
+            lifetimes: lifetimes,
+            ty_params: ty_params,
+        }
+    }
+}
+
+/// The generics of a definition from another crate, named as `Generics` and
+/// `ty::Region` name those of a local one.
+impl<'tcx> TransExtra<ParamSpace> for ty::Generics<'tcx> {
+    type Out = ir::Generics;
+    fn trans_extra(&self, trcx: &mut TransCtxt, space: ParamSpace) -> ir::Generics {
+        let lifetimes = self.regions.get_slice(space).iter()
+                            .map(|r| format!("r_named_0_{}", r.def_id.node))
+                            .collect();
+
+        let prefix = space.trans(trcx);
+        let ty_params = (0..self.types.len(space))
+                            .map(|i| format!("{}{}", prefix, i))
+                            .collect();
+
+        ir::Generics {
+            lifetimes: lifetimes,
+            ty_params: ty_params,
+        }
     }
 }
 
@@ -192,15 +280,18 @@ impl TransExtra<ParamSpace> for TyParam {
 */
 
 impl<'tcx> Trans for subst::Substs<'tcx> {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
-        format!("{} {}",
-                self.regions.trans(trcx),
-                self.types.as_slice().trans(trcx))
+    type Out = ir::Substs;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Substs {
+        ir::Substs {
+            regions: self.regions.trans(trcx),
+            types: self.types.as_slice().trans(trcx),
+        }
     }
 }
 
 impl Trans for subst::RegionSubsts {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = Vec<ir::Region>;
+    fn trans(&self, trcx: &mut TransCtxt) -> Vec<ir::Region> {
         match *self {
             subst::ErasedRegions => panic!("unsupported ErasedRegions"),
             subst::NonerasedRegions(ref regions) => regions.as_slice().trans(trcx),
@@ -209,121 +300,119 @@ impl Trans for subst::RegionSubsts {
 }
 
 impl Trans for FunctionRetTy {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = ir::Return;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Return {
         match *self {
-            Return(ref t) => t.trans(trcx),
-            NoReturn(_) => format!("bottom"),
-            DefaultReturn(_) => format!("unit"),
+            Return(ref t) => ir::Return::Value(t.trans(trcx)),
+            NoReturn(_) => ir::Return::Diverging,
+            DefaultReturn(_) => ir::Return::Default,
         }
     }
 }
 
 
 impl Trans for FnDecl {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
-        format!("(args {}) return {}",
-                self.inputs.trans(trcx),
-                self.output.trans(trcx))
+    type Out = (Vec<ir::Pat>, ir::Return);
+    fn trans(&self, trcx: &mut TransCtxt) -> (Vec<ir::Pat>, ir::Return) {
+        (self.inputs.trans(trcx),
+         self.output.trans(trcx))
     }
 }
 
 impl Trans for Arg {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
-        let ty_str = self.ty.trans(trcx);
-        self.pat.trans_extra(trcx, ty_str)
+    type Out = ir::Pat;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Pat {
+        let ty = self.ty.trans(trcx);
+        self.pat.trans_extra(trcx, ty)
     }
 }
 
 impl Trans for Ty {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = ir::Ty;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Ty {
         match trcx.tcx.ast_ty_to_ty_cache.borrow().get(&self.id) {
             Some(ref t) => t.trans(trcx),
-            _ => format!("[[no_ty_to_ty {}]]", self.repr(trcx.tcx)),
+            _ => ir::Ty::Missing(self.repr(trcx.tcx)),
         }
     }
 }
 
 impl<'tcx> Trans for ty::Ty<'tcx> {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = ir::Ty;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Ty {
         use rustc::middle::ty::sty::*;
-        let s = match self.sty {
-            ty_bool => format!("bool"),
-            ty_char => format!("char"),
-            ty_int(TyIs) => format!("int size"),
-            ty_int(ity) => format!("int {}",
+        match self.sty {
+            ty_bool => ir::Ty::Bool,
+            ty_char => ir::Ty::Char,
+            ty_int(TyIs) => ir::Ty::Int(ir::IntSize::PtrSize),
+            ty_int(ity) => ir::Ty::Int(ir::IntSize::Bits(
                                    match ity {
                                        TyI64 => 64us,
                                        TyI32 => 32,
                                        TyI16 => 16,
                                        TyI8 => 8,
                                        TyIs => unreachable!(),
-                                   }),
-            ty_uint(TyUs) => format!("uint size"),
-            ty_uint(uty) => format!("uint {}",
+                                   })),
+            ty_uint(TyUs) => ir::Ty::Uint(ir::IntSize::PtrSize),
+            ty_uint(uty) => ir::Ty::Uint(ir::IntSize::Bits(
                                     match uty {
                                         TyU64 => 64us,
                                         TyU32 => 32,
                                         TyU16 => 16,
                                         TyU8 => 8,
                                         TyUs => unreachable!(),
-                                    }),
-            ty_float(fty) => format!("float {}",
+                                    })),
+            ty_float(fty) => ir::Ty::Float(
                                      match fty {
                                          TyF64 => 64us,
                                          TyF32 => 32,
                                      }),
             // TODO: handle substs
-            ty_enum(did, ref substs) => format!("adt {} {}",
-                                                mangled_def_name(trcx, did),
-                                                substs.trans(trcx)),
+            ty_enum(did, ref substs) => {
+                note_dep(trcx, Dep::Adt(did));
+                ir::Ty::Adt(mangled_def_name(trcx, did),
+                            substs.trans(trcx))
+            },
             // ty_uniq
-            ty_str => format!("str"),
-            ty_vec(ref ty, None) => format!("vec {}",
-                                            ty.trans(trcx)),
-            ty_vec(ref ty, Some(len)) => format!("fixed_vec {} {}",
-                                                 len,
-                                                 ty.trans(trcx)),
-            ty_ptr(mt) => format!("{} {}",
-                                  match mt.mutbl {
-                                      MutMutable => "ptr_mut",
-                                      MutImmutable => "ptr",
-                                  },
-                                  mt.ty.trans(trcx)),
-            ty_rptr(ref r, mt) => format!("{} {} {}",
-                                          match mt.mutbl {
-                                              MutMutable => "ref_mut",
-                                              MutImmutable => "ref",
-                                          },
-                                          r.trans(trcx),
-                                          mt.ty.trans(trcx)),
+            ty_str => ir::Ty::Str,
+            ty_vec(ref ty, None) => ir::Ty::Vec(Box::new(ty.trans(trcx))),
+            ty_vec(ref ty, Some(len)) => ir::Ty::FixedVec(len,
+                                                          Box::new(ty.trans(trcx))),
+            ty_ptr(mt) => ir::Ty::Ptr(mt.mutbl.trans(trcx),
+                                      Box::new(mt.ty.trans(trcx))),
+            ty_rptr(ref r, mt) => ir::Ty::Ref(r.trans(trcx),
+                                              mt.mutbl.trans(trcx),
+                                              Box::new(mt.ty.trans(trcx))),
             //ty_bare_fn(_, _) => format!("fn"),
             // ty_closure
             // ty_trait
             // TODO: handle substs
-            ty_struct(did, ref substs) => format!("adt {} {}",
-                                                  mangled_def_name(trcx, did),
-                                                  substs.trans(trcx)),
+            ty_struct(did, ref substs) => {
+                note_dep(trcx, Dep::Adt(did));
+                ir::Ty::Adt(mangled_def_name(trcx, did),
+                            substs.trans(trcx))
+            },
             // ty_unboxed_closure
-            ty_tup(ref ts) if ts.len() == 0 => format!("unit"),
-            ty_tup(ref ts) => format!("tuple {}", ts.trans(trcx)),
-            ty_projection(ref proj) => proj.trans(trcx),
+            ty_tup(ref ts) if ts.len() == 0 => ir::Ty::Unit,
+            ty_tup(ref ts) => ir::Ty::Tuple(ts.trans(trcx)),
+            ty_projection(ref proj) => ir::Ty::Abstract(proj.trans(trcx)),
             ty_param(ref param) => {
-                format!("var {}{}",
-                        param.space.trans(trcx),
-                        param.idx)
+                ir::Ty::Var(format!("{}{}",
+                                    param.space.trans(trcx),
+                                    param.idx))
             },
             // ty_open
             // ty_infer
             // ty_err
             _ => panic!("unrecognized type: {:?}", self),
 
-        };
-        format!("[{}]", s)
+        }
     }
 }
 
 impl<'tcx> Trans for ty::ProjectionTy<'tcx> {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = ir::Projection;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Projection {
         let trait_did = self.trait_ref.def_id;
         let name = format!("{}${}",
                            mangled_def_name(trcx, self.trait_ref.def_id),
@@ -331,14 +420,16 @@ impl<'tcx> Trans for ty::ProjectionTy<'tcx> {
 
         trcx.observed_abstract_types.insert(name.clone(), trait_did);
 
-        format!("abstract {} {}",
-                name,
-                self.trait_ref.substs.trans(trcx))
+        ir::Projection {
+            name: name,
+            substs: self.trait_ref.substs.trans(trcx),
+        }
     }
 }
 
 impl Trans for ty::Region {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = ir::Region;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Region {
         match *self {
             ty::ReEarlyBound(id, _space, _idx, _name) => {
                 format!("r_named_0_{}", id)
@@ -368,7 +459,8 @@ impl Trans for ty::Region {
 }
 
 impl TransExtra<Option<NodeId>> for ty::BoundRegion {
-    fn trans_extra(&self, trcx: &mut TransCtxt, binder_id: Option<NodeId>) -> String {
+    type Out = ir::Region;
+    fn trans_extra(&self, trcx: &mut TransCtxt, binder_id: Option<NodeId>) -> ir::Region {
         match *self {
             ty::BrAnon(idx) => format!("r_anon_{}", idx),
             ty::BrNamed(did, _) =>
@@ -391,58 +483,195 @@ impl TransExtra<Option<NodeId>> for ty::BoundRegion {
     }
 }
 
-impl Trans for Block {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
-        format!("{} {{\n{}\t{}\n}}\n",
-                match self.rules {
-                    DefaultBlock => "block",
-                    UnsafeBlock(_) => "unsafe",
+fn trans_span(trcx: &mut TransCtxt, sp: Span) -> Option<ir::Span> {
+    if !trcx.spans {
+        return None;
+    }
+    diag_span(trcx, sp)
+}
+
+/// Like `trans_span`, but regardless of `--crust-spans`.  Diagnostics always
+/// carry a position.
+fn diag_span(trcx: &mut TransCtxt, sp: Span) -> Option<ir::Span> {
+    source_span(trcx.tcx.sess.codemap(), sp)
+}
+
+pub fn source_span(cm: &codemap::CodeMap, sp: Span) -> Option<ir::Span> {
+    if sp == codemap::DUMMY_SP {
+        return None;
+    }
+    let lo = cm.lookup_char_pos(sp.lo);
+    let hi = cm.lookup_char_pos(sp.hi);
+    Some(ir::Span {
+        file: lo.file.name.clone(),
+        lo_line: lo.line,
+        lo_col: lo.col.to_usize(),
+        hi_line: hi.line,
+        hi_col: hi.col.to_usize(),
+    })
+}
+
+fn unit_placeholder(tag: &'static str) -> ir::Expr {
+    ir::Expr::new(ir::Ty::Unit,
+                  ir::ExprKind::Placeholder(tag))
+}
+
+fn trans_block_body(trcx: &mut TransCtxt,
+                    unsafety: bool,
+                    block: &Block,
+                    tag: &'static str) -> ir::Block {
+    if unsafety {
+        trcx.unsafe_depth += 1;
+    }
+    let result = ir::Block {
+        unsafety: unsafety,
+        stmts: block.stmts.trans(trcx),
+        expr: Box::new(block.expr.as_ref().map(|e| e.trans(trcx))
+                           .unwrap_or(unit_placeholder(tag))),
+    };
+    if unsafety {
+        trcx.unsafe_depth -= 1;
+    }
+    result
+}
+
+/// Translate a loop body, moving the `__crust::unwind` and
+/// `__crust::invariant` calls at its top into the loop's annotations.
+fn trans_loop_body(trcx: &mut TransCtxt, body: &Block) -> (ir::Block, ir::LoopInfo) {
+    let mut block = body.trans(trcx);
+    let mut info = ir::LoopInfo {
+        invariants: Vec::new(),
+        unwind: trcx.default_unwind,
+    };
+    let mut count = 0;
+    for stmt in block.stmts.iter() {
+        let (name, args) = match *stmt {
+            ir::Stmt::Expr(ir::Expr { kind: ir::ExprKind::Call(ref name, _, ref args), .. }) =>
+                (name, args),
+            _ => break,
+        };
+        if *name == "__crust$invariant" {
+            info.invariants.push(args[0].clone());
+        } else if *name == "__crust$unwind" {
+            let bound = match args[0].kind {
+                ir::ExprKind::SimpleLiteral(ref lit) => lit.parse::<usize>().ok(),
+                _ => None,
+            };
+            match bound {
+                Some(n) => info.unwind = Some(n),
+                None => {
+                    // The IR statements start out one to one with the AST's.
+                    let sp = body.stmts.get(count).map_or(body.span, |s| s.span);
+                    annotation_error(trcx, sp, "unwind",
+                                     "`__crust::unwind` needs an integer literal bound",
+                                     "write the bound as a literal, e.g. `__crust::unwind(10)`");
                 },
-                self.stmts.trans(trcx),
-                self.expr.as_ref().map(|e| e.trans(trcx))
-                    .unwrap_or(format!("[unit] simple_literal _Block")))
+            }
+        } else {
+            break;
+        }
+        count += 1;
+    }
+    block.stmts = block.stmts[count..].to_vec();
+    (block, info)
+}
+
+/// The bound from a `#[crust_unwind = N]` attribute on the item `id`.
+fn unwind_attr(trcx: &mut TransCtxt, id: NodeId) -> Option<usize> {
+    let tcx = trcx.tcx;
+    let attr = match tcx.map.attrs(id).iter().find(|a| a.check_name("crust_unwind")) {
+        Some(attr) => attr,
+        None => return None,
+    };
+    let bound = match attr.node.value.node {
+        MetaNameValue(_, ref lit) => match lit.node {
+            LitInt(n, _) => Some(n as usize),
+            LitStr(ref s, _) => s.parse::<usize>().ok(),
+            _ => None,
+        },
+        _ => None,
+    };
+    if bound.is_none() {
+        annotation_error(trcx, attr.span, "crust_unwind",
+                         "malformed `#[crust_unwind]` attribute",
+                         "give the bound as an integer, e.g. `#[crust_unwind = 10]`");
+    }
+    bound
+}
+
+/// Report a malformed crust annotation in the current function.  Unlike a
+/// translation failure, this doesn't stop the item from being translated; the
+/// annotation is ignored.
+fn annotation_error(trcx: &mut TransCtxt, sp: Span, construct: &str, message: &str, help: &str) {
+    let span = diag_span(trcx, sp);
+    let diag = ir::Diagnostic {
+        item: trcx.cur_fn.clone(),
+        span: span,
+        construct: String::from_str(construct),
+        message: String::from_str(message),
+        help: Some(String::from_str(help)),
+    };
+    trcx.diagnostics.push(diag);
+}
+
+impl Trans for Block {
+    type Out = ir::Block;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Block {
+        let unsafety = match self.rules {
+            DefaultBlock => false,
+            UnsafeBlock(_) => true,
+        };
+        trans_block_body(trcx, unsafety, self, "_Block")
     }
 }
 
 impl Trans for Stmt {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = ir::Stmt;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Stmt {
         match self.node {
-            StmtDecl(ref d, _id) => format!("\t{};\n", d.trans(trcx)),
-            StmtExpr(ref e, _id) => format!("\texpr {};\n", e.trans(trcx)),
-            StmtSemi(ref e, _id) => format!("\texpr {};\n", e.trans(trcx)),
+            StmtDecl(ref d, _id) => d.trans(trcx),
+            StmtExpr(ref e, _id) => ir::Stmt::Expr(e.trans(trcx)),
+            StmtSemi(ref e, _id) => ir::Stmt::Expr(e.trans(trcx)),
             StmtMac(..) => panic!("expected no macros, but saw StmtMac"),
         }
     }
 }
 
 impl Trans for Decl {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = ir::Stmt;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Stmt {
         match self.node {
             DeclLocal(ref local) => local.trans(trcx),
             // TODO: handle inner items
-            DeclItem(_) => format!("expr ([unit] simple_literal _DeclItem)"),
+            DeclItem(_) => ir::Stmt::Expr(
+                    ir::Expr::new(ir::Ty::Unit,
+                                  ir::ExprKind::SimpleLiteral(format!("_DeclItem")))),
         }
     }
 }
 
 impl Trans for Local {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
-        format!("let {} {}",
-                self.pat.trans(trcx),
-                self.init.trans(trcx))
+    type Out = ir::Stmt;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Stmt {
+        ir::Stmt::Let(self.pat.trans(trcx),
+                      self.init.trans(trcx),
+                      trans_span(trcx, self.span))
     }
 }
 
 impl Trans for Field {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
-        format!("{} {}",
-                self.ident.node.trans(trcx),
-                self.expr.trans(trcx))
+    type Out = ir::Field;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Field {
+        ir::Field {
+            name: self.ident.node.trans(trcx),
+            expr: self.expr.trans(trcx),
+        }
     }
 }
 
 impl Trans for Lifetime {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = ir::Region;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Region {
         use rustc::middle::resolve_lifetime::DefRegion::*;
         match *trcx.tcx.named_region_map.get(&self.id)
                   .expect("missing DefRegion") {
@@ -457,9 +686,10 @@ impl Trans for Lifetime {
 
 fn trans_method_call(trcx: &mut TransCtxt,
                      callee: &MethodCallee,
-                     args: Vec<String>) -> String {
+                     args: Vec<ir::Expr>) -> ir::ExprKind {
     let name = match callee.origin {
         MethodOrigin::MethodStatic(did) => {
+            note_dep(trcx, Dep::Item(did));
             mangled_def_name(trcx, did)
         },
         MethodOrigin::MethodTypeParam(ref mp) => {
@@ -478,309 +708,330 @@ fn trans_method_call(trcx: &mut TransCtxt,
         },
         _ => panic!("unsupported MethodOrigin variant"),
     };
-    format!("call {} {} {}",
-            name,
-            callee.substs.trans(trcx),
-            args.trans(trcx))
+    ir::ExprKind::Call(name,
+                       callee.substs.trans(trcx),
+                       args)
 }
 
-impl Trans for Expr {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
-        let mut add_ty = true;
-
-        let variant = match self.node {
-            ExprBox(..) => panic!("unsupported ExprBox"),
+fn bool_literal_pat(value: bool) -> ir::Pat {
+    ir::Pat {
+        ty: ir::Ty::Bool,
+        kind: ir::PatKind::SimpleLiteral(format!("{}", value)),
+    }
+}
 
-            ExprVec(ref expr_list) => {
-                format!("vec {}", expr_list.trans(trcx))
+impl Trans for Expr {
+    type Out = ir::Expr;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Expr {
+        let unadjusted = match self.node {
+            // Parens have no node of their own in the IR.
+            ExprParen(ref expr) => expr.trans(trcx),
+            _ => {
+                let kind = trans_expr_kind(trcx, self);
+                let expr_ty = trcx.tcx.node_types()[&self.id];
+                ir::Expr::new(expr_ty.trans(trcx),
+                              kind)
             },
+        };
 
-            ExprCall(ref func, ref args) => {
-                if let Some(struct_name) = find_tuple_struct_ctor(trcx, func.id) {
-                    let mut fields = Vec::new();
-                    for (i, a) in args.iter().enumerate() {
-                        fields.push(format!("field{} {}", i, a.trans(trcx)));
-                    }
-                    format!("struct_literal {}", fields.trans(trcx))
-                } else if let Some((var_name, var_idx)) = find_variant(trcx, func.id) {
-                    format!("enum_literal {} {} {}",
-                            var_name,
-                            var_idx,
-                            args.trans(trcx))
-                } else {
-                    let (did, is_abstract) = match trcx.tcx.def_map.borrow()[&func.id].base_def {
-                        def::DefMethod(did, prov) => match prov {
-                            def::MethodProvenance::FromTrait(_) => (did, true),
-                            _ => (did, false),
-                        },
-                        def => (def.def_id(), false),
-                    };
-                    let name = mangled_def_name(trcx, did);
-                    let substs = match trcx.tcx.item_substs.borrow().get(&func.id) {
-                        Some(item_substs) => item_substs.substs.trans(trcx),
-                        None => format!("0 0"),
-                    };
-                    if is_abstract {
-                        trcx.observed_abstract_fns.insert(name.clone(), did);
-                    }
-                    format!("call {} {} {}",
-                            name,
-                            substs,
-                            args.trans(trcx))
-                }
-            },
+        let mut result = match trcx.tcx.adjustments.borrow().get(&self.id) {
+            None => unadjusted,
+            Some(adj) => adjust_expr(trcx, adj, self, unadjusted),
+        };
+        result.span = trans_span(trcx, self.span);
+        result
+    }
+}
 
-            ExprMethodCall(name, ref tys, ref args) => {
-                let call = MethodCall::expr(self.id);
-                let map = trcx.tcx.method_map.borrow();
-                let callee = &map[&call];
-                let arg_strs = args.iter().map(|x| x.trans(trcx)).collect();
-                assert!(tys.len() == 0); // no idea what `tys` does
-                trans_method_call(trcx, callee, arg_strs)
-            },
+fn trans_expr_kind(trcx: &mut TransCtxt, ex: &Expr) -> ir::ExprKind {
+    match ex.node {
+        ExprBox(..) => panic!("unsupported ExprBox"),
 
-            ExprTup(ref xs) if xs.len() == 0 => format!("simple_literal _"),
-            ExprTup(ref xs) => format!("tuple_literal {}", xs.trans(trcx)),
-
-            ExprBinary(op, ref a, ref b) => {
-                match trcx.tcx.method_map.borrow().get(&MethodCall::expr(self.id)) {
-                    Some(callee) => {
-                        let b_str = if ast_util::is_by_value_binop(op.node) {
-                            b.trans(trcx)
-                        } else {
-                            let unadjusted = b.trans(trcx);
-                            do_auto_ref(trcx, &**b, unadjusted)
-                        };
-                        let arg_strs = vec![a.trans(trcx), b_str];
-                        trans_method_call(trcx, callee, arg_strs)
-                    },
-                    None => {
-                        format!("binop {:?} {} {}",
-                                op.node,
-                                a.trans(trcx),
-                                b.trans(trcx))
-                    },
-                }
-            },
+        ExprVec(ref expr_list) => {
+            ir::ExprKind::Vec(expr_list.trans(trcx))
+        },
 
-            ExprUnary(op, ref a) => {
-                match trcx.tcx.method_map.borrow().get(&MethodCall::expr(self.id)) {
-                    Some(callee) => {
-                        let arg_strs = vec![a.trans(trcx)];
-                        let meth_call = trans_method_call(trcx, callee, arg_strs);
-                        match op {
-                            UnDeref => format!("deref ([ref r_dummy {}] {})",
-                                               trcx.tcx.node_types()[&self.id].trans(trcx),
-                                               meth_call),
-                            _ => meth_call
-                        }
-                    },
-                    None => {
-                        match op {
-                            UnDeref => format!("deref {}", a.trans(trcx)),
-                            _ => format!("unop {:?} {}",
-                                         op,
-                                         a.trans(trcx)),
-                        }
+        ExprCall(ref func, ref args) => {
+            if let Some(struct_name) = find_tuple_struct_ctor(trcx, func.id) {
+                let mut fields = Vec::new();
+                for (i, a) in args.iter().enumerate() {
+                    fields.push(ir::Field {
+                        name: format!("field{}", i),
+                        expr: a.trans(trcx),
+                    });
+                }
+                ir::ExprKind::StructLiteral(fields)
+            } else if let Some((var_name, var_idx)) = find_variant(trcx, func.id) {
+                ir::ExprKind::EnumLiteral(var_name,
+                                          var_idx,
+                                          args.trans(trcx))
+            } else {
+                let (did, is_abstract) = match trcx.tcx.def_map.borrow()[&func.id].base_def {
+                    def::DefMethod(did, prov) => match prov {
+                        def::MethodProvenance::FromTrait(_) => (did, true),
+                        _ => (did, false),
                     },
+                    def => (def.def_id(), false),
+                };
+                note_dep(trcx, Dep::Item(did));
+                let name = mangled_def_name(trcx, did);
+                let substs = match trcx.tcx.item_substs.borrow().get(&func.id) {
+                    Some(item_substs) => item_substs.substs.trans(trcx),
+                    None => ir::Substs { regions: vec![], types: vec![] },
+                };
+                if is_abstract {
+                    trcx.observed_abstract_fns.insert(name.clone(), did);
                 }
-            },
+                ir::ExprKind::Call(name,
+                                   substs,
+                                   args.trans(trcx))
+            }
+        },
 
-            ExprLit(ref lit) =>
-                format!("simple_literal {}", lit.trans(trcx)),
+        ExprMethodCall(name, ref tys, ref args) => {
+            let call = MethodCall::expr(ex.id);
+            let map = trcx.tcx.method_map.borrow();
+            let callee = &map[&call];
+            let arg_exprs = args.iter().map(|x| x.trans(trcx)).collect();
+            assert!(tys.len() == 0); // no idea what `tys` does
+            trans_method_call(trcx, callee, arg_exprs)
+        },
 
-            ExprCast(ref e, _) =>
-                format!("cast {}", e.trans(trcx)),
+        ExprTup(ref xs) if xs.len() == 0 => ir::ExprKind::SimpleLiteral(format!("_")),
+        ExprTup(ref xs) => ir::ExprKind::TupleLiteral(xs.trans(trcx)),
+
+        ExprBinary(op, ref a, ref b) => {
+            match trcx.tcx.method_map.borrow().get(&MethodCall::expr(ex.id)) {
+                Some(callee) => {
+                    let b_expr = if ast_util::is_by_value_binop(op.node) {
+                        b.trans(trcx)
+                    } else {
+                        let unadjusted = b.trans(trcx);
+                        do_auto_ref(trcx, &**b, unadjusted)
+                    };
+                    let arg_exprs = vec![a.trans(trcx), b_expr];
+                    trans_method_call(trcx, callee, arg_exprs)
+                },
+                None => {
+                    ir::ExprKind::BinOp(format!("{:?}", op.node),
+                                        Box::new(a.trans(trcx)),
+                                        Box::new(b.trans(trcx)))
+                },
+            }
+        },
 
-            ExprIf(ref cond, ref then, ref opt_else) => {
-                let ty = trcx.tcx.node_types()[&then.id];
+        ExprUnary(op, ref a) => {
+            match trcx.tcx.method_map.borrow().get(&MethodCall::expr(ex.id)) {
+                Some(callee) => {
+                    let arg_exprs = vec![a.trans(trcx)];
+                    let meth_call = trans_method_call(trcx, callee, arg_exprs);
+                    match op {
+                        UnDeref => {
+                            let ty = trcx.tcx.node_types()[&ex.id].trans(trcx);
+                            let ref_ty = ir::Ty::Ref(format!("r_dummy"),
+                                                     ir::Mutability::Imm,
+                                                     Box::new(ty));
+                            ir::ExprKind::Deref(Box::new(ir::Expr::new(ref_ty, meth_call)))
+                        },
+                        _ => meth_call
+                    }
+                },
+                None => {
+                    match op {
+                        UnDeref => ir::ExprKind::Deref(Box::new(a.trans(trcx))),
+                        _ => ir::ExprKind::UnOp(format!("{:?}", op),
+                                                Box::new(a.trans(trcx))),
+                    }
+                },
+            }
+        },
 
-                // NB: `then` is a Block, but opt_else is `Option<Expr>`.
-                format!("match {} 2 \
-                        {{ ([bool] simple_literal true) >> ({} {}) }} \
-                        {{ ([bool] simple_literal false) >> {} }}",
-                        cond.trans(trcx),
-                        ty.trans(trcx),
-                        then.trans(trcx),
-                        opt_else.as_ref().map_or(format!("[unit] simple_literal _ExprIf"),
-                                                 |e| e.trans(trcx)))
-            },
+        ExprLit(ref lit) =>
+            ir::ExprKind::SimpleLiteral(lit.trans(trcx)),
+
+        ExprCast(ref e, _) =>
+            ir::ExprKind::Cast(Box::new(e.trans(trcx))),
+
+        ExprIf(ref cond, ref then, ref opt_else) => {
+            let ty = trcx.tcx.node_types()[&then.id];
+
+            // NB: `then` is a Block, but opt_else is `Option<Expr>`.
+            let cond_expr = cond.trans(trcx);
+            let then_expr = ir::Expr::new(ty.trans(trcx),
+                                          ir::ExprKind::Block(then.trans(trcx)));
+            let else_expr = opt_else.as_ref().map_or(unit_placeholder("_ExprIf"),
+                                                     |e| e.trans(trcx));
+            ir::ExprKind::Match(Box::new(cond_expr),
+                                vec![ir::MatchArm { pat: bool_literal_pat(true),
+                                                    body: then_expr },
+                                     ir::MatchArm { pat: bool_literal_pat(false),
+                                                    body: else_expr }])
+        },
 
-            ExprIfLet(..) => panic!("unsupported ExprIfLet"),
+        ExprIfLet(..) => panic!("unsupported ExprIfLet"),
 
-            ExprWhile(ref guard, ref body, _) =>
-                format!("while {} [unit] {}",
-                        guard.trans(trcx),
-                        body.trans(trcx)),
+        ExprWhile(ref guard, ref body, _) => {
+            let guard = guard.trans(trcx);
+            let (body, info) = trans_loop_body(trcx, &**body);
+            ir::ExprKind::While(Box::new(guard), body, info)
+        },
 
-            ExprWhileLet(..) => panic!("unsupported ExprWhileLet"),
+        ExprWhileLet(..) => panic!("unsupported ExprWhileLet"),
 
-            ExprForLoop(ref patt, ref expr, ref body, _ident) =>
-                format!("for {} {} {}",
-                        patt.trans(trcx),
-                        expr.trans(trcx),
-                        body.trans(trcx)),
+        ExprForLoop(ref patt, ref expr, ref body, _ident) => {
+            let patt = patt.trans(trcx);
+            let expr = expr.trans(trcx);
+            let (body, info) = trans_loop_body(trcx, &**body);
+            ir::ExprKind::For(patt, Box::new(expr), body, info)
+        },
 
-            ExprLoop(ref body, _ident) =>
-                format!("while ([bool] simple_literal true) [unit] {}",
-                        body.trans(trcx)),
+        ExprLoop(ref body, _ident) => {
+            let always = ir::Expr::new(ir::Ty::Bool,
+                                       ir::ExprKind::SimpleLiteral(format!("true")));
+            let (body, info) = trans_loop_body(trcx, &**body);
+            ir::ExprKind::While(Box::new(always), body, info)
+        },
 
-            ExprMatch(ref expr, ref arms, _src) => {
-                let mut arm_strs = vec![];
-                for arm in arms.iter() {
-                    for pat in arm.pats.iter() {
-                        arm_strs.push(format!("{{ {} >> {} }}",
-                                              pat.trans(trcx),
-                                              arm.body.trans(trcx)));
-                    }
+        ExprMatch(ref expr, ref arms, _src) => {
+            let mut ir_arms = vec![];
+            for arm in arms.iter() {
+                for pat in arm.pats.iter() {
+                    ir_arms.push(ir::MatchArm {
+                        pat: pat.trans(trcx),
+                        body: arm.body.trans(trcx),
+                    });
                 }
-                format!("match {} {}",
-                        expr.trans(trcx),
-                        arm_strs.trans(trcx))
-            },
+            }
+            ir::ExprKind::Match(Box::new(expr.trans(trcx)),
+                                ir_arms)
+        },
 
-            ExprClosure(..) => panic!("unsupported ExprClosure"),
-
-            ExprBlock(ref b) => b.trans(trcx),
-
-            ExprAssign(ref l, ref r) =>
-                format!("assign {} {}",
-                        l.trans(trcx),
-                        r.trans(trcx)),
-
-            ExprAssignOp(ref op, ref rhs, ref operand) =>
-                format!("assign_op {:?} {} {}",
-                        op.node,
-                        rhs.trans(trcx),
-                        operand.trans(trcx)),
-
-            ExprField(ref expr, field) =>
-                format!("field {} {}",
-                        expr.trans(trcx),
-                        field.node.as_str()),
-
-            ExprTupField(ref expr, index) =>
-                format!("field {} field{}",
-                        expr.trans(trcx),
-                        index.node),
-
-            ExprIndex(ref arr, ref idx) => {
-                match trcx.tcx.method_map.borrow().get(&MethodCall::expr(self.id)) {
-                    Some(callee) => {
-                        /*
-                        let idx_ty = trcx.tcx.node_types()[&idx.id];
-                        let idx_str = format!("({} addr_of {})",
-                                              auto_ref_ty(trcx, &**idx, None, idx_ty)
-                                                  .trans(trcx),
-                                              idx.trans(trcx));
-                                              */
-                        let arg_strs = vec![arr.trans(trcx), idx.trans(trcx)];
-                        let call_str = trans_method_call(trcx, callee, arg_strs);
-
-                        let mutbl = match ty::ty_fn_ret(callee.ty).0 {
-                            ty::FnConverging(ty) => match ty.sty {
-                                ty::ty_rptr(_, mt) => mt.mutbl,
-                                _ => panic!("unexpected ty variant"),
-                            },
-                            ty::FnDiverging => panic!("unexpected FnDiverging"),
-                        };
-                        let result_ty = trcx.tcx.node_types()[&self.id];
-                        let result_ptr_ty = auto_ref_ty(trcx, self, Some(mutbl), result_ty);
-                        format!("deref ({} {})",
-                                result_ptr_ty.trans(trcx),
-                                call_str)
-                    },
-                    None => {
-                        format!("index {} {}",
-                                arr.trans(trcx),
-                                idx.trans(trcx))
-                    },
-                }
-            },
+        ExprClosure(..) => panic!("unsupported ExprClosure"),
+
+        ExprBlock(ref b) => ir::ExprKind::Block(b.trans(trcx)),
+
+        ExprAssign(ref l, ref r) =>
+            ir::ExprKind::Assign(Box::new(l.trans(trcx)),
+                                 Box::new(r.trans(trcx))),
+
+        ExprAssignOp(ref op, ref rhs, ref operand) =>
+            ir::ExprKind::AssignOp(format!("{:?}", op.node),
+                                   Box::new(rhs.trans(trcx)),
+                                   Box::new(operand.trans(trcx))),
+
+        ExprField(ref expr, field) =>
+            ir::ExprKind::Field(Box::new(expr.trans(trcx)),
+                                field.node.trans(trcx)),
+
+        ExprTupField(ref expr, index) =>
+            ir::ExprKind::Field(Box::new(expr.trans(trcx)),
+                                format!("field{}", index.node)),
+
+        ExprIndex(ref arr, ref idx) => {
+            match trcx.tcx.method_map.borrow().get(&MethodCall::expr(ex.id)) {
+                Some(callee) => {
+                    /*
+                    let idx_ty = trcx.tcx.node_types()[&idx.id];
+                    let idx_str = format!("({} addr_of {})",
+                                          auto_ref_ty(trcx, &**idx, None, idx_ty)
+                                              .trans(trcx),
+                                          idx.trans(trcx));
+                                          */
+                    let arg_exprs = vec![arr.trans(trcx), idx.trans(trcx)];
+                    let call = trans_method_call(trcx, callee, arg_exprs);
+
+                    let mutbl = match ty::ty_fn_ret(callee.ty).0 {
+                        ty::FnConverging(ty) => match ty.sty {
+                            ty::ty_rptr(_, mt) => mt.mutbl,
+                            _ => panic!("unexpected ty variant"),
+                        },
+                        ty::FnDiverging => panic!("unexpected FnDiverging"),
+                    };
+                    let result_ty = trcx.tcx.node_types()[&ex.id];
+                    let result_ptr_ty = auto_ref_ty(trcx, ex, Some(mutbl), result_ty);
+                    ir::ExprKind::Deref(Box::new(ir::Expr::new(result_ptr_ty.trans(trcx),
+                                                               call)))
+                },
+                None => {
+                    ir::ExprKind::Index(Box::new(arr.trans(trcx)),
+                                        Box::new(idx.trans(trcx)))
+                },
+            }
+        },
 
-            ExprRange(ref opt_low, ref opt_high) => {
-                format!("range {} {}", opt_low.trans(trcx), opt_high.trans(trcx))
-            },
+        ExprRange(ref opt_low, ref opt_high) => {
+            ir::ExprKind::Range(opt_low.trans(trcx).map(|e| Box::new(e)),
+                                opt_high.trans(trcx).map(|e| Box::new(e)))
+        },
 
-            ExprPath(ref opt_qself, ref path) => {
-                assert!(opt_qself.is_none());
-                if let Some((var_name, var_idx)) = find_variant(trcx, self.id) {
-                    format!("enum_literal {} {} 0",
-                            var_name,
-                            var_idx)
-                } else {
-                    use rustc::middle::def::*;
-                    match trcx.tcx.def_map.borrow()[&self.id].base_def {
-                        DefLocal(..) =>
-                            format!("var {}",
-                                    path.segments[path.segments.len() - 1]
-                                        .identifier.as_str()),
-                        DefStruct(did) =>
-                            format!("struct_literal 0"),
-                        DefStatic(did, _) =>
-                            format!("var {}", mangled_def_name(trcx, did)),
-                        d => format!("const {}",
-                                     mangled_def_name(trcx, d.def_id())),
-                    }
+        ExprPath(ref opt_qself, ref path) => {
+            assert!(opt_qself.is_none());
+            if let Some((var_name, var_idx)) = find_variant(trcx, ex.id) {
+                ir::ExprKind::EnumLiteral(var_name,
+                                          var_idx,
+                                          vec![])
+            } else {
+                use rustc::middle::def::*;
+                match trcx.tcx.def_map.borrow()[&ex.id].base_def {
+                    DefLocal(..) =>
+                        ir::ExprKind::Var(format!("{}",
+                                                  path.segments[path.segments.len() - 1]
+                                                      .identifier.as_str())),
+                    DefStruct(did) =>
+                        ir::ExprKind::StructLiteral(vec![]),
+                    DefStatic(did, _) =>
+                        ir::ExprKind::Var(mangled_def_name(trcx, did)),
+                    d => {
+                        note_dep(trcx, Dep::Item(d.def_id()));
+                        ir::ExprKind::Const(mangled_def_name(trcx, d.def_id()))
+                    },
                 }
-            },
-
-            ExprAddrOf(_mutbl, ref expr) =>
-                format!("addr_of {}", expr.trans(trcx)),
+            }
+        },
 
-            ExprBreak(ref opt_ident) => {
-                assert!(opt_ident.is_none());
-                format!("break")
-            },
+        ExprAddrOf(MutImmutable, ref expr) =>
+            ir::ExprKind::AddrOf(Box::new(expr.trans(trcx))),
 
-            ExprAgain(ref opt_ident) => {
-                assert!(opt_ident.is_none());
-                format!("continue")
-            },
+        ExprAddrOf(MutMutable, ref expr) => {
+            let kind = ir::ExprKind::AddrOf(Box::new(expr.trans(trcx)));
+            let ty = trcx.tcx.node_types()[&ex.id].trans(trcx);
+            check_mut_ref(trcx, ir::Expr::new(ty, kind)).kind
+        },
 
-            ExprRet(ref opt_expr) =>
-                format!("return {}",
-                        opt_expr.as_ref().map(|e| e.trans(trcx))
-                                .unwrap_or(format!("[unit] simple_literal _ExprRet"))),
+        ExprBreak(ref opt_ident) => {
+            assert!(opt_ident.is_none());
+            ir::ExprKind::Break
+        },
 
-            ExprInlineAsm(..) => panic!("unsupported ExprInlineAsm"),
+        ExprAgain(ref opt_ident) => {
+            assert!(opt_ident.is_none());
+            ir::ExprKind::Continue
+        },
 
-            ExprMac(..) => panic!("unsupported ExprMac"),
+        ExprRet(ref opt_expr) =>
+            ir::ExprKind::Return(Box::new(
+                    opt_expr.as_ref().map(|e| e.trans(trcx))
+                            .unwrap_or(unit_placeholder("_ExprRet")))),
 
-            ExprStruct(ref name, ref fields, ref opt_base) => {
-                assert!(opt_base.is_none());
-                format!("struct_literal {}", fields.trans(trcx))
-            },
+        ExprInlineAsm(..) => panic!("unsupported ExprInlineAsm"),
 
-            ExprRepeat(..) => panic!("unsupported ExprRepeat"),
+        ExprMac(..) => panic!("unsupported ExprMac"),
 
-            ExprParen(ref expr) => {
-                add_ty = false;
-                expr.trans(trcx)
-            },
-        };
+        ExprStruct(ref name, ref fields, ref opt_base) => {
+            assert!(opt_base.is_none());
+            ir::ExprKind::StructLiteral(fields.trans(trcx))
+        },
 
-        let expr_ty = trcx.tcx.node_types()[&self.id];
-        let unadjusted = 
-                if add_ty {
-                    format!("({} {})",
-                            expr_ty.trans(trcx),
-                            variant)
-                } else {
-                    variant
-                };
+        ExprRepeat(..) => panic!("unsupported ExprRepeat"),
 
-        match trcx.tcx.adjustments.borrow().get(&self.id) {
-            None => unadjusted,
-            Some(adj) => adjust_expr(trcx, adj, self, unadjusted),
-        }
+        ExprParen(..) => unreachable!(),
     }
 }
 
 fn adjust_expr<'a, 'tcx>(trcx: &mut TransCtxt<'a, 'tcx>,
                          adj: &ty::AutoAdjustment<'tcx>,
                          expr: &Expr,
-                         unadjusted: String) -> String {
+                         unadjusted: ir::Expr) -> ir::Expr {
     let mut result = unadjusted;
     let mut result_ty = trcx.tcx.node_types()[&expr.id];
 
@@ -794,37 +1045,40 @@ fn adjust_expr<'a, 'tcx>(trcx: &mut TransCtxt<'a, 'tcx>,
 
             fn go_autoref<'a, 'tcx>(trcx: &mut TransCtxt<'a, 'tcx>,
                                     autoref: &ty::AutoRef<'tcx>,
-                                    mut result: String,
-                                    mut result_ty: ty::Ty<'tcx>) -> (String, ty::Ty<'tcx>) {
+                                    mut result: ir::Expr,
+                                    mut result_ty: ty::Ty<'tcx>) -> (ir::Expr, ty::Ty<'tcx>) {
                 match *autoref {
                     ty::AutoPtr(region, mutbl, ref next_autoref) => {
                         if let Some(ref ar) = *next_autoref {
                             let (new_result, new_result_ty) =
                                     go_autoref(trcx, &**ar, result, result_ty);
-                            result = format!("(( {} ))", new_result);
+                            result = ir::Expr::new(new_result.ty.clone(),
+                                                   ir::ExprKind::Grouped(Box::new(new_result)));
                             result_ty = new_result_ty;
                         }
 
                         let mt = ty::mt { ty: result_ty, mutbl: mutbl };
                         result_ty = ty::mk_t(trcx.tcx, ty::ty_rptr(trcx.tcx.mk_region(region), mt));
-                        result = format!("({} addr_of {})",
-                                         result_ty.trans(trcx),
-                                         result);
+                        result = ir::Expr::new(result_ty.trans(trcx),
+                                               ir::ExprKind::AddrOf(Box::new(result)));
+                        if mutbl == MutMutable {
+                            result = check_mut_ref(trcx, result);
+                        }
                     },
 
                     ty::AutoUnsafe(mutbl, ref next_autoref) => {
                         if let Some(ref ar) = *next_autoref {
                             let (new_result, new_result_ty) =
                                     go_autoref(trcx, &**ar, result, result_ty);
-                            result = format!("(( {} ))", new_result);
+                            result = ir::Expr::new(new_result.ty.clone(),
+                                                   ir::ExprKind::Grouped(Box::new(new_result)));
                             result_ty = new_result_ty;
                         }
 
                         let mt = ty::mt { ty: result_ty, mutbl: mutbl };
                         result_ty = ty::mk_t(trcx.tcx, ty::ty_ptr(mt));
-                        result = format!("({} addr_of {})",
-                                         result_ty.trans(trcx),
-                                         result);
+                        result = ir::Expr::new(result_ty.trans(trcx),
+                                               ir::ExprKind::AddrOf(Box::new(result)));
                     },
 
                     ty::AutoUnsize(ref unsize) => {
@@ -835,10 +1089,8 @@ fn adjust_expr<'a, 'tcx>(trcx: &mut TransCtxt<'a, 'tcx>,
                                         ty::mk_vec(trcx.tcx, item_ty, None),
                                     _ => panic!("UnsizeLength of non-ty_vec"),
                                 };
-                                result = format!("({} unsize_len {} {})",
-                                                 result_ty.trans(trcx),
-                                                 len,
-                                                 result);
+                                result = ir::Expr::new(result_ty.trans(trcx),
+                                                       ir::ExprKind::UnsizeLen(len, Box::new(result)));
                             },
 
                             _ => panic!("unsupported UnsizeKind variant"),
@@ -864,6 +1116,221 @@ fn adjust_expr<'a, 'tcx>(trcx: &mut TransCtxt<'a, 'tcx>,
     result
 }
 
+/// A reference that `check_mut_ref` checks new `&mut`s against: an argument,
+/// or a reference stored in a field of the struct an argument points to.
+#[derive(Clone)]
+struct RefArg {
+    arg: ir::Name,
+    arg_ty: ir::Ty,
+    field: Option<(ir::Name, ir::Ty)>,
+}
+
+impl RefArg {
+    fn ty(&self) -> &ir::Ty {
+        match self.field {
+            Some((_, ref ty)) => ty,
+            None => &self.arg_ty,
+        }
+    }
+
+    fn expr(&self) -> ir::Expr {
+        let var = ir::Expr::new(self.arg_ty.clone(), ir::ExprKind::Var(self.arg.clone()));
+        let (field, field_ty) = match self.field {
+            Some((ref field, ref ty)) => (field.clone(), ty.clone()),
+            None => return var,
+        };
+        let pointee_ty = match self.arg_ty {
+            ir::Ty::Ref(_, _, ref ty) => (**ty).clone(),
+            _ => unreachable!(),
+        };
+        let pointee = ir::Expr::new(pointee_ty, ir::ExprKind::Deref(Box::new(var)));
+        ir::Expr::new(field_ty, ir::ExprKind::Field(Box::new(pointee), field))
+    }
+}
+
+/// The reference arguments of a function, and the references reachable
+/// through one field of their pointees, for `check_mut_ref`.  `inputs` are the
+/// AST arguments, including `self`.  Unsized pointees are left out, since
+/// their references are fat.
+fn ref_args(trcx: &mut TransCtxt,
+            self_arg: Option<&ir::SelfArg>,
+            args: &[ir::Pat],
+            inputs: &[Arg]) -> Vec<RefArg> {
+    let mut params = Vec::new();
+    match self_arg {
+        Some(&ir::SelfArg::Region(ref region, mutbl, Some(ref ty), ref name)) =>
+            params.push((name.clone(),
+                         ir::Ty::Ref(region.clone(), mutbl, Box::new(ty.clone())),
+                         &inputs[0])),
+        Some(&ir::SelfArg::Explicit(ref ty, ref name)) =>
+            params.push((name.clone(), ty.clone(), &inputs[0])),
+        _ => {},
+    }
+    let offset = inputs.len() - args.len();
+    for (arg, input) in args.iter().zip(inputs[offset..].iter()) {
+        if let ir::PatKind::Var(ref name) = arg.kind {
+            params.push((name.clone(), arg.ty.clone(), input));
+        }
+    }
+
+    let mut result = Vec::new();
+    for (name, ty, input) in params.into_iter() {
+        if !is_sized_ref(&ty) {
+            continue;
+        }
+        result.push(RefArg { arg: name.clone(), arg_ty: ty.clone(), field: None });
+        let input_ty = match trcx.tcx.node_types().get(&input.pat.id) {
+            Some(&t) => t,
+            None => continue,
+        };
+        for (field, field_ty) in ref_fields(trcx, input_ty).into_iter() {
+            result.push(RefArg {
+                arg: name.clone(),
+                arg_ty: ty.clone(),
+                field: Some((field, field_ty)),
+            });
+        }
+    }
+    result
+}
+
+/// The fields holding sized references in the struct that `ty` points to.
+fn ref_fields<'a, 'tcx>(trcx: &mut TransCtxt<'a, 'tcx>,
+                        ty: ty::Ty<'tcx>) -> Vec<(ir::Name, ir::Ty)> {
+    let pointee = match ty.sty {
+        ty::ty_rptr(_, mt) => mt.ty,
+        _ => return Vec::new(),
+    };
+    let (did, substs) = match pointee.sty {
+        ty::ty_struct(did, substs) => (did, substs),
+        _ => return Vec::new(),
+    };
+    let mut result = Vec::new();
+    for (i, f) in ty::lookup_struct_fields(trcx.tcx, did).iter().enumerate() {
+        let field_ty = ty::lookup_field_type(trcx.tcx, did, f.id, substs).trans(trcx);
+        if !is_sized_ref(&field_ty) {
+            continue;
+        }
+        let name = if f.name == special_idents::unnamed_field.name {
+            format!("field{}", i)
+        } else {
+            f.name.trans(trcx)
+        };
+        result.push((name, field_ty));
+    }
+    result
+}
+
+fn is_sized_ref(ty: &ir::Ty) -> bool {
+    match *ty {
+        ir::Ty::Ref(_, _, ref pointee) => is_sized(&**pointee),
+        _ => false,
+    }
+}
+
+fn is_sized(ty: &ir::Ty) -> bool {
+    match *ty {
+        ir::Ty::Str | ir::Ty::Vec(_) => false,
+        _ => true,
+    }
+}
+
+/// The variable a place expression is rooted in, with the fields taken on the
+/// way from it, looking through indexing and derefs.  A raw pointer is traced
+/// back to the place it was computed from, through casts, address-ofs and the
+/// first argument of a call returning it, so `*self.value.get()` is rooted in
+/// `self` with fields `[value]`.
+fn place_root(expr: &ir::Expr) -> Option<(&ir::Name, Vec<&ir::Name>)> {
+    match expr.kind {
+        ir::ExprKind::Var(ref name) => Some((name, Vec::new())),
+        ir::ExprKind::Field(ref e, ref field) => place_root(&**e).map(|(root, mut fields)| {
+            fields.push(field);
+            (root, fields)
+        }),
+        ir::ExprKind::Index(ref e, _) |
+        ir::ExprKind::Grouped(ref e) |
+        ir::ExprKind::Deref(ref e) |
+        ir::ExprKind::AddrOf(ref e) |
+        ir::ExprKind::Cast(ref e) => place_root(&**e),
+        ir::ExprKind::Call(_, _, ref args) if args.len() > 0 => match expr.ty {
+            ir::Ty::Ptr(..) => place_root(&args[0]),
+            _ => None,
+        },
+        ir::ExprKind::Block(ref b) if b.stmts.len() == 0 => place_root(&*b.expr),
+        _ => None,
+    }
+}
+
+/// With `--crust-alias-checks`, wrap a new `&mut` created in unsafe code so
+/// it is checked against each reference argument of the current function, and
+/// each reference stored directly in an argument's pointee:
+///
+///     {
+///         let __crust_mref_N = &mut E;
+///         __crust::assert_disjoint(__crust_mref_N, ARG_1);
+///         __crust::assert_disjoint(__crust_mref_N, (*ARG_1).FIELD);
+///         ...
+///         __crust_mref_N
+///     }
+///
+/// References that `E` is itself borrowed from are skipped, since reborrowing
+/// part of an argument is fine.
+fn check_mut_ref(trcx: &mut TransCtxt, mref: ir::Expr) -> ir::Expr {
+    if !trcx.alias_checks || trcx.unsafe_depth == 0 {
+        return mref;
+    }
+    let (ref_ty, pointee_ty) = match mref.ty {
+        ir::Ty::Ref(_, _, ref ty) if is_sized(&**ty) => (mref.ty.clone(), (**ty).clone()),
+        _ => return mref,
+    };
+    let others = {
+        let root = match mref.kind {
+            ir::ExprKind::AddrOf(ref place) => place_root(&**place),
+            _ => None,
+        };
+        let borrowed_from = |r: &RefArg| match root {
+            Some((name, ref fields)) if *name == r.arg => match r.field {
+                None => true,
+                Some((ref field, _)) => fields.first() == Some(&field),
+            },
+            _ => false,
+        };
+        trcx.ref_args.iter()
+                     .filter(|r| !borrowed_from(*r))
+                     .map(|r| r.clone())
+                     .collect::<Vec<_>>()
+    };
+    if others.len() == 0 {
+        return mref;
+    }
+
+    let var = format!("__crust_mref_{}", trcx.mut_ref_counter);
+    trcx.mut_ref_counter += 1;
+    let var_expr = ir::Expr::new(ref_ty.clone(), ir::ExprKind::Var(var.clone()));
+    let mut stmts = vec![ir::Stmt::Let(ir::Pat { ty: ref_ty.clone(),
+                                                 kind: ir::PatKind::Var(var.clone()) },
+                                       Some(mref),
+                                       None)];
+    for other in others.into_iter() {
+        let arg_pointee_ty = match *other.ty() {
+            ir::Ty::Ref(_, _, ref ty) => (**ty).clone(),
+            _ => unreachable!(),
+        };
+        let substs = ir::Substs {
+            regions: vec![],
+            types: vec![pointee_ty.clone(), arg_pointee_ty],
+        };
+        let args = vec![var_expr.clone(), other.expr()];
+        stmts.push(ir::Stmt::Expr(ir::Expr::new(ir::Ty::Unit,
+                ir::ExprKind::Call(format!("__crust$assert_disjoint"), substs, args))));
+    }
+    ir::Expr::new(ref_ty, ir::ExprKind::Block(ir::Block {
+        unsafety: false,
+        stmts: stmts,
+        expr: Box::new(var_expr),
+    }))
+}
+
 fn auto_ref_ty<'a, 'tcx>(trcx: &mut TransCtxt<'a, 'tcx>,
                          expr: &Expr,
                          mutbl: Option<Mutability>,
@@ -875,25 +1342,24 @@ fn auto_ref_ty<'a, 'tcx>(trcx: &mut TransCtxt<'a, 'tcx>,
 
 fn do_auto_ref(trcx: &mut TransCtxt,
                expr: &Expr,
-               unadjusted: String) -> String {
+               unadjusted: ir::Expr) -> ir::Expr {
     let result = unadjusted;
     let mut result_ty = trcx.tcx.node_types()[&expr.id];
     result_ty = auto_ref_ty(trcx, expr, None, result_ty);
 
-    format!("({} addr_of {})",
-            result_ty.trans(trcx),
-            result)
+    ir::Expr::new(result_ty.trans(trcx),
+                  ir::ExprKind::AddrOf(Box::new(result)))
 }
 
 fn deref_once<'a, 'tcx>(trcx: &mut TransCtxt<'a, 'tcx>,
                    expr: &Expr,
                    level: usize,
-                   expr_str: String,
-                   expr_ty: ty::Ty<'tcx>) -> (String, ty::Ty<'tcx>) {
-    let (expr_str, expr_ty) =
+                   expr_ir: ir::Expr,
+                   expr_ty: ty::Ty<'tcx>) -> (ir::Expr, ty::Ty<'tcx>) {
+    let (expr_ir, expr_ty) =
         if let Some(callee) = trcx.tcx.method_map.borrow().get(&ty::MethodCall::autoderef(expr.id, level)) {
-            let arg_str = do_auto_ref(trcx, expr, expr_str);
-            let new_expr_variant = trans_method_call(trcx, callee, vec![arg_str]);
+            let arg = do_auto_ref(trcx, expr, expr_ir);
+            let new_expr_kind = trans_method_call(trcx, callee, vec![arg]);
 
             let method_ret = match ty::ty_fn_ret(callee.ty).0 {
                 ty::FnConverging(ty) => ty,
@@ -915,18 +1381,17 @@ fn deref_once<'a, 'tcx>(trcx: &mut TransCtxt<'a, 'tcx>,
             };
             let new_expr_ty = auto_ref_ty(trcx, expr, Some(mutbl), deref_ty);
 
-            (format!("({} {})", new_expr_ty.trans(trcx), new_expr_variant), new_expr_ty)
+            (ir::Expr::new(new_expr_ty.trans(trcx), new_expr_kind), new_expr_ty)
         } else {
-            (expr_str, expr_ty)
+            (expr_ir, expr_ty)
         };
 
     match expr_ty.sty {
         ty::ty_ptr(ty::mt { ty, .. }) |
         ty::ty_rptr(_, ty::mt { ty, .. }) => {
-            let new_expr_str = format!("({} deref {})",
-                                       ty.trans(trcx),
-                                       expr_str);
-            (new_expr_str, ty)
+            let new_expr_ir = ir::Expr::new(ty.trans(trcx),
+                                            ir::ExprKind::Deref(Box::new(expr_ir)));
+            (new_expr_ir, ty)
         },
         _ => panic!("unexpected ty variant: {}", expr_ty.repr(trcx.tcx)),
     }
@@ -970,6 +1435,7 @@ fn find_variant(trcx: &mut TransCtxt, id: NodeId) -> Option<(String, usize)> {
 }
 
 impl Trans for Lit {
+    type Out = String;
     fn trans(&self, trcx: &mut TransCtxt) -> String {
         match self.node {
             LitStr(ref s, ref style) => print_str_lit(s.bytes()),
@@ -985,92 +1451,101 @@ impl Trans for Lit {
     }
 }
 
-impl TransExtra<String> for Pat {
-    fn trans_extra(&self, trcx: &mut TransCtxt, ty_str: String) -> String {
-        let variant = match self.node {
-            PatWild(PatWildSingle) => format!("wild"),
+impl TransExtra<ir::Ty> for Pat {
+    type Out = ir::Pat;
+    fn trans_extra(&self, trcx: &mut TransCtxt, ty: ir::Ty) -> ir::Pat {
+        let kind = match self.node {
+            PatWild(PatWildSingle) => ir::PatKind::Wild,
             PatIdent(mode, name, None) => {
                 if let Some((var_name, var_idx)) = find_variant(trcx, self.id) {
-                    format!("enum {} {} 0",
-                            var_name,
-                            var_idx)
+                    ir::PatKind::Enum(var_name,
+                                      var_idx,
+                                      vec![])
                 } else {
                     use rustc::middle::def::*;
                     match trcx.tcx.def_map.borrow().get(&self.id).map(|r| &r.base_def) {
-                        None | Some(&DefLocal(_)) => 
+                        None | Some(&DefLocal(_)) =>
                             match mode {
-                                BindByRef(_) => format!("ref_var {}", name.node.trans(trcx)),
-                                BindByValue(_) => format!("var {}", name.node.trans(trcx)),
+                                BindByRef(_) => ir::PatKind::RefVar(name.node.trans(trcx)),
+                                BindByValue(_) => ir::PatKind::Var(name.node.trans(trcx)),
                             },
-                        Some(ref d) => format!("const {}",
-                                               mangled_def_name(trcx, d.def_id())),
+                        Some(ref d) => ir::PatKind::Const(mangled_def_name(trcx, d.def_id())),
                     }
                 }
             },
             PatEnum(ref path, Some(ref args)) => {
                 if let Some((var_name, var_idx)) = find_variant(trcx, self.id) {
-                    format!("enum {} {} {}",
-                            var_name,
-                            var_idx,
-                            args.trans(trcx))
+                    ir::PatKind::Enum(var_name,
+                                      var_idx,
+                                      args.trans(trcx))
                 } else if let Some(struct_name) = find_tuple_struct_ctor(trcx, self.id) {
-                    format!("tuple {}", args.trans(trcx))
+                    ir::PatKind::Tuple(args.trans(trcx))
                 } else {
                     panic!("couldn't find enum variant or tuple struct for {}", path.repr(trcx.tcx));
                 }
             },
-            PatTup(ref args) => format!("tuple {}", args.trans(trcx)),
-            PatRegion(ref pat, _mutbl) => format!("addr_of {}", pat.trans(trcx)),
-            // NB: For PatLit, we skip the code below that adds the pattern type.
-            PatLit(ref expr) =>
-                match const_eval::eval_const_expr(trcx.tcx, expr) {
-                    const_eval::const_float(f) => format!("simple_literal {}", f),
-                    const_eval::const_int(i) => format!("simple_literal {}", i),
-                    const_eval::const_uint(i) => format!("simple_literal {}", i),
-                    const_eval::const_str(s) => format!("simple_literal {}",
-                                                        print_str_lit(s.bytes())),
-                    const_eval::const_bool(b) => format!("simple_literal {}", b as u8),
+            PatTup(ref args) => ir::PatKind::Tuple(args.trans(trcx)),
+            PatRegion(ref pat, _mutbl) => ir::PatKind::AddrOf(Box::new(pat.trans(trcx))),
+            PatLit(ref expr) => {
+                let lit = match const_eval::eval_const_expr(trcx.tcx, expr) {
+                    const_eval::const_float(f) => format!("{}", f),
+                    const_eval::const_int(i) => format!("{}", i),
+                    const_eval::const_uint(i) => format!("{}", i),
+                    const_eval::const_str(s) => print_str_lit(s.bytes()),
+                    const_eval::const_bool(b) => format!("{}", b as u8),
                     _ => panic!("unhandled const_val variant"),
-                },
+                };
+                ir::PatKind::SimpleLiteral(lit)
+            },
             _ => panic!("unhandled Pat_ variant"),
         };
 
-        format!("({} {})",
-                ty_str,
-                variant)
+        ir::Pat {
+            ty: ty,
+            kind: kind,
+        }
     }
 }
 
 impl Trans for Pat {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
-        let ty_str = trcx.tcx.node_types()[&self.id].trans(trcx);
-        self.trans_extra(trcx, ty_str)
+    type Out = ir::Pat;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Pat {
+        let ty = trcx.tcx.node_types()[&self.id].trans(trcx);
+        self.trans_extra(trcx, ty)
     }
 }
 
 impl TransExtra<usize> for StructField {
-    fn trans_extra(&self, trcx: &mut TransCtxt, idx: usize) -> String {
-        match self.node.ident() {
-            Some(ident) => format!("{} {}",
-                                   ident.trans(trcx),
-                                   self.node.ty.trans(trcx)),
-            None => format!("field{} {}",
-                            idx,
-                            self.node.ty.trans(trcx)),
+    type Out = ir::FieldDef;
+    fn trans_extra(&self, trcx: &mut TransCtxt, idx: usize) -> ir::FieldDef {
+        let vis = match self.node.kind {
+            NamedField(_, vis) => vis,
+            UnnamedField(vis) => vis,
+        };
+        ir::FieldDef {
+            vis: vis.trans(trcx),
+            name: match self.node.ident() {
+                Some(ident) => ident.trans(trcx),
+                None => format!("field{}", idx),
+            },
+            ty: self.node.ty.trans(trcx),
         }
     }
 }
 
 impl Trans for Variant {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
-        format!("{} {}",
-                mangled_def_name(trcx, local_def(self.node.id)),
-                self.node.kind.trans(trcx))
+    type Out = ir::VariantDef;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::VariantDef {
+        ir::VariantDef {
+            name: mangled_def_name(trcx, local_def(self.node.id)),
+            args: self.node.kind.trans(trcx),
+        }
     }
 }
 
 impl Trans for VariantKind {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = Vec<ir::Ty>;
+    fn trans(&self, trcx: &mut TransCtxt) -> Vec<ir::Ty> {
         match *self {
             TupleVariantKind(ref args) => args.trans(trcx),
             _ => panic!("unsupported VariantKind variant"),
@@ -1079,47 +1554,76 @@ impl Trans for VariantKind {
 }
 
 impl Trans for ty::DtorKind {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
-        let opt = match *self {
+    type Out = Option<ir::Name>;
+    fn trans(&self, trcx: &mut TransCtxt) -> Option<ir::Name> {
+        match *self {
             ty::NoDtor => None,
-            ty::TraitDtor(did, _) => Some(mangled_def_name(trcx, did)),
-        };
-        opt.trans(trcx)
+            ty::TraitDtor(did, _) => {
+                note_dep(trcx, Dep::Item(did));
+                Some(mangled_def_name(trcx, did))
+            },
+        }
     }
 }
 
 impl Trans for Visibility {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = ir::Visibility;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Visibility {
         match *self {
-            Public => format!("pub"),
-            Inherited => format!("priv"),
+            Public => ir::Visibility::Public,
+            Inherited => ir::Visibility::Private,
         }
     }
 }
 
+/// Whether the item can be named from outside the crate.  A `pub` item inside a
+/// private module is not exported.
+fn is_exported(trcx: &mut TransCtxt, id: NodeId) -> bool {
+    trcx.exported_items.contains(&id)
+}
+
 impl Trans for VariantArg {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = ir::Ty;
+    fn trans(&self, trcx: &mut TransCtxt) -> ir::Ty {
         self.ty.trans(trcx)
     }
 }
 
 struct TransVisitor<'b, 'a: 'b, 'tcx: 'a> {
     trcx: &'b mut TransCtxt<'a, 'tcx>,
-    filter_fn: HashSet<String>
+    filter: Filter,
 }
 
-fn try_str<F: FnOnce() -> String>(f: F, what: &str) -> String {
-    let mut opt_str = None;
+/// Guess the kind of construct from a panic message.  Translation failures
+/// read "unsupported ExprClosure", "unrecognized type: ...", and so on.
+fn construct_kind(msg: &str) -> String {
+    let mut words = msg.split(' ');
+    let first = words.next().unwrap_or("");
+    let rest = match first {
+        "unsupported" | "unhandled" | "unrecognized" | "unexpected" => words.next(),
+        "can't" => words.nth(1),
+        _ => None,
+    };
+    match rest {
+        Some(w) if w.len() > 0 => String::from_str(w.trim_right_matches(':')),
+        _ => format!("other"),
+    }
+}
+
+fn try_trans<T, F: FnOnce() -> T>(f: F,
+                                  what: &str,
+                                  span: Option<ir::Span>) -> Result<T, ir::Item> {
+    let mut opt_result = None;
     let mut opt_f = Some(f);
     let result = unsafe {
         ::std::rt::unwind::try(|| {
             let f = opt_f.take().unwrap();
-            opt_str = Some(f());
+            opt_result = Some(f());
         })
     };
     match result {
         Ok(()) => {
-            opt_str.unwrap()
+            Ok(opt_result.unwrap())
         },
         Err(e) => {
             fn read(mut e: Box<Any>) -> String {
@@ -1135,90 +1639,281 @@ fn try_str<F: FnOnce() -> String>(f: F, what: &str) -> String {
 
                 format!("(unknown error type: {:?}", e.get_type_id())
             }
-            format!("# error with {}: {}", what, read(e))
+            let msg = read(e);
+            Err(ir::Item::Error(ir::Diagnostic {
+                item: String::from_str(what),
+                span: span,
+                construct: construct_kind(&*msg),
+                message: msg,
+                help: None,
+            }))
         },
     }
 }
 
+fn record_errors<'a, I: Iterator<Item=&'a ir::Item>>(trcx: &mut TransCtxt, items: I) {
+    for item in items {
+        if let ir::Item::Error(ref diag) = *item {
+            trcx.diagnostics.push(diag.clone());
+        }
+    }
+}
+
+fn emit_entry(trcx: &mut TransCtxt, entry: &ir::Entry) {
+    match *entry {
+        ir::Entry::Single(ref opt_item) => record_errors(trcx, opt_item.iter()),
+        ir::Entry::Group(ref items, ref opt_tail) =>
+            record_errors(trcx, items.iter().chain(opt_tail.iter())),
+    }
+    match trcx.format {
+        Format::Text => {
+            let text = entry.print();
+            emit_line(trcx, &*text);
+        },
+        Format::Json => {
+            for line in json::entry_lines(entry).iter() {
+                emit_line(trcx, &**line);
+            }
+        },
+    }
+}
+
+fn emit_item(trcx: &mut TransCtxt, item: &ir::Item) {
+    record_errors(trcx, Some(item).into_iter());
+    let line = match trcx.format {
+        Format::Text => item.print(),
+        Format::Json => item.json(),
+    };
+    emit_line(trcx, &*line);
+}
+
+fn emit_line(trcx: &mut TransCtxt, line: &str) {
+    writeln!(trcx.out, "{}", line).unwrap();
+}
+
+/// Debugging notes, printed to stderr with `--crust-verbose`.
+fn trace(trcx: &mut TransCtxt, msg: &str) {
+    if trcx.verbose {
+        writeln!(&mut io::stderr(), "# {}", msg).unwrap();
+    }
+}
+
+fn try_item<F: FnOnce() -> ir::Item>(f: F,
+                                     what: &str,
+                                     span: Option<ir::Span>) -> ir::Item {
+    try_trans(f, what, span).unwrap_or_else(|err| err)
+}
+
 impl<'b, 'a, 'tcx, 'v> Visitor<'v> for TransVisitor<'b, 'a, 'tcx> {
     fn visit_item(&mut self, i: &'v Item) {
         let name = mangled_def_name(self.trcx, local_def(i.id));
-        let s = try_str(|| i.trans_extra(self.trcx, &self.filter_fn),
-                        &*name);
-        println!("{}", s);
+        let span = diag_span(self.trcx, i.span);
+        let entry = match try_trans(|| i.trans_extra(self.trcx, &self.filter),
+                                    &*name,
+                                    span) {
+            Ok(entry) => entry,
+            Err(err) => ir::Entry::Single(Some(err)),
+        };
+        emit_entry(self.trcx, &entry);
         visit::walk_item(self, i);
     }
 }
 
 impl<'tcx> Trans for ty::GenericPredicates<'tcx> {
-    fn trans(&self, trcx: &mut TransCtxt) -> String {
+    type Out = Vec<ir::Predicate>;
+    fn trans(&self, trcx: &mut TransCtxt) -> Vec<ir::Predicate> {
         let mut parts = Vec::new();
         for p in self.predicates.iter() {
             match *p {
                 ty::Predicate::Trait(ref trait_p) => {
-                    parts.push(format!("ty_impl {} {}",
-                                       mangled_def_name(trcx, trait_p.0.trait_ref.def_id),
-                                       trait_p.0.trait_ref.substs.trans(trcx)));
+                    parts.push(ir::Predicate::Impl(
+                            mangled_def_name(trcx, trait_p.0.trait_ref.def_id),
+                            trait_p.0.trait_ref.substs.trans(trcx)));
                 },
                 ty::Predicate::Equate(ref eq_p) => {
-                    parts.push(format!("ty_eq {} {}",
-                                       (eq_p.0).0.trans(trcx),
-                                       (eq_p.0).1.trans(trcx)));
+                    parts.push(ir::Predicate::Eq((eq_p.0).0.trans(trcx),
+                                                 (eq_p.0).1.trans(trcx)));
                 },
                 ty::Predicate::Projection(ref proj_p) => {
-                    parts.push(format!("ty_eq_proj {} {}",
-                                       proj_p.0.projection_ty.trans(trcx),
-                                       proj_p.0.ty.trans(trcx)));
+                    parts.push(ir::Predicate::EqProj(proj_p.0.projection_ty.trans(trcx),
+                                                     proj_p.0.ty.trans(trcx)));
                 },
                 _ => {},
             }
         }
-        parts.trans(trcx)
+        parts
     }
 }
 
-impl<'a> TransExtra<&'a HashSet<String>> for Item {
-    fn trans_extra(&self, trcx: &mut TransCtxt, filter_fn: &'a HashSet<String>) -> String {
+fn arbitrary_fn_ret_ty(adt_name: &str, generics: &ir::Generics) -> ir::Ty {
+    ir::Ty::Adt(String::from_str(adt_name), ir::Substs {
+        regions: generics.lifetimes.clone(),
+        types: generics.ty_params.iter().map(|t| ir::Ty::Var(t.clone())).collect(),
+    })
+}
+
+/// The "arbitrary" constructor emitted after each struct and enum.  crust
+/// compiles `__crust::nondet()` at the type into a call to it, so harnesses
+/// get a well-formed value of any shape.  It is private, so it never shows up
+/// in a crate's API or gets a generated driver of its own.
+fn arbitrary_fn(adt_name: &str, generics: &ir::Generics, kind: ir::ExprKind) -> ir::Item {
+    let ty = arbitrary_fn_ret_ty(adt_name, generics);
+    ir::Item::Fn(ir::FnDef {
+        vis: ir::Visibility::Private,
+        exported: false,
+        name: format!("{}$__crust_arbitrary", adt_name),
+        generics: generics.clone(),
+        self_arg: None,
+        args: Vec::new(),
+        ret: ir::Return::Value(ty.clone()),
+        impl_clause: None,
+        preds: Vec::new(),
+        body: ir::Block {
+            unsafety: false,
+            stmts: Vec::new(),
+            expr: Box::new(ir::Expr::new(ty, kind)),
+        },
+        span: None,
+    })
+}
+
+/// A struct definition followed by its "arbitrary" constructor.
+fn struct_entry(def: ir::StructDef) -> ir::Entry {
+    let fields = def.fields.iter().map(|f| ir::Field {
+        name: f.name.clone(),
+        expr: arbitrary_expr(&f.ty),
+    }).collect();
+    let arbitrary = arbitrary_fn(&def.name, &def.generics,
+                                 ir::ExprKind::StructLiteral(fields));
+    ir::Entry::Group(vec![ir::Item::Struct(def), arbitrary], None)
+}
+
+/// An enum definition followed by its "arbitrary" constructor.
+fn enum_entry(trcx: &mut TransCtxt, did: DefId, def: ir::EnumDef) -> ir::Entry {
+    if def.variants.len() == 0 {
+        // Nothing to construct.
+        return ir::Entry::Single(Some(ir::Item::Enum(def)));
+    }
+
+    // Pick a variant by its discriminant, as `EnumLiteral` does.
+    let discrs = ty::enum_variants(trcx.tcx, did).iter()
+                     .map(|v| v.disr_val as usize).collect::<Vec<_>>();
+    let ty = arbitrary_fn_ret_ty(&def.name, &def.generics);
+    let last = def.variants.len() - 1;
+    let arms = def.variants.iter().zip(discrs.iter()).enumerate()
+                  .map(|(i, (v, &discr))| ir::MatchArm {
+        pat: ir::Pat {
+            ty: ir::Ty::Uint(ir::IntSize::PtrSize),
+            kind: if i == last {
+                ir::PatKind::Wild
+            } else {
+                ir::PatKind::SimpleLiteral(format!("{}", i))
+            },
+        },
+        body: ir::Expr::new(ty.clone(),
+                            ir::ExprKind::EnumLiteral(
+                                v.name.clone(),
+                                discr,
+                                v.args.iter().map(arbitrary_expr).collect())),
+    }).collect();
+    let choice = arbitrary_expr(&ir::Ty::Uint(ir::IntSize::PtrSize));
+    let arbitrary = arbitrary_fn(&def.name, &def.generics,
+                                 ir::ExprKind::Match(Box::new(choice), arms));
+    ir::Entry::Group(vec![ir::Item::Enum(def), arbitrary], None)
+}
+
+/// A nondet value of type `ty`.  Tuples, and arrays of anything that has an
+/// arbitrary constructor, are built element by element so each element goes
+/// through its own constructor.
+fn arbitrary_expr(ty: &ir::Ty) -> ir::Expr {
+    let kind = match *ty {
+        ir::Ty::Tuple(ref tys) =>
+            ir::ExprKind::TupleLiteral(tys.iter().map(arbitrary_expr).collect()),
+        ir::Ty::FixedVec(len, ref elem) if has_arbitrary_parts(&**elem) =>
+            ir::ExprKind::Vec((0..len).map(|_| arbitrary_expr(&**elem)).collect()),
+        _ => ir::ExprKind::Call(format!("__crust$nondet"),
+                                ir::Substs { regions: vec![], types: vec![ty.clone()] },
+                                vec![]),
+    };
+    ir::Expr::new(ty.clone(), kind)
+}
+
+fn has_arbitrary_parts(ty: &ir::Ty) -> bool {
+    match *ty {
+        ir::Ty::Adt(..) | ir::Ty::Var(_) | ir::Ty::Abstract(_) => true,
+        ir::Ty::Tuple(ref tys) => tys.iter().any(has_arbitrary_parts),
+        ir::Ty::FixedVec(_, ref elem) => has_arbitrary_parts(&**elem),
+        _ => false,
+    }
+}
+
+fn fn_unsafety(style: Unsafety) -> bool {
+    match style {
+        Unsafety::Unsafe => true,
+        Unsafety::Normal => false,
+    }
+}
+
+impl<'a> TransExtra<&'a Filter> for Item {
+    type Out = ir::Entry;
+    fn trans_extra(&self, trcx: &mut TransCtxt, filter: &'a Filter) -> ir::Entry {
         match self.node {
             ItemStruct(ref def, ref g) => {
-                format!("struct {} {} {} {};",
-                        mangled_def_name(trcx, local_def(self.id)),
-                        g.trans_extra(trcx, TypeSpace),
-                        def.fields.trans_extra(trcx, SliceIndex),
-                        ty::ty_dtor(trcx.tcx, local_def(self.id)).trans(trcx))
+                let def = ir::StructDef {
+                    name: mangled_def_name(trcx, local_def(self.id)),
+                    generics: g.trans_extra(trcx, TypeSpace),
+                    fields: def.fields.trans_extra(trcx, SliceIndex),
+                    dtor: ty::ty_dtor(trcx.tcx, local_def(self.id)).trans(trcx),
+                    span: trans_span(trcx, self.span),
+                };
+                struct_entry(def)
             },
             ItemEnum(ref def, ref g) => {
-                format!("enum {} {} {} {};",
-                        mangled_def_name(trcx, local_def(self.id)),
-                        g.trans_extra(trcx, TypeSpace),
-                        def.variants.trans(trcx),
-                        ty::ty_dtor(trcx.tcx, local_def(self.id)).trans(trcx))
+                let def = ir::EnumDef {
+                    name: mangled_def_name(trcx, local_def(self.id)),
+                    generics: g.trans_extra(trcx, TypeSpace),
+                    variants: def.variants.trans(trcx),
+                    dtor: ty::ty_dtor(trcx.tcx, local_def(self.id)).trans(trcx),
+                    span: trans_span(trcx, self.span),
+                };
+                enum_entry(trcx, local_def(self.id), def)
             },
             ItemFn(ref decl, style, _, ref generics, ref body) => {
                 let mangled_name = mangled_def_name(trcx, local_def(self.id));
-                if filter_fn.contains(&mangled_name) {
-                    format!("")
+                if filter.excludes(&*mangled_name) {
+                    ir::Entry::Single(Some(ir::Item::Comment(format!("filtered {}", mangled_name))))
                 } else {
-                    format!("fn {} {} {} {} 0 preds {} body {} {} {{\n{}\t{}\n}}\n\n",
-                            self.vis.trans(trcx),
-                            mangled_name,
-                            generics.trans_extra(trcx, FnSpace),
-                            decl.trans(trcx),
-                            trcx.tcx.predicates.borrow().get(&local_def(self.id))
-                                .unwrap().trans(trcx),
-                            decl.output.trans(trcx),
-                            match style {
-                                Unsafety::Unsafe => "unsafe",
-                                Unsafety::Normal => "block",
-                            },
-                            body.stmts.trans(trcx),
-                            body.expr.as_ref().map(|e| e.trans(trcx))
-                                .unwrap_or(format!("[unit] simple_literal _ItemFn")))
+                    trcx.cur_fn = mangled_name.clone();
+                    trcx.default_unwind = unwind_attr(trcx, self.id);
+                    let (args, ret) = decl.trans(trcx);
+                    let args_checked = ref_args(trcx, None, &*args, &*decl.inputs);
+                    trcx.ref_args = args_checked;
+                    trcx.unsafe_depth = 0;
+                    let mut body = trans_block_body(trcx, fn_unsafety(style), &**body, "_ItemFn");
+                    if trcx.borrow_model {
+                        body = borrows::instrument(None, &*args, body);
+                    }
+                    let f = ir::FnDef {
+                        vis: self.vis.trans(trcx),
+                        exported: is_exported(trcx, self.id),
+                        name: mangled_name,
+                        generics: generics.trans_extra(trcx, FnSpace),
+                        self_arg: None,
+                        args: args,
+                        ret: ret,
+                        impl_clause: None,
+                        preds: ty::lookup_predicates(trcx.tcx,
+                                                     item_def_id(trcx, local_def(self.id)))
+                                   .trans(trcx),
+                        body: body,
+                        span: trans_span(trcx, self.span),
+                    };
+                    ir::Entry::Single(Some(ir::Item::Fn(apply_stub(trcx, f))))
                 }
             },
 
             ItemImpl(_, _, ref impl_generics, ref trait_ref, ref self_ty, ref items) => {
-                let mut result = String::new();
+                let mut parts = Vec::new();
 
                 let mut seen_methods: HashSet<String> = HashSet::new();
                 for item in items.iter() {
@@ -1228,63 +1923,70 @@ impl<'a> TransExtra<&'a HashSet<String>> for Item {
                             seen_methods.insert(base_name);
 
                             let name = mangled_def_name(trcx, local_def(item.id));
-                            try_str(|| trans_method(trcx,
-                                                    self,
-                                                    (item.id, &item.ident, Some(item.vis)),
-                                                    sig,
-                                                    block), &*name)
+                            if filter.excludes(&*name) {
+                                parts.push(ir::Item::Comment(format!("filtered {}", name)));
+                                continue;
+                            }
+                            let span = diag_span(trcx, item.span);
+                            try_item(|| trans_method(trcx,
+                                                     MethodParent::Item(self),
+                                                     (item.id, &item.ident, Some(item.vis)),
+                                                     sig,
+                                                     block,
+                                                     item.span), &*name, span)
                         },
                         TypeImplItem(ref ty) => {
                             let name = mangled_def_name(trcx, local_def(item.id));
-                            try_str(|| {
+                            let span = diag_span(trcx, item.span);
+                            try_item(|| {
                                 let name_str = item.ident.trans(trcx);
-                                let self_str = self_ty.trans(trcx);
-                                let typ_str = ty.trans(trcx);
-                                format!("associated_type {} {} {}",
+                                let typ = ty.trans(trcx);
+                                ir::Item::AssociatedType(
                                         impl_generics.trans_extra(trcx, TypeSpace),
                                         trans_impl_clause(trcx,
                                                           self.id,
                                                           trait_ref.as_ref().unwrap(),
                                                           Some(name_str),
-                                                          self_str,
                                                           [].as_slice(),
                                                           [].as_slice()),
-                                        typ_str)
-                            }, &*name)
+                                        typ)
+                            }, &*name, span)
                         },
                         _ => panic!("unexpected ImplItem_ variant"),
                     };
-                    result.push_str(part.as_slice());
-                    result.push_str("\n");
+                    parts.push(part);
                 }
 
                 let opt_ty_trait_ref = ty::impl_trait_ref(trcx.tcx, local_def(self.id));
                 if let Some(ty_trait_ref) = opt_ty_trait_ref {
-                    println!("# impling for trait");
+                    trace(trcx, "impling for trait");
                     for item in ty::trait_items(trcx.tcx, ty_trait_ref.def_id).iter() {
                         match *item {
                             ty::MethodTraitItem(ref method) => {
                                 let did = method.def_id;
                                 let name = mangled_def_name(trcx, did);
-                                println!("# SAW {}", name);
-                                trcx.observed_abstract_fns.insert(name, did);
+                                trace(trcx, &*format!("SAW {}", name));
+                                trcx.observed_abstract_fns.insert(name.clone(), did);
 
                                 let base_name = method.name.trans(trcx);
                                 if !seen_methods.contains(&base_name) {
-                                    let self_ty_str = self_ty.trans(trcx);
+                                    // The default was left out, so there is
+                                    // nothing for the impl to use.
+                                    if filter.excludes(&*name) {
+                                        parts.push(ir::Item::Comment(format!("filtered {}", name)));
+                                        continue;
+                                    }
+                                    note_dep(trcx, Dep::Item(did));
                                     let method_name = method.name.trans(trcx);
                                     let i = trans_impl_clause(trcx,
                                                               self.id,
                                                               trait_ref.as_ref().unwrap(),
                                                               Some(method_name),
-                                                              self_ty_str,
                                                               [].as_slice(),
                                                               [].as_slice());
-                                    let code = format!("use_default {} {}",
-                                                       impl_generics.trans_extra(trcx, TypeSpace),
-                                                       i);
-                                    result.push_str(code.as_slice());
-                                    result.push_str("\n");
+                                    parts.push(ir::Item::UseDefault(
+                                            impl_generics.trans_extra(trcx, TypeSpace),
+                                            i));
                                 }
                             },
                             ty::TypeTraitItem(..) => {},
@@ -1293,104 +1995,113 @@ impl<'a> TransExtra<&'a HashSet<String>> for Item {
 
                     let preds = trcx.tcx.predicates.borrow().get(&local_def(self.id))
                                     .unwrap().trans(trcx);
-                    let self_str = self_ty.trans(trcx);
-                    result.push_str(&*format!("impl {} {} {}",
-                                              impl_generics.trans_extra(trcx, TypeSpace),
+                    let decl = ir::Item::Impl(impl_generics.trans_extra(trcx, TypeSpace),
                                               trans_impl_clause(trcx,
                                                                 self.id,
                                                                 trait_ref.as_ref().unwrap(),
                                                                 None,
-                                                                self_str,
                                                                 [].as_slice(),
                                                                 [].as_slice()),
-                                              preds));
+                                              preds);
+                    ir::Entry::Group(parts, Some(decl))
                 } else {
-                    println!("# inherent impl");
+                    trace(trcx, "inherent impl");
+                    ir::Entry::Group(parts, None)
                 }
-
-                result
             },
             ItemConst(ref ty, ref expr) => {
-                format!("const {} {} {}",
-                        mangled_def_name(trcx, local_def(self.id)),
-                        ty.trans(trcx),
-                        expr.trans(trcx))
+                ir::Entry::Single(Some(ir::Item::Const(mangled_def_name(trcx, local_def(self.id)),
+                                                       ty.trans(trcx),
+                                                       expr.trans(trcx))))
             },
             ItemForeignMod(ref fm) => {
                 let abi_str = format!("{:?}", fm.abi);
-                let mut result = String::new();
+                let mut parts = Vec::new();
                 for item in fm.items.iter() {
                     let part = match item.node {
                         ForeignItemFn(ref decl, ref generics) => {
                             let name = mangled_def_name(trcx, local_def(item.id));
-                            try_str(|| {
-                                format!("extern_fn {} {} {} {}",
-                                        abi_str,
-                                        name,
-                                        generics.trans_extra(trcx, FnSpace),
-                                        decl.trans(trcx))
-                            }, &*name)
+                            let span = diag_span(trcx, item.span);
+                            try_item(|| {
+                                let (args, ret) = decl.trans(trcx);
+                                ir::Item::ExternFn(abi_str.clone(),
+                                                   name.clone(),
+                                                   generics.trans_extra(trcx, FnSpace),
+                                                   args,
+                                                   ret)
+                            }, &*name, span)
                         },
                         ForeignItemStatic(ref ty, is_mutbl) => {
                             let name = mangled_def_name(trcx, local_def(item.id));
-                            try_str(|| {
+                            let span = diag_span(trcx, item.span);
+                            try_item(|| {
                                 panic!("can't translate ForeignItemStatic");
-                            }, &*name)
+                            }, &*name, span)
                         },
                     };
-                    result.push_str(part.as_slice());
-                    result.push_str("\n");
+                    parts.push(part);
                 }
-                result
-
+                ir::Entry::Group(parts, None)
             },
             ItemStatic(ref ty, _, ref ex) => {
                 let mangled_name = mangled_def_name(trcx, local_def(self.id));
-                format!("static {} {} {}", mangled_name, ty.trans(trcx), ex.trans(trcx))
+                if filter.excludes(&*mangled_name) {
+                    return ir::Entry::Single(Some(ir::Item::Comment(
+                            format!("filtered {}", mangled_name))));
+                }
+                ir::Entry::Single(Some(ir::Item::Static(mangled_name,
+                                                        ty.trans(trcx),
+                                                        ex.trans(trcx))))
             },
             ItemTrait(unsafety, ref trait_generics, ref bounds, ref items) => {
-                let mut result = String::new();
+                let mut parts = Vec::new();
                 for item in items.iter() {
                     let part = match item.node {
                         MethodTraitItem(ref sig, ref opt_block) => {
                             let did = local_def(item.id);
                             let name = mangled_def_name(trcx, did);
                             trcx.observed_abstract_fns.insert(name.clone(), did);
-                            if let Some(block) = opt_block.as_ref() {
-                                println!("# method {} has default impl", name);
-                                try_str(|| trans_method(trcx,
-                                                        self,
-                                                        (item.id, &item.ident, None),
-                                                        sig,
-                                                        block), &*name)
+                            if opt_block.is_some() && filter.excludes(&*name) {
+                                ir::Item::Comment(format!("filtered {}", name))
+                            } else if let Some(block) = opt_block.as_ref() {
+                                trace(trcx, &*format!("method {} has default impl", name));
+                                let span = diag_span(trcx, item.span);
+                                try_item(|| trans_method(trcx,
+                                                         MethodParent::Item(self),
+                                                         (item.id, &item.ident, None),
+                                                         sig,
+                                                         block,
+                                                         item.span), &*name, span)
                             } else {
-                                format!("# unimplemented trait method")
+                                ir::Item::Comment(format!("unimplemented trait method"))
                             }
                         },
-                        _ => format!("# non-method trait item"),
+                        _ => ir::Item::Comment(format!("non-method trait item")),
                     };
-                    result.push_str(part.as_slice());
-                    result.push_str("\n");
+                    parts.push(part);
                 }
-                result
+                ir::Entry::Group(parts, None)
             },
-            _ => format!(""),
+            _ => ir::Entry::Single(None),
         }
     }
 }
 
 fn combine_generics(trcx: &mut TransCtxt,
-                    impl_g: &Generics,
+                    impl_g: ir::Generics,
                     fn_g: &Generics,
-                    add_self: bool) -> (Vec<String>, Vec<String>) {
+                    add_self: bool) -> ir::Generics {
     let lifetimes =
-            impl_g.lifetimes.iter().map(|l| format!("r_named_0_{}", l.lifetime.id)).chain(
+            impl_g.lifetimes.into_iter().chain(
             fn_g.lifetimes.iter().map(|l| format!("r_named_0_{}", l.lifetime.id))).collect();
     let ty_params =
-            (0..impl_g.ty_params.len()).map(|i| format!("t_{}", i)).chain(
+            impl_g.ty_params.into_iter().chain(
             (if add_self { Some(String::from_str("s_0")) } else { None }).into_iter()).chain(
             (0..fn_g.ty_params.len()).map(|i| format!("f_{}", i))).collect();
-    (lifetimes, ty_params)
+    ir::Generics {
+        lifetimes: lifetimes,
+        ty_params: ty_params,
+    }
 }
 
 fn clean_path_elem(s: &str, out: &mut String) {
@@ -1417,6 +2128,7 @@ fn clean_path_elem(s: &str, out: &mut String) {
 }
 
 fn mangled_def_name(trcx: &mut TransCtxt, did: DefId) -> String {
+    let did = item_def_id(trcx, did);
     let mut name = String::new();
     if did.krate == LOCAL_CRATE {
         name.push_str(&*trcx.crate_name);
@@ -1461,65 +2173,81 @@ fn sanitize_ident(s: &str) -> String {
     result
 }
 
-/*
-fn find_item_ast<'tcx>(tcx: &ty::ctxt<'tcx>, did: DefId) -> Option<&'tcx Item> {
-    if did.krate == LOCAL_CRATE {
-        match tcx.map.get(did.node) {
-            ast_map::NodeItem(ast) => Some(ast),
-            _ => panic!("expected NodeItem"),
-        }
-    } else {
-        let result = csearch::maybe_get_item_ast(
-            tcx, did,
-            |a,b,c,d| astencode::decode_inlined_item(a, b, c, d));
-        let item = match result {
-            csearch::not_found => return None,
-            csearch::found(item) => item,
-            csearch::found_parent(_, item) => item,
-        };
-        match item {
-            &IIItem(ref ast) => Some(&**ast),
-            _ => panic!("expected IIItem"),
-        }
+/// The definition `did` stands for.  Items decoded from another crate have
+/// local ids, but keep the names and predicates of the original.
+fn item_def_id(trcx: &TransCtxt, did: DefId) -> DefId {
+    if did.krate != LOCAL_CRATE {
+        return did;
+    }
+    match trcx.inlined.get(&did.node) {
+        Some(&orig) => orig,
+        None => did,
     }
 }
 
-fn find_trait_item_ast<'tcx>(tcx: &ty::ctxt<'tcx>, did: DefId) -> Option<&'tcx TraitItem> {
-    if did.krate == LOCAL_CRATE {
-        match tcx.map.get(did.node) {
-            ast_map::NodeTraitItem(ast) => Some(ast),
-            _ => panic!("expected NodeTraitItem"),
-        }
-    } else {
-        let result = csearch::maybe_get_item_ast(
-            tcx, did,
-            |a,b,c,d| astencode::decode_inlined_item(a, b, c, d));
-        let item = match result {
-            csearch::not_found => return None,
-            csearch::found(item) => item,
-            csearch::found_parent(_, item) => item,
-        };
-        match item {
-            &IITraitItem(_, ref ast) => Some(ast),
-            _ => panic!("expected IITraitItem"),
-        }
+/// Queue a definition from another crate for `--crust-inline-deps`.
+fn note_dep(trcx: &mut TransCtxt, dep: Dep) {
+    let did = match dep {
+        Dep::Item(did) | Dep::Adt(did) => did,
+    };
+    if !trcx.inline_deps || did.krate == LOCAL_CRATE {
+        return;
+    }
+    if trcx.deps_seen.insert(dep) {
+        trcx.dep_queue.push(dep);
     }
 }
-*/
+
+/// Decode the AST of a definition in another crate from its metadata.  Only
+/// generic and `#[inline]` fns, methods and consts are there.  The decoded
+/// item is numbered with fresh local `NodeId`s.
+fn find_item_ast<'tcx>(tcx: &ty::ctxt<'tcx>, did: DefId) -> Option<&'tcx InlinedItem> {
+    assert!(did.krate != LOCAL_CRATE);
+    let result = csearch::maybe_get_item_ast(
+        tcx, did,
+        Box::new(|a,b,c,d| astencode::decode_inlined_item(a, b, c, d)));
+    match result {
+        csearch::FoundAst::NotFound => None,
+        csearch::FoundAst::Found(item) => Some(item),
+        csearch::FoundAst::FoundParent(_, item) => Some(item),
+    }
+}
+
+/// The impl or trait a method belongs to.  A method decoded from another
+/// crate comes without its parent's AST, so only the parent's `DefId` is known.
+#[derive(Clone, Copy)]
+enum MethodParent<'a> {
+    Item(&'a Item),
+    ForeignImpl(DefId),
+    ForeignTrait(DefId),
+}
 
 fn trans_method(trcx: &mut TransCtxt,
-                trait_: &Item,
+                parent: MethodParent,
                 (meth_id, name, opt_vis): (NodeId, &Ident, Option<Visibility>),
                 sig: &MethodSig,
-                body: &Block) -> String {
+                body: &Block,
+                span: Span) -> ir::Item {
     let mangled_name = mangled_def_name(trcx, local_def(meth_id));
-
-    let (is_default, impl_generics, trait_ref, self_ty_str) = match trait_.node {
-        ItemImpl(_, _, ref generics, ref trait_ref, ref self_ty, _) =>
-            (false, generics, trait_ref.as_ref(), self_ty.trans(trcx)),
-        ItemTrait(_, ref generics, _, _) =>
-            (true, generics, None, String::from_str("var s_0")),
-        _ => panic!("expected ItemImpl"),
+    trcx.cur_fn = mangled_name.clone();
+    trcx.default_unwind = unwind_attr(trcx, meth_id);
+
+    let (is_default, impl_generics, self_ty) = match parent {
+        MethodParent::Item(item) => match item.node {
+            ItemImpl(_, _, ref generics, _, ref self_ty, _) =>
+                (false, generics.trans_extra(trcx, TypeSpace), Some(self_ty.trans(trcx))),
+            ItemTrait(_, ref generics, _, _) =>
+                (true, generics.trans_extra(trcx, TypeSpace), None),
+            _ => panic!("expected ItemImpl"),
+        },
+        MethodParent::ForeignImpl(impl_did) => {
+            let scheme = ty::lookup_item_type(trcx.tcx, impl_did);
+            (false, scheme.generics.trans_extra(trcx, TypeSpace), Some(scheme.ty.trans(trcx)))
+        },
+        MethodParent::ForeignTrait(trait_did) => {
+            let trait_def = ty::lookup_trait_def(trcx.tcx, trait_did);
+            (true, trait_def.generics.trans_extra(trcx, TypeSpace), None)
+        },
     };
 
     let generics = &sig.generics;
@@ -1527,148 +2255,353 @@ fn trans_method(trcx: &mut TransCtxt,
     let style = &sig.unsafety;
     let decl = &*sig.decl;
 
-    let mut arg_strs = vec![];
-
 
     let self_arg = match exp_self.node {
         SelfStatic => None,
         SelfValue(ref name) => {
-            Some(format!("({} var {})",
-                         self_ty_str,
-                         name.trans(trcx)))
+            Some(ir::SelfArg::Value(self_ty.clone(),
+                                    name.trans(trcx)))
         },
         SelfRegion(ref opt_lifetime, mutbl, ref name) => {
-            Some(format!("({} {} {} var {})",
-                         match mutbl {
-                             MutMutable => "ref_mut",
-                             MutImmutable => "ref",
-                         },
-                         match *opt_lifetime {
-                             Some(ref lifetime) =>
-                                 lifetime.trans(trcx),
-                             None => format!("r_anon_0"),
-                         },
-                         self_ty_str,
-                         name.trans(trcx)))
+            Some(ir::SelfArg::Region(match *opt_lifetime {
+                                         Some(ref lifetime) =>
+                                             lifetime.trans(trcx),
+                                         None => format!("r_anon_0"),
+                                     },
+                                     mutbl.trans(trcx),
+                                     self_ty.clone(),
+                                     name.trans(trcx)))
         },
         SelfExplicit(ref ty, ref name) => {
-            Some(format!("({} var {})",
-                         ty.trans(trcx),
-                         name.trans(trcx)))
+            Some(ir::SelfArg::Explicit(ty.trans(trcx),
+                                       name.trans(trcx)))
         },
     };
     let offset = match self_arg {
-        Some(arg) => {
-            arg_strs.push(arg);
-            1
-        },
+        Some(_) => 1,
         None => 0,
     };
 
-    let impl_clause =
-        if !is_default {
-            match trait_ref {
-                Some(trait_ref) => {
-                    let name_str = name.trans(trcx);
-                    format!("1 {}",
-                            trans_impl_clause(trcx,
-                                              trait_.id,
-                                              trait_ref,
-                                              Some(name_str),
-                                              self_ty_str,
-                                              generics.lifetimes.as_slice(),
-                                              generics.ty_params.as_slice()))
-                },
-                None => format!("0"),
-            }
-        } else {
-            format!("0")
-        };
+    let impl_clause = match parent {
+        MethodParent::Item(item) => match item.node {
+            ItemImpl(_, _, _, Some(ref trait_ref), _, _) => {
+                let name_str = name.trans(trcx);
+                Some(trans_impl_clause(trcx,
+                                       item.id,
+                                       trait_ref,
+                                       Some(name_str),
+                                       generics.lifetimes.as_slice(),
+                                       generics.ty_params.as_slice()))
+            },
+            _ => None,
+        },
+        MethodParent::ForeignImpl(impl_did) => match ty::impl_trait_ref(trcx.tcx, impl_did) {
+            Some(trait_ref) => {
+                let name_str = name.trans(trcx);
+                Some(trait_ref_clause(trcx,
+                                      &*trait_ref,
+                                      Some(name_str),
+                                      generics.lifetimes.as_slice(),
+                                      generics.ty_params.as_slice()))
+            },
+            None => None,
+        },
+        MethodParent::ForeignTrait(_) => None,
+    };
 
-    let (lifetimes, mut ty_params) = combine_generics(trcx, impl_generics, generics, is_default);
-    let vis_str =
-        if trait_ref.is_none() && !is_default {
+    let fn_generics = combine_generics(trcx, impl_generics, generics, is_default);
+    let vis =
+        if impl_clause.is_none() && !is_default {
             opt_vis.unwrap().trans(trcx)
         } else {
-            format!("pub")
+            ir::Visibility::Public
         };
 
 
 
 
-    arg_strs.extend(decl.inputs[offset..].iter().map(|x| x.trans(trcx)));
-    format!("fn {} {}{} {} {} (args {}) return {} {} preds {} body {} {} {{\n{}\t{}\n}}\n\n",
-            vis_str,
-            mangled_name,
-            if is_default { "$$__default" } else { "" },
-            lifetimes.trans(trcx),
-            ty_params.trans(trcx),
-            arg_strs.trans(trcx),
-            decl.output.trans(trcx),
-            impl_clause,
-            trcx.tcx.predicates.borrow().get(&local_def(meth_id))
-                .unwrap().trans(trcx),
-            decl.output.trans(trcx),
-            match *style {
-                Unsafety::Unsafe => "unsafe",
-                Unsafety::Normal => "block",
-            },
-            body.stmts.trans(trcx),
-            body.expr.as_ref().map(|e| e.trans(trcx))
-                .unwrap_or(format!("[unit] simple_literal _method")))
+    let args = decl.inputs[offset..].iter().map(|x| x.trans(trcx)).collect::<Vec<_>>();
+    let args_checked = ref_args(trcx, self_arg.as_ref(), &*args, &*decl.inputs);
+    trcx.ref_args = args_checked;
+    trcx.unsafe_depth = 0;
+    let mut body = trans_block_body(trcx, fn_unsafety(*style), body, "_method");
+    if trcx.borrow_model {
+        body = borrows::instrument(self_arg.as_ref(), &*args, body);
+    }
+    let f = ir::FnDef {
+        vis: vis,
+        exported: is_exported(trcx, meth_id),
+        name: if is_default {
+            format!("{}$$__default", mangled_name)
+        } else {
+            mangled_name
+        },
+        generics: fn_generics,
+        self_arg: self_arg,
+        args: args,
+        ret: decl.output.trans(trcx),
+        impl_clause: impl_clause,
+        preds: ty::lookup_predicates(trcx.tcx, item_def_id(trcx, local_def(meth_id)))
+                   .trans(trcx),
+        body: body,
+        span: trans_span(trcx, span),
+    };
+    // Default methods are copied into impls by Preprocess, under the impl's
+    // generics, so they can't be stubbed here.  Stub the overriding method
+    // instead.
+    if is_default {
+        ir::Item::Fn(f)
+    } else {
+        ir::Item::Fn(apply_stub(trcx, f))
+    }
+}
+
+/// Replace the body of `f` with a call to its model, if it has one.  The
+/// arguments are renamed to plain variables so they can be forwarded whatever
+/// patterns they were written with.
+fn apply_stub(trcx: &mut TransCtxt, mut f: ir::FnDef) -> ir::FnDef {
+    let model = match trcx.stubs.model_for(&*f.name) {
+        Some(model) => String::from_str(model),
+        None => return f,
+    };
+    trace(trcx, &*format!("stubbing {} with {}", f.name, model));
+
+    let mut call_args = Vec::new();
+    match f.self_arg {
+        Some(ir::SelfArg::Value(Some(ref ty), ref name)) =>
+            call_args.push(ir::Expr::new(ty.clone(), ir::ExprKind::Var(name.clone()))),
+        Some(ir::SelfArg::Region(ref r, m, Some(ref ty), ref name)) =>
+            call_args.push(ir::Expr::new(ir::Ty::Ref(r.clone(), m, Box::new(ty.clone())),
+                                         ir::ExprKind::Var(name.clone()))),
+        Some(ir::SelfArg::Explicit(ref ty, ref name)) =>
+            call_args.push(ir::Expr::new(ty.clone(), ir::ExprKind::Var(name.clone()))),
+        Some(_) => panic!("can't stub {}: self type is unknown", f.name),
+        None => {},
+    }
+    for (i, arg) in f.args.iter_mut().enumerate() {
+        let name = format!("__stub_arg{}", i);
+        *arg = ir::Pat { ty: arg.ty.clone(), kind: ir::PatKind::Var(name.clone()) };
+        call_args.push(ir::Expr::new(arg.ty.clone(), ir::ExprKind::Var(name)));
+    }
+
+    let ret_ty = match f.ret {
+        ir::Return::Value(ref ty) => ty.clone(),
+        ir::Return::Diverging | ir::Return::Default => ir::Ty::Unit,
+    };
+    // Regions are erased during monomorphization, so only the type
+    // parameters need forwarding.
+    let substs = ir::Substs {
+        regions: Vec::new(),
+        types: f.generics.ty_params.iter().map(|t| ir::Ty::Var(t.clone())).collect(),
+    };
+    let call = ir::Expr::new(ret_ty, ir::ExprKind::Call(model, substs, call_args));
+    // A diverging function still never returns: the model must diverge too
+    // (Preprocess checks this with `--stub-models`), so cut off the path
+    // after the call rather than falling off the end with a unit value.
+    let (stmts, tail) = match f.ret {
+        ir::Return::Diverging => {
+            let empty = ir::Substs { regions: Vec::new(), types: Vec::new() };
+            let unreachable = ir::ExprKind::Call(format!("__crust$unreachable"), empty, Vec::new());
+            (vec![ir::Stmt::Expr(call)], ir::Expr::new(ir::Ty::Unit, unreachable))
+        },
+        _ => (Vec::new(), call),
+    };
+    f.body = ir::Block {
+        unsafety: f.body.unsafety,
+        stmts: stmts,
+        expr: Box::new(tail),
+    };
+    f
 }
 
 fn add_fn_lifetimes(trcx: &mut TransCtxt,
                     fn_lifetimes: &[LifetimeDef],
-                    lifetimes: &mut Vec<String>) {
+                    lifetimes: &mut Vec<ir::Region>) {
     lifetimes.extend(fn_lifetimes.iter().map(|l| format!("r_named_0_{}", l.lifetime.id)));
 }
 
 fn add_fn_ty_params(trcx: &mut TransCtxt,
                     fn_ty_params: &[TyParam],
-                    ty_params: &mut Vec<String>) {
-    ty_params.extend((0..fn_ty_params.len()).map(|i| format!("var f_{}", i)));
+                    ty_params: &mut Vec<ir::Name>) {
+    ty_params.extend((0..fn_ty_params.len()).map(|i| format!("f_{}", i)));
 }
 
 fn trans_impl_clause(trcx: &mut TransCtxt,
                      impl_id: NodeId,
                      trait_ref: &TraitRef,
                      name: Option<String>,
-                     self_ty: String,
                      fn_lifetimes: &[LifetimeDef],
-                     fn_ty_params: &[TyParam]) -> String {
+                     fn_ty_params: &[TyParam]) -> ir::ImplClause {
     let last_seg = trait_ref.path.segments.as_slice().last().unwrap();
-    let (mut lifetimes, mut ty_params): (Vec<_>, Vec<_>) = match last_seg.parameters {
-        AngleBracketedParameters(ref params) => {
-            let trait_refs = trcx.tcx.impl_trait_refs.borrow();
-            let substs = trait_refs[&impl_id].substs;
-            let ls = match substs.regions {
-                subst::ErasedRegions => panic!("unsupported ErasedRegions"),
-                subst::NonerasedRegions(ref regions) =>
-                    regions.as_slice().iter().map(|r| r.trans(trcx)).collect(),
-            };
-            let ts = substs.types.as_slice().iter().map(|t| t.trans(trcx)).collect();
-            (ls, ts)
-        },
+    match last_seg.parameters {
+        AngleBracketedParameters(_) => {},
         ParenthesizedParameters(_) =>
             panic!("unsupported ParenthesizedParameters"),
+    }
+    let ty_trait_ref = trcx.tcx.impl_trait_refs.borrow()[&impl_id].clone();
+    trait_ref_clause(trcx, &*ty_trait_ref, name, fn_lifetimes, fn_ty_params)
+}
+
+/// The impl clause for an impl of `trait_ref`, which also serves impls from
+/// other crates.
+fn trait_ref_clause(trcx: &mut TransCtxt,
+                    trait_ref: &ty::TraitRef,
+                    name: Option<String>,
+                    fn_lifetimes: &[LifetimeDef],
+                    fn_ty_params: &[TyParam]) -> ir::ImplClause {
+    let substs = trait_ref.substs;
+    let mut lifetimes: Vec<_> = match substs.regions {
+        subst::ErasedRegions => panic!("unsupported ErasedRegions"),
+        subst::NonerasedRegions(ref regions) =>
+            regions.as_slice().iter().map(|r| r.trans(trcx)).collect(),
     };
+    let ty_args = substs.types.as_slice().iter().map(|t| t.trans(trcx)).collect();
 
+    let mut ty_params = Vec::new();
     add_fn_lifetimes(trcx, fn_lifetimes, &mut lifetimes);
     add_fn_ty_params(trcx, fn_ty_params, &mut ty_params);
 
-    let name_tail =
-        if let Some(name) = name {
-            format!("${}", name)
-        } else {
-            format!("")
+    ir::ImplClause {
+        trait_name: mangled_def_name(trcx, trait_ref.def_id),
+        item: name,
+        lifetimes: lifetimes,
+        ty_args: ty_args,
+        fn_ty_params: ty_params,
+    }
+}
+
+/// Translate the definitions from other crates queued by `note_dep`, and
+/// those they use in turn.
+fn trans_deps(trcx: &mut TransCtxt, filter: &Filter) {
+    while let Some(dep) = trcx.dep_queue.pop() {
+        let did = match dep {
+            Dep::Item(did) | Dep::Adt(did) => did,
         };
+        let name = mangled_def_name(trcx, did);
+        if filter.excludes(&*name) {
+            emit_entry(trcx, &ir::Entry::Single(Some(ir::Item::Comment(
+                    format!("filtered {}", name)))));
+            continue;
+        }
+        let entry = match try_trans(|| trans_dep(trcx, dep, filter), &*name, None) {
+            Ok(entry) => entry,
+            Err(err) => ir::Entry::Single(Some(err)),
+        };
+        emit_entry(trcx, &entry);
+    }
+}
+
+fn trans_dep(trcx: &mut TransCtxt, dep: Dep, filter: &Filter) -> ir::Entry {
+    let did = match dep {
+        Dep::Adt(did) => return trans_foreign_adt(trcx, did),
+        Dep::Item(did) => did,
+    };
+    let inlined = match find_item_ast(trcx.tcx, did) {
+        Some(inlined) => inlined,
+        None => {
+            let name = mangled_def_name(trcx, did);
+            trace(trcx, &*format!("no inlined AST for {}", name));
+            return ir::Entry::Single(None);
+        },
+    };
+    match *inlined {
+        IIItem(ref item) => {
+            trcx.inlined.insert(item.id, did);
+            item.trans_extra(trcx, filter)
+        },
+        IIImplItem(impl_did, ref item) => {
+            trcx.inlined.insert(item.id, did);
+            let f = match item.node {
+                MethodImplItem(ref sig, ref body) =>
+                    trans_method(trcx,
+                                 MethodParent::ForeignImpl(impl_did),
+                                 (item.id, &item.ident, Some(item.vis)),
+                                 sig,
+                                 body,
+                                 item.span),
+                _ => return ir::Entry::Single(None),
+            };
+            let decl = if trcx.dep_impls.insert(impl_did) {
+                foreign_impl_decl(trcx, impl_did)
+            } else {
+                None
+            };
+            ir::Entry::Group(vec![f], decl)
+        },
+        IITraitItem(trait_did, ref item) => {
+            trcx.inlined.insert(item.id, did);
+            match item.node {
+                MethodTraitItem(ref sig, Some(ref body)) =>
+                    ir::Entry::Single(Some(trans_method(trcx,
+                                                        MethodParent::ForeignTrait(trait_did),
+                                                        (item.id, &item.ident, None),
+                                                        sig,
+                                                        body,
+                                                        item.span))),
+                _ => ir::Entry::Single(None),
+            }
+        },
+        IIForeign(_) => ir::Entry::Single(None),
+    }
+}
 
-    format!("{}{} {} {}",
-            mangled_def_name(trcx, trcx.tcx.def_map.borrow()[&trait_ref.ref_id].def_id()),
-            name_tail,
-            lifetimes.trans(trcx),
-            ty_params.trans(trcx))
+/// The `Impl` declaration for a trait impl in another crate, which the impl's
+/// methods need for dispatch.
+fn foreign_impl_decl(trcx: &mut TransCtxt, impl_did: DefId) -> Option<ir::Item> {
+    let trait_ref = match ty::impl_trait_ref(trcx.tcx, impl_did) {
+        Some(trait_ref) => trait_ref,
+        None => return None,
+    };
+    let generics = ty::lookup_item_type(trcx.tcx, impl_did).generics
+                       .trans_extra(trcx, TypeSpace);
+    let clause = trait_ref_clause(trcx, &*trait_ref, None, [].as_slice(), [].as_slice());
+    let preds = ty::lookup_predicates(trcx.tcx, impl_did).trans(trcx);
+    Some(ir::Item::Impl(generics, clause, preds))
+}
+
+/// A struct or enum from another crate.  Type definitions have no inlined AST,
+/// so this works from the type information in the metadata.
+fn trans_foreign_adt(trcx: &mut TransCtxt, did: DefId) -> ir::Entry {
+    let scheme = ty::lookup_item_type(trcx.tcx, did);
+    let name = mangled_def_name(trcx, did);
+    let generics = scheme.generics.trans_extra(trcx, TypeSpace);
+    let dtor = ty::ty_dtor(trcx.tcx, did).trans(trcx);
+    match scheme.ty.sty {
+        ty::ty_struct(..) => {
+            let fields = ty::lookup_struct_fields(trcx.tcx, did).iter().enumerate()
+                             .map(|(i, f)| ir::FieldDef {
+                vis: f.vis.trans(trcx),
+                name: if f.name == special_idents::unnamed_field.name {
+                    format!("field{}", i)
+                } else {
+                    f.name.trans(trcx)
+                },
+                ty: ty::lookup_field_type_unsubstituted(trcx.tcx, did, f.id).trans(trcx),
+            }).collect();
+            struct_entry(ir::StructDef {
+                name: name,
+                generics: generics,
+                fields: fields,
+                dtor: dtor,
+                span: None,
+            })
+        },
+        ty::ty_enum(..) => {
+            let variants = ty::enum_variants(trcx.tcx, did).iter()
+                               .map(|v| ir::VariantDef {
+                name: mangled_def_name(trcx, v.id),
+                args: v.args.trans(trcx),
+            }).collect();
+            enum_entry(trcx, did, ir::EnumDef {
+                name: name,
+                generics: generics,
+                variants: variants,
+                dtor: dtor,
+                span: None,
+            })
+        },
+        _ => ir::Entry::Single(None),
+    }
 }
 
 fn print_abstract_fn_decls(trcx: &mut TransCtxt) {
@@ -1680,7 +2613,7 @@ fn print_abstract_fn_decls(trcx: &mut TransCtxt) {
 
 
     for (name, method_did) in names.into_iter() {
-        println!("{}", try_str(|| {
+        let item = try_item(|| {
             let (method_generics, inputs, output) = {
                 let trait_defs = trcx.tcx.trait_defs.borrow();
                 let impl_or_trait_items = trcx.tcx.impl_or_trait_items.borrow();
@@ -1701,7 +2634,10 @@ fn print_abstract_fn_decls(trcx: &mut TransCtxt) {
 
             let mut args = Vec::new();
             for (i, arg_ty) in inputs.iter().enumerate() {
-                args.push(format!("({} var arg{})", arg_ty.trans(trcx), i));
+                args.push(ir::Pat {
+                    ty: arg_ty.trans(trcx),
+                    kind: ir::PatKind::Var(format!("arg{}", i)),
+                });
             }
 
             let mut regions = Vec::new();
@@ -1719,17 +2655,16 @@ fn print_abstract_fn_decls(trcx: &mut TransCtxt) {
             }
 
             let return_ty = match output {
-                ty::FnConverging(ty) => ty.trans(trcx),
-                ty::FnDiverging => format!("bottom"),
+                ty::FnConverging(ty) => ir::Return::Value(ty.trans(trcx)),
+                ty::FnDiverging => ir::Return::Diverging,
             };
 
-            format!("abstract_fn {} {} {} args {} return {}",
-                    name,
-                    regions.trans(trcx),
-                    types.trans(trcx),
-                    args.trans(trcx),
-                    return_ty)
-        }, &*name));
+            ir::Item::AbstractFn(name.clone(),
+                                 ir::Generics { lifetimes: regions, ty_params: types },
+                                 args,
+                                 return_ty)
+        }, &*name, None);
+        emit_item(trcx, &item);
     }
 }
 
@@ -1741,7 +2676,7 @@ fn print_abstract_type_decls(trcx: &mut TransCtxt) {
     let names = names;
 
     for (name, trait_did) in names.into_iter() {
-        println!("{}", try_str(|| {
+        let item = try_item(|| {
             let trait_generics = {
                 let trait_defs = trcx.tcx.trait_defs.borrow();
                 let opt_trait = trait_defs.get(&trait_did);
@@ -1767,11 +2702,10 @@ fn print_abstract_type_decls(trcx: &mut TransCtxt) {
                                    ty_param.index));
             }
 
-            format!("abstract_type {} {} {}",
-                    name,
-                    regions.trans(trcx),
-                    types.trans(trcx))
-        }, &*name));
+            ir::Item::AbstractType(name.clone(),
+                                   ir::Generics { lifetimes: regions, ty_params: types })
+        }, &*name, None);
+        emit_item(trcx, &item);
     }
 }
 
@@ -1784,18 +2718,109 @@ fn print_str_lit<I: Iterator<Item=u8>>(bytes: I) -> String {
 }
 
 
-pub fn process(tcx: &ty::ctxt, filter_fn : HashSet<String>, crate_name: String) {
+/// Write `diags` to `path` as JSON Lines, or to stderr, followed by a count of
+/// each kind of construct on stderr.
+pub fn report_diagnostics(diags: &[ir::Diagnostic], path: &Option<PathBuf>) {
+    let mut err = io::stderr();
+    match *path {
+        Some(ref path) => {
+            let mut f = fs::File::create(path).unwrap();
+            for diag in diags.iter() {
+                writeln!(f, "{}", diag.json()).unwrap();
+            }
+        },
+        None => {
+            for diag in diags.iter() {
+                let pos = match diag.span {
+                    Some(ref sp) => format!("{}:{}:{}: ", sp.file, sp.lo_line, sp.lo_col + 1),
+                    None => String::new(),
+                };
+                writeln!(err, "{}error: {}: {} [{}]",
+                         pos, diag.item, diag.message, diag.construct).unwrap();
+                if let Some(ref help) = diag.help {
+                    writeln!(err, "{}help: {}", pos, help).unwrap();
+                }
+            }
+        },
+    }
+
+    if diags.len() == 0 {
+        return;
+    }
+    let mut counts = BTreeMap::new();
+    for diag in diags.iter() {
+        let count = counts.get(&*diag.construct).map_or(0, |&c| c);
+        counts.insert(&*diag.construct, count + 1);
+    }
+    writeln!(err, "translation failures by construct:").unwrap();
+    for (construct, count) in counts.iter() {
+        writeln!(err, "{:>8}  {}", count, construct).unwrap();
+    }
+    writeln!(err, "{:>8}  total", diags.len()).unwrap();
+}
+
+/// Translate the crate, writing the IR to `opts.output` or stdout.  Returns
+/// the number of items that failed to translate.
+pub fn process(tcx: &ty::ctxt,
+               exported_items: &ExportedItems,
+               crate_name: String,
+               opts: Options) -> usize {
     let krate = tcx.map.krate();
+    // Write to a temporary file next to the output and rename it into place
+    // at the end, so a failed run never leaves a truncated IR file behind.
+    let tmp_output = opts.output.as_ref().map(|p| {
+        let mut name = p.file_name().unwrap().to_os_string();
+        name.push(".tmp");
+        p.with_file_name(&name)
+    });
+    let out: Box<Write> = match tmp_output {
+        Some(ref tmp) => Box::new(io::BufWriter::new(fs::File::create(tmp).unwrap())),
+        None => Box::new(io::stdout()),
+    };
     let mut trcx = TransCtxt {
         tcx: tcx,
+        exported_items: exported_items,
         observed_abstract_fns: HashMap::new(),
         observed_abstract_types: HashMap::new(),
         crate_name: crate_name,
+        stubs: opts.stubs,
+        format: opts.format,
+        spans: opts.spans,
+        diagnostics: Vec::new(),
+        out: out,
+        verbose: opts.verbose,
+        cur_fn: String::new(),
+        default_unwind: None,
+        alias_checks: opts.alias_checks,
+        borrow_model: opts.borrow_model,
+        unsafe_depth: 0,
+        ref_args: Vec::new(),
+        mut_ref_counter: 0,
+        inline_deps: opts.inline_deps,
+        dep_queue: Vec::new(),
+        deps_seen: HashSet::new(),
+        dep_impls: HashSet::new(),
+        inlined: HashMap::new(),
     };
+    if opts.format == Format::Json {
+        let header = json::header(&*trcx.crate_name);
+        emit_line(&mut trcx, &*header);
+    }
     {
-        let mut visitor = TransVisitor { trcx: &mut trcx, filter_fn: filter_fn };
+        let mut visitor = TransVisitor { trcx: &mut trcx, filter: opts.filter };
         visit::walk_crate(&mut visitor, krate);
+        trans_deps(visitor.trcx, &visitor.filter);
     }
     print_abstract_fn_decls(&mut trcx);
     print_abstract_type_decls(&mut trcx);
+
+    trcx.out.flush().unwrap();
+    if let (Some(tmp), Some(output)) = (tmp_output, opts.output) {
+        // Drop the writer to close the file before renaming it.
+        trcx.out = Box::new(io::sink());
+        fs::rename(&tmp, &output).unwrap();
+    }
+
+    report_diagnostics(&*trcx.diagnostics, &opts.diagnostics_file);
+    trcx.diagnostics.len()
 }
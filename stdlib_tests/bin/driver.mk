@@ -13,12 +13,24 @@ ZEALOT ?= python $(TEST_HOME)/bin/zealot.py
 SCRIPT_BIN ?= $(TEST_HOME)/bin
 
 
-STDLIBS = core libc alloc unicode collections __crust2
+STDLIBS = core libc alloc unicode collections __crust2 __crust_models
 STDLIB_RLIBS = $(patsubst %,lib/lib%.rlib,$(STDLIBS))
 STDLIB_IRS = $(patsubst %,ir/lib%.ir,$(STDLIBS))
 
 TARGET = $(TEST_HOME)/x86_64-custom-linux-gnu.json
 
+# Optional `TARGET MODEL` list of functions to replace with models (see
+# src/stubs.rs).  The models themselves live in src/crust-models.rs.
+STUBS ?=
+RBMC_FLAGS = $(if $(STUBS),--crust-stubs $(STUBS))
+PREPROCESS_FLAGS = $(if $(STUBS),--stub-models $(STUBS)) $(if $(UNWIND),--unwind)
+
+# Extra flags for crust, e.g. `-panic-check` to report reachable panics.
+CRUST_FLAGS ?=
+# Set UNWIND=1 to let panics unwind through cleanup (see README).
+UNWIND ?=
+CRUST_FLAGS += $(if $(UNWIND),-unwind)
+
 
 lib/lib%.rlib: $(SRC)/lib%/lib.rs
 	$(RUSTC) -L lib --out-dir=lib --target=$(TARGET) $<
@@ -32,30 +44,37 @@ lib/lib%.rlib: $(CRUST_HOME)/tests/driver/%.rs
 lib/lib%.rlib: $(CRUST_HOME)/tests/custom/%.rs
 	$(RUSTC) -L lib --out-dir=lib --target=$(TARGET) $<
 
-lib/lib__crust.rlib: $(CRUST_HOME)/src/crust-stubs.rs
+lib/lib__crust.rlib: $(CRUST_HOME)/src/crust-stubs.rs lib/libcollections.rlib
 	$(RUSTC) -L lib --out-dir=lib --target=$(TARGET) $<
 
 lib/lib__crust2.rlib: $(CRUST_HOME)/src/crust.rs lib/lib__crust.rlib
 	$(RUSTC) -L lib --out-dir=lib --target=$(TARGET) $<
 
+lib/lib__crust_models.rlib: $(CRUST_HOME)/src/crust-models.rs lib/lib__crust.rlib
+	$(RUSTC) -L lib --out-dir=lib --target=$(TARGET) $<
+
 
 ir/lib%.ir: $(SRC)/lib%/lib.rs $(STDLIB_RLIBS)
-	$(RBMC) -L lib --target=$(TARGET) $< >$@.tmp
+	$(RBMC) $(RBMC_FLAGS) -L lib --target=$(TARGET) $< >$@.tmp
 	mv -v $@.tmp $@
 
 ir/lib%.ir: $(CRUST_HOME)/tests/%.rs $(STDLIB_RLIBS)
-	$(RBMC) -L lib --target=$(TARGET) $< >$@.tmp
+	$(RBMC) $(RBMC_FLAGS) -L lib --target=$(TARGET) $< >$@.tmp
 	mv -v $@.tmp $@
 
 ir/lib%.ir: $(CRUST_HOME)/tests/driver/%.rs $(STDLIB_RLIBS)
-	$(RBMC) -L lib --target=$(TARGET) $< >$@.tmp
+	$(RBMC) $(RBMC_FLAGS) -L lib --target=$(TARGET) $< >$@.tmp
 	mv -v $@.tmp $@
 
 ir/lib%.ir: $(CRUST_HOME)/tests/custom/%.rs $(STDLIB_RLIBS)
-	$(RBMC) -L lib --target=$(TARGET) $< >$@.tmp
+	$(RBMC) $(RBMC_FLAGS) -L lib --target=$(TARGET) $< >$@.tmp
 	mv -v $@.tmp $@
 
 ir/lib__crust2.ir: $(CRUST_HOME)/src/crust.rs $(STDLIB_RLIBS)
+	$(RBMC) $(RBMC_FLAGS) -L lib --target=$(TARGET) $< >$@.tmp
+	mv -v $@.tmp $@
+
+ir/lib__crust_models.ir: $(CRUST_HOME)/src/crust-models.rs $(STDLIB_RLIBS)
 	$(RBMC) -L lib --target=$(TARGET) $< >$@.tmp
 	mv -v $@.tmp $@
 
@@ -66,7 +85,7 @@ ir/scrubbed.%.ir: ir/%.ir
 	mv -v $@.tmp $@
 
 ir/prep.%.ir: ir/%.ir
-	$(PREPROCESS) --passes hl-prepare-libs <$< >$@.tmp
+	$(PREPROCESS) $(PREPROCESS_FLAGS) --passes hl-prepare-libs <$< >$@.tmp
 	mv -v $@.tmp $@
 
 ir/stubs.%.ir: ir/prep.%.ir
@@ -81,11 +100,14 @@ ir/stdlibs_lib%.ir: ir/lib%.ir ir/stdlibs.ir ir/lib__crust2.ir
 	cat $^ >$@.tmp
 	mv -v $@.tmp $@
 
-ir/core_lib%.ir: ir/lib%.ir ir/libcore.ir ir/lib__crust2.ir
+# The stub models have to be in the same IR as the functions they replace.
+STUB_MODEL_IRS = $(if $(STUBS),ir/lib__crust_models.ir)
+
+ir/core_lib%.ir: ir/lib%.ir ir/libcore.ir ir/lib__crust2.ir $(STUB_MODEL_IRS)
 	cat $^ >$@.tmp
 	mv -v $@.tmp $@
 
-ir/alloc_lib%.ir: ir/lib%.ir ir/libcore.ir ir/liblibc.ir ir/liballoc.ir ir/lib__crust2.ir
+ir/alloc_lib%.ir: ir/lib%.ir ir/libcore.ir ir/liblibc.ir ir/liballoc.ir ir/lib__crust2.ir $(STUB_MODEL_IRS)
 	cat $^ >$@.tmp
 	mv -v $@.tmp $@
 
@@ -121,11 +143,11 @@ test/%.rs: driver/%.drv
 	mv -v test/$*_0.rs $@
 
 test/%.drv.ir: test/%.rs lib/lib__crust.rlib
-	$(ZEALOT) $(RBMC) -L lib --target=$(TARGET) $< >$@.tmp
+	$(ZEALOT) $(RBMC) $(RBMC_FLAGS) -L lib --target=$(TARGET) $< >$@.tmp
 	mv -v $@.tmp $@
 
 test/%.drv-prep.ir: driver/%.lib-stubs.ir test/%.drv.ir
-	cat $^ | $(PREPROCESS) --passes hl-prepare-drivers >$@.tmp
+	cat $^ | $(PREPROCESS) $(PREPROCESS_FLAGS) --passes hl-prepare-drivers >$@.tmp
 	mv -v $@.tmp $@
 
 #test/%.drv-split.stamp: test/%.drv.ir
@@ -145,7 +167,7 @@ test/%.drv-fin.ir: driver/%.lib-prep.ir test/%.drv-prep.ir
 	mv -v $@.tmp $@
 
 test/%.c: test/%.drv-fin.ir
-	$(CRUST_NATIVE) $< >$@.tmp
+	$(CRUST_NATIVE) $(CRUST_FLAGS) $< >$@.tmp
 	mv -v $@.tmp $@
 
 .SECONDARY:
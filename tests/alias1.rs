@@ -0,0 +1,65 @@
+// crust-verify
+// crust-verify-rbmc: --crust-alias-checks
+#![feature(no_std)]
+#![feature(core)]
+#![crate_type = "lib"]
+#![no_std]
+
+extern crate core;
+extern crate __crust;
+
+pub struct UnsafeCell<T> {
+    value: T,
+}
+
+impl<T> UnsafeCell<T> {
+    pub fn get(&self) -> *mut T {
+        &self.value as *const T as *mut T
+    }
+}
+
+pub struct Slot {
+    value: UnsafeCell<u32>,
+}
+
+impl Slot {
+    pub fn get(&self) -> u32 {
+        unsafe { *self.value.get() }
+    }
+
+    // Borrowed from `self` itself, so not checked against it.
+    pub fn get_mut(&self) -> &mut u32 {
+        unsafe { &mut *self.value.get() }
+    }
+
+    pub fn copy_from(&self, other: &Slot) {
+        let dst = unsafe { &mut *self.value.get() };
+        *dst = other.get();
+    }
+}
+
+pub struct Pair<'a> {
+    src: &'a Slot,
+    dst: &'a Slot,
+}
+
+impl<'a> Pair<'a> {
+    pub fn transfer(&self) {
+        let dst = unsafe { &mut *self.dst.value.get() };
+        *dst = self.src.get();
+    }
+}
+
+pub fn crust_test_0() {
+    let a = Slot { value: UnsafeCell { value: 1 } };
+    let b = Slot { value: UnsafeCell { value: 2 } };
+    *a.get_mut() = 3;
+    a.copy_from(&b);
+}
+
+pub fn crust_test_1() {
+    let a = Slot { value: UnsafeCell { value: 1 } };
+    let b = Slot { value: UnsafeCell { value: 2 } };
+    let p = Pair { src: &a, dst: &b };
+    p.transfer();
+}
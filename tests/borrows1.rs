@@ -0,0 +1,35 @@
+// crust-verify
+// crust-verify-rbmc: --crust-borrow-model
+#![feature(no_std)]
+#![feature(core)]
+#![crate_type = "lib"]
+#![no_std]
+
+extern crate core;
+extern crate __crust;
+
+pub fn crust_test_0() {
+    let mut x: i32 = 0;
+    let r = &mut x;
+    let p = r as *mut i32;
+    unsafe { *p = 2; }
+    *r = 1;
+}
+
+pub fn crust_test_1() {
+    let mut x: i32 = 0;
+    let p = &mut x as *mut i32;
+    let r = unsafe { &mut *p };
+    *r = 1;
+    unsafe { *p = 2; }
+}
+
+pub fn crust_test_2() {
+    let mut x: i32 = 0;
+    let p = &mut x as *mut i32;
+    let mut r = unsafe { &mut *p };
+    *r = 1;
+    unsafe { *p = 2; }
+    r = unsafe { &mut *p };
+    *r = 3;
+}
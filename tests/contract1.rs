@@ -0,0 +1,56 @@
+// crust-verify
+#![feature(no_std)]
+#![feature(core)]
+#![feature(custom_attribute)]
+#![crate_type = "lib"]
+#![no_std]
+
+extern crate core;
+extern crate __crust;
+
+#[crust_requires = "x < 100"]
+#[crust_ensures = "result > old(x)"]
+pub fn incr(x: u32) -> u32 {
+    x + 1
+}
+
+#[crust_requires = "x < 50"]
+#[crust_ensures = "result == x + 2"]
+pub fn incr_twice(x: u32) -> u32 {
+    incr(incr(x))
+}
+
+pub struct Counter {
+    n: u32,
+}
+
+pub trait Step {
+    fn get(&self) -> u32;
+
+    #[crust_requires = "self.get() < 10"]
+    #[crust_ensures = "result < 20"]
+    fn next(&self) -> u32 {
+        self.get() * 2
+    }
+}
+
+impl Step for Counter {
+    fn get(&self) -> u32 {
+        self.n
+    }
+}
+
+pub fn crust_test_0() {
+    let x: u32 = __crust::nondet();
+    incr(x);
+}
+
+pub fn crust_test_1() {
+    let x: u32 = __crust::nondet();
+    incr_twice(x);
+}
+
+pub fn crust_test_2() {
+    let c = Counter { n: __crust::nondet() };
+    c.next();
+}
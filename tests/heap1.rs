@@ -0,0 +1,70 @@
+// crust-verify
+#![feature(no_std)]
+#![feature(core)]
+#![feature(alloc)]
+#![crate_type = "lib"]
+#![no_std]
+
+extern crate core;
+extern crate alloc;
+extern crate __crust;
+
+use alloc::heap;
+use core::prelude::Drop;
+
+pub struct Buf {
+    ptr: *mut u8,
+    size: usize,
+}
+
+impl Buf {
+    pub fn new(size: usize) -> Buf {
+        Buf { ptr: unsafe { heap::allocate(size, 1) }, size: size }
+    }
+
+    pub fn grow(&mut self, size: usize) {
+        if self.ptr as usize == 0 {
+            return;
+        }
+        unsafe {
+            let ptr = heap::reallocate(self.ptr, self.size, size, 1);
+            if ptr as usize != 0 {
+                self.ptr = ptr;
+                self.size = size;
+            }
+        }
+    }
+}
+
+impl Drop for Buf {
+    fn drop(&mut self) {
+        if self.ptr as usize != 0 {
+            unsafe { heap::deallocate(self.ptr, self.size, 1) }
+        }
+    }
+}
+
+pub fn crust_test_0() {
+    {
+        let b = Buf::new(4);
+        unsafe { *b.ptr = 1; }
+    }
+    __crust::assert_no_leaks();
+}
+
+pub fn crust_test_1() {
+    {
+        let mut b = Buf::new(4);
+        b.grow(8);
+    }
+    __crust::assert_no_leaks();
+}
+
+pub fn crust_test_2() {
+    __crust::allow_alloc_failures(1);
+    {
+        let mut b = Buf::new(4);
+        b.grow(8);
+    }
+    __crust::assert_no_leaks();
+}
@@ -0,0 +1,58 @@
+// crust-verify
+// crust-verify-crust: -uninit-check
+#![feature(no_std)]
+#![feature(core)]
+#![feature(alloc)]
+#![crate_type = "lib"]
+#![no_std]
+
+extern crate core;
+extern crate alloc;
+extern crate __crust;
+
+use alloc::heap;
+use core::intrinsics;
+use core::ptr;
+
+unsafe fn at(p: *mut u32, i: usize) -> *mut u32 {
+    intrinsics::offset(p as *const u32, i as isize) as *mut u32
+}
+
+pub unsafe fn fill(p: *mut u32, n: usize) {
+    let mut i = 0;
+    while i < n {
+        ptr::write(at(p, i), i as u32);
+        i += 1;
+    }
+}
+
+pub unsafe fn sum(p: *mut u32, n: usize) -> u32 {
+    let mut s = 0;
+    let mut i = 0;
+    while i < n {
+        s += ptr::read(at(p, i));
+        i += 1;
+    }
+    s
+}
+
+pub fn crust_test_0() {
+    unsafe {
+        let p = heap::allocate(16, 4) as *mut u32;
+        fill(p, 3);
+        sum(p, 3);
+        heap::deallocate(p as *mut u8, 16, 4);
+    }
+}
+
+pub fn crust_test_1() {
+    unsafe {
+        let p = heap::allocate(16, 4) as *mut u32;
+        let q = heap::allocate(16, 4) as *mut u32;
+        fill(p, 2);
+        ptr::copy_nonoverlapping(p as *const u32, q, 2);
+        sum(q, 2);
+        heap::deallocate(p as *mut u8, 16, 4);
+        heap::deallocate(q as *mut u8, 16, 4);
+    }
+}